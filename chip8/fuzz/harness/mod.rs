@@ -0,0 +1,139 @@
+// What the fuzz targets do with their input, kept apart from libFuzzer so the
+// tests can replay the seeds and regressions on stable Rust. Inputs are plain
+// bytes laid out as described on each function, so seeds can be written by
+// hand. Anything missing from a short input counts as zeros.
+//
+// Besides never panicking, the Cpu has to turn down keys past F and come back
+// from its own save states unchanged.
+//
+// From chip8/fuzz, `cargo fuzz run run_rom corpus/run_rom seeds/run_rom`
+// starts from the hand-written seeds and keeps what it finds in corpus, which
+// isn't checked in. Crashes go in regressions/<target>, named after what went
+// wrong, once they're fixed.
+#![allow(dead_code)]
+
+use chip8::*;
+
+// Frames a run lasts, with the timers ticking once a frame
+const FRAMES: usize = 120;
+
+struct Input<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Input<'a> {
+    fn byte(&mut self) -> u8 {
+        self.take(1).first().copied().unwrap_or(0)
+    }
+
+    fn u16(&mut self) -> u16 {
+        u16::from_le_bytes([self.byte(), self.byte()])
+    }
+
+    fn take(&mut self, len: usize) -> &'a [u8] {
+        let (taken, rest) = self.data.split_at(len.min(self.data.len()));
+        self.data = rest;
+        taken
+    }
+}
+
+// One bit per quirk, in the order of the fields
+fn quirks(bits: u8) -> Quirks {
+    let on = |bit: u8| bits & (1 << bit) != 0;
+    Quirks {
+        shift_uses_vy: on(0),
+        memory_increments_i: on(1),
+        jump_uses_vx: on(2),
+        clip_sprites: on(3),
+        logic_resets_vf: on(4),
+        display_wait: on(5),
+    }
+}
+
+fn press(cpu: &mut Cpu, key: usize, pressed: bool) {
+    let result = cpu.keypress(key, pressed);
+    assert_eq!(result.is_ok(), key < 16, "pressing key {key}");
+}
+
+fn run(cpu: &mut Cpu, instructions_per_frame: u8, keys: &[u8]) {
+    for frame in 0..FRAMES {
+        for change in keys.chunks_exact(2) {
+            if change[0] as usize == frame {
+                press(cpu, (change[1] & 0x7F) as usize, change[1] & 0x80 != 0);
+            }
+        }
+        for _ in 0..instructions_per_frame {
+            cpu.tick();
+        }
+        cpu.tick_timers();
+    }
+
+    let state = cpu.save_state();
+    let before = cpu.snapshot();
+    cpu.load_state(&state).unwrap();
+    assert_eq!(cpu.snapshot(), before, "restoring a save state");
+}
+
+// A ROM and how to run it:
+//   byte 0      quirks, one bit each in the order of the Quirks fields
+//   byte 1      instructions per frame
+//   byte 2      the number of key changes, each two bytes: the frame to make
+//               the change before, then the key, with the top bit set for
+//               pressed and clear for released
+//   the rest    the ROM
+pub fn run_rom(data: &[u8]) {
+    let mut input = Input { data };
+    let quirks = quirks(input.byte());
+    let instructions_per_frame = input.byte();
+    let changes = input.byte() as usize;
+    let keys = input.take(2 * changes);
+    let rom = input.data;
+
+    let mut cpu = Cpu::setup_cpu();
+    cpu.set_seed(0);
+    cpu.set_quirks(quirks);
+    if cpu.load_rom(rom).is_err() {
+        return;
+    }
+    run(&mut cpu, instructions_per_frame, keys);
+}
+
+// A machine part way through running, to reach the edges of memory and the
+// stack straight away:
+//   bytes 0-1   PC, little endian, turned down past the end of memory
+//   bytes 2-3   I, little endian
+//   byte 4      how deep the stack is, turned down past 16
+//   byte 5      quirks, as for run_rom
+//   bytes 6-21  V0 to VF
+//   byte 22     instructions per frame
+//   bytes 23-24 the keys held, one bit each
+//   the rest    written to memory from PC, as much as fits
+pub fn run_state(data: &[u8]) {
+    let mut input = Input { data };
+    let pc = input.u16();
+    let index_register = input.u16();
+    let depth = input.byte() as usize;
+    let quirks = quirks(input.byte());
+    let registers = input.take(16).to_vec();
+    let instructions_per_frame = input.byte();
+    let keys = input.u16();
+
+    let mut cpu = Cpu::setup_cpu();
+    cpu.set_seed(0);
+    cpu.set_quirks(quirks);
+    let pc_set = cpu.set_pc(pc).is_ok();
+    assert_eq!(pc_set, pc < 0xFFF, "setting PC to {pc:#X}");
+    cpu.set_index_register(index_register);
+    let stack_set = cpu.set_stack(&vec![pc; depth]).is_ok();
+    assert_eq!(stack_set, depth <= 16, "setting a stack {depth} deep");
+    for (x, &value) in registers.iter().enumerate() {
+        cpu.set_variable_register(x, value).unwrap();
+    }
+    for key in 0..16 {
+        press(&mut cpu, key, keys & (1 << key) != 0);
+    }
+    let code = cpu.get_pc();
+    let fits = input.data.len().min(0x1000 - code as usize);
+    cpu.write_memory(code, &input.data[..fits]).unwrap();
+    run(&mut cpu, instructions_per_frame, &[]);
+}
@@ -0,0 +1,49 @@
+// Turns a single opcode into a human readable mnemonic, following the
+// same decoding as `Cpu::execute`. Unknown opcodes are shown as raw data.
+pub fn disassemble(op: u16) -> String {
+    let nibble1 = (op & 0xF000) >> 12;
+    let nibble2 = (op & 0x0F00) >> 8;
+    let nibble3 = (op & 0x00F0) >> 4;
+    let nibble4 = op & 0x000F;
+    let nnn = op & 0xFFF;
+    let nn = op & 0xFF;
+
+    match (nibble1, nibble2, nibble3, nibble4) {
+        (0, 0, 0, 0) => "NOP".to_string(),
+        (0, 0, 0xE, 0) => "CLS".to_string(),
+        (0, 0, 0xE, 0xE) => "RET".to_string(),
+        (1, _, _, _) => format!("JP {nnn:#05X}"),
+        (2, _, _, _) => format!("CALL {nnn:#05X}"),
+        (3, _, _, _) => format!("SE V{nibble2:X}, {nn:#04X}"),
+        (4, _, _, _) => format!("SNE V{nibble2:X}, {nn:#04X}"),
+        (5, _, _, _) => format!("SE V{nibble2:X}, V{nibble3:X}"),
+        (6, _, _, _) => format!("LD V{nibble2:X}, {nn:#04X}"),
+        (7, _, _, _) => format!("ADD V{nibble2:X}, {nn:#04X}"),
+        (8, _, _, 0) => format!("LD V{nibble2:X}, V{nibble3:X}"),
+        (8, _, _, 1) => format!("OR V{nibble2:X}, V{nibble3:X}"),
+        (8, _, _, 2) => format!("AND V{nibble2:X}, V{nibble3:X}"),
+        (8, _, _, 3) => format!("XOR V{nibble2:X}, V{nibble3:X}"),
+        (8, _, _, 4) => format!("ADD V{nibble2:X}, V{nibble3:X}"),
+        (8, _, _, 5) => format!("SUB V{nibble2:X}, V{nibble3:X}"),
+        (8, _, _, 6) => format!("SHR V{nibble2:X}"),
+        (8, _, _, 7) => format!("SUBN V{nibble2:X}, V{nibble3:X}"),
+        (8, _, _, 0xE) => format!("SHL V{nibble2:X}"),
+        (9, _, _, 0) => format!("SNE V{nibble2:X}, V{nibble3:X}"),
+        (0xA, _, _, _) => format!("LD I, {nnn:#05X}"),
+        (0xB, _, _, _) => format!("JP V0, {nnn:#05X}"),
+        (0xC, _, _, _) => format!("RND V{nibble2:X}, {nn:#04X}"),
+        (0xD, _, _, _) => format!("DRW V{nibble2:X}, V{nibble3:X}, {nibble4:#X}"),
+        (0xE, _, 9, 0xE) => format!("SKP V{nibble2:X}"),
+        (0xE, _, 0xA, 1) => format!("SKNP V{nibble2:X}"),
+        (0xF, _, 0, 7) => format!("LD V{nibble2:X}, DT"),
+        (0xF, _, 0, 0xA) => format!("LD V{nibble2:X}, K"),
+        (0xF, _, 1, 5) => format!("LD DT, V{nibble2:X}"),
+        (0xF, _, 1, 8) => format!("LD ST, V{nibble2:X}"),
+        (0xF, _, 1, 0xE) => format!("ADD I, V{nibble2:X}"),
+        (0xF, _, 2, 9) => format!("LD F, V{nibble2:X}"),
+        (0xF, _, 3, 3) => format!("LD B, V{nibble2:X}"),
+        (0xF, _, 5, 5) => format!("LD [I], V{nibble2:X}"),
+        (0xF, _, 6, 5) => format!("LD V{nibble2:X}, [I]"),
+        (_, _, _, _) => format!("DW {op:#06X}"),
+    }
+}
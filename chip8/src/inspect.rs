@@ -0,0 +1,189 @@
+use crate::*;
+
+use std::error::Error;
+use std::fmt;
+
+// Everything about a Cpu at one moment, for debuggers and tests to look at
+// and compare. Changing it doesn't affect the Cpu it came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CpuState {
+    pub memory: Vec<u8>,
+    pub display: Vec<bool>,
+    pub pc: u16,
+    // Return addresses, oldest first
+    pub stack: Vec<u16>,
+    pub index_register: u16,
+    pub variable_registers: [u8; V_REGS],
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub keys: [bool; NUM_KEYS],
+    pub quirks: Quirks,
+    pub waiting_for_vblank: bool,
+}
+
+// Why a change asked for from outside the Cpu, usually by a debugger, was
+// turned down
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DebugError {
+    // Writing at or past the end of memory
+    OutOfMemory { addr: usize },
+    // Running from the last byte of memory, where a two byte instruction
+    // doesn't fit
+    NoRoomForInstruction { addr: usize },
+    OffScreen { x: usize, y: usize },
+    NoSuchRegister(usize),
+    NoSuchKey(usize),
+    StackTooDeep { depth: usize, max: usize },
+}
+
+impl fmt::Display for DebugError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DebugError::OutOfMemory { addr } => write!(
+                f,
+                "address {addr:#05X} is past the end of the {MEM_SIZE} bytes of memory"
+            ),
+            DebugError::NoRoomForInstruction { addr } => write!(
+                f,
+                "an instruction at {addr:#05X} would run past the end of memory"
+            ),
+            DebugError::OffScreen { x, y } => write!(
+                f,
+                "pixel ({x}, {y}) is off the {SCREEN_WIDTH}x{SCREEN_HEIGHT} screen"
+            ),
+            DebugError::NoSuchRegister(x) => write!(f, "there is no register V{x:X}"),
+            DebugError::NoSuchKey(key) => write!(f, "there is no key {key:X}, the last is F"),
+            DebugError::StackTooDeep { depth, max } => write!(
+                f,
+                "a stack {depth} calls deep doesn't fit, the most is {max}"
+            ),
+        }
+    }
+}
+
+impl Error for DebugError {}
+
+// Looking inside the Cpu, and changing it in ways that leave it in a state it
+// can carry on running from
+impl Cpu {
+    pub fn snapshot(&self) -> CpuState {
+        CpuState {
+            memory: self.memory.to_vec(),
+            display: self.display.to_vec(),
+            pc: self.pc,
+            stack: self.get_stack().to_vec(),
+            index_register: self.index_register,
+            variable_registers: self.variable_registers,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            keys: self.keys,
+            quirks: self.quirks,
+            waiting_for_vblank: self.waiting_for_vblank,
+        }
+    }
+
+    pub fn get_memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    pub fn get_pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn get_index_register(&self) -> u16 {
+        self.index_register
+    }
+
+    pub fn get_variable_registers(&self) -> &[u8] {
+        &self.variable_registers
+    }
+
+    pub fn get_delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn get_sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn get_stack(&self) -> &[u16] {
+        &self.stack[..self.sp as usize]
+    }
+
+    pub fn get_keys(&self) -> &[bool] {
+        &self.keys
+    }
+
+    // Whether a sprite was drawn with the display wait quirk, holding the Cpu
+    // up until the next timer tick
+    pub fn is_waiting_for_vblank(&self) -> bool {
+        self.waiting_for_vblank
+    }
+
+    // The next instruction runs from here. It takes two bytes, so the last
+    // byte of memory can't be used.
+    pub fn set_pc(&mut self, addr: u16) -> Result<(), DebugError> {
+        if addr as usize + 1 >= MEM_SIZE {
+            return Err(DebugError::NoRoomForInstruction {
+                addr: addr as usize,
+            });
+        }
+        self.pc = addr;
+        Ok(())
+    }
+
+    pub fn set_index_register(&mut self, value: u16) {
+        self.index_register = value;
+    }
+
+    pub fn set_variable_register(&mut self, x: usize, value: u8) -> Result<(), DebugError> {
+        let register = self
+            .variable_registers
+            .get_mut(x)
+            .ok_or(DebugError::NoSuchRegister(x))?;
+        *register = value;
+        Ok(())
+    }
+
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.delay_timer = value;
+    }
+
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.sound_timer = value;
+    }
+
+    // Replace the call stack, oldest return address first
+    pub fn set_stack(&mut self, stack: &[u16]) -> Result<(), DebugError> {
+        if stack.len() > STACK_SIZE {
+            return Err(DebugError::StackTooDeep {
+                depth: stack.len(),
+                max: STACK_SIZE,
+            });
+        }
+        self.stack = [0; STACK_SIZE];
+        self.stack[..stack.len()].copy_from_slice(stack);
+        self.sp = stack.len() as u16;
+        Ok(())
+    }
+
+    // Write bytes anywhere in memory, the font included. Nothing is written
+    // unless all of it fits.
+    pub fn write_memory(&mut self, addr: u16, data: &[u8]) -> Result<(), DebugError> {
+        let start = addr as usize;
+        let end = start + data.len();
+        if end > MEM_SIZE {
+            return Err(DebugError::OutOfMemory { addr: end - 1 });
+        }
+        self.memory[start..end].copy_from_slice(data);
+        Ok(())
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, lit: bool) -> Result<(), DebugError> {
+        if x >= SCREEN_WIDTH || y >= SCREEN_HEIGHT {
+            return Err(DebugError::OffScreen { x, y });
+        }
+        self.display[x + SCREEN_WIDTH * y] = lit;
+        Ok(())
+    }
+}
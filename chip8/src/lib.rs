@@ -1,5 +1,20 @@
-use rand::random;
-use std::io;
+mod disasm;
+mod inspect;
+mod profile;
+mod quirks;
+mod rom;
+mod state;
+
+pub use disasm::disassemble;
+pub use inspect::{CpuState, DebugError};
+pub use profile::{Profile, Subroutine};
+pub use quirks::Quirks;
+pub use rom::*;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+
+use std::ops::Range;
 
 pub const SCREEN_WIDTH: usize = 64;
 pub const SCREEN_HEIGHT: usize = 32;
@@ -11,6 +26,11 @@ const NUM_KEYS: usize = 16;
 
 const START_ADDR: u16 = 0x200;
 
+// Bring an address that ran off the end of memory back to the start
+fn wrap(addr: u16) -> u16 {
+    addr % MEM_SIZE as u16
+}
+
 const FONTSET_SIZE: usize = 80;
 const FONTSET: [u8; FONTSET_SIZE] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
@@ -42,9 +62,23 @@ pub struct Cpu {
     delay_timer: u8,
     sound_timer: u8,
     keys: [bool; NUM_KEYS],
+    quirks: Quirks,
+    // CXNN draws from a seeded generator so runs can be replayed exactly
+    seed: u64,
+    // The generator behind StdRng, which can be moved straight to any point in
+    // its sequence when a save state is loaded
+    rng: ChaCha12Rng,
+    // Numbers drawn since seeding, so save states can bring the generator back
+    rng_draws: u64,
+    // Set by DXYN with the display wait quirk until the next timer tick
+    waiting_for_vblank: bool,
+    // Bytes in the last ROM loaded, so profiles know which memory is code
+    rom_len: usize,
+    profile: Option<Profile>,
 }
 impl Cpu {
     pub fn setup_cpu() -> Self {
+        let seed = rand::random();
         let mut cpu = Cpu {
             memory: [0; MEM_SIZE],
             display: [false; SCREEN_WIDTH * SCREEN_HEIGHT],
@@ -56,6 +90,13 @@ impl Cpu {
             delay_timer: 0,
             sound_timer: 0,
             keys: [false; NUM_KEYS],
+            quirks: Quirks::default(),
+            seed,
+            rng: ChaCha12Rng::seed_from_u64(seed),
+            rng_draws: 0,
+            waiting_for_vblank: false,
+            rom_len: 0,
+            profile: None,
         };
         cpu.memory[..FONTSET_SIZE].copy_from_slice(&FONTSET);
         cpu
@@ -72,21 +113,47 @@ impl Cpu {
         self.delay_timer = 0;
         self.sound_timer = 0;
         self.keys = [false; NUM_KEYS];
+        self.rng = ChaCha12Rng::seed_from_u64(self.seed);
+        self.rng_draws = 0;
+        self.waiting_for_vblank = false;
+        self.rom_len = 0;
         self.memory[..FONTSET_SIZE].copy_from_slice(&FONTSET);
     }
 
     pub fn tick(&mut self) {
+        if self.waiting_for_vblank {
+            return;
+        }
+        let pc = self.pc;
         let op = self.fetch();
         self.execute(op);
+        if let Some(profile) = &mut self.profile {
+            profile.record(pc, op);
+        }
     }
 
     fn fetch(&mut self) -> u16 {
-        let op: u16 = (((self.memory[self.pc as usize]) as u16) << 8)
-            | self.memory[(self.pc + 1) as usize] as u16;
-        self.pc += 2;
+        let op = u16::from_be_bytes([self.read(self.pc), self.read(self.pc.wrapping_add(1))]);
+        self.pc = wrap(self.pc.wrapping_add(2));
         op
     }
 
+    // Addresses wrap around at the end of memory, so no program can reach
+    // outside it
+    fn read(&self, addr: u16) -> u8 {
+        self.memory[addr as usize % MEM_SIZE]
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        self.memory[addr as usize % MEM_SIZE] = value;
+    }
+
+    // Whether the key in VX is held. Keys past F never are.
+    fn key_held(&self, x: u16) -> bool {
+        let key = self.variable_registers[x as usize] as usize;
+        self.keys.get(key).copied().unwrap_or(false)
+    }
+
     fn execute(&mut self, op: u16) {
         let nibble1 = (op & 0xF000) >> 12;
         let nibble2 = (op & 0x0F00) >> 8;
@@ -95,12 +162,14 @@ impl Cpu {
 
         match (nibble1, nibble2, nibble3, nibble4) {
             // NOOP
-            (0, 0, 0, 0) => return,
+            (0, 0, 0, 0) => (),
             // CLEAR SCREEN
             (0, 0, 0xE, 0) => self.clear_screen(),
-            // RETURN FROM SUBROUTINE
+            // RETURN FROM SUBROUTINE, unless there's nowhere to return to
             (0, 0, 0xE, 0xE) => {
-                self.pc = self.pop();
+                if let Some(addr) = self.pop() {
+                    self.pc = wrap(addr);
+                }
             }
             // JUMP
             (1, _, _, _) => {
@@ -116,14 +185,14 @@ impl Cpu {
             (3, _, _, _) => {
                 let nn = op & 0xFF;
                 if self.variable_registers[nibble2 as usize] == nn as u8 {
-                    self.pc += 2;
+                    self.pc = wrap(self.pc + 2);
                 }
             }
             // SKIP IF VX != 0xNN
             (4, _, _, _) => {
                 let nn = op & 0xFF;
                 if self.variable_registers[nibble2 as usize] != nn as u8 {
-                    self.pc += 2;
+                    self.pc = wrap(self.pc + 2);
                 }
             }
             // SKIP IF VX == VY
@@ -131,7 +200,7 @@ impl Cpu {
                 if self.variable_registers[nibble2 as usize]
                     == self.variable_registers[nibble3 as usize]
                 {
-                    self.pc += 2;
+                    self.pc = wrap(self.pc + 2);
                 }
             }
             // VX = 0xNN
@@ -154,16 +223,25 @@ impl Cpu {
             (8, _, _, 1) => {
                 self.variable_registers[nibble2 as usize] |=
                     self.variable_registers[nibble3 as usize];
+                if self.quirks.logic_resets_vf {
+                    self.variable_registers[0xF] = 0;
+                }
             }
             // VX &= VY
             (8, _, _, 2) => {
                 self.variable_registers[nibble2 as usize] &=
                     self.variable_registers[nibble3 as usize];
+                if self.quirks.logic_resets_vf {
+                    self.variable_registers[0xF] = 0;
+                }
             }
             // VX ^= VY
             (8, _, _, 3) => {
                 self.variable_registers[nibble2 as usize] ^=
                     self.variable_registers[nibble3 as usize];
+                if self.quirks.logic_resets_vf {
+                    self.variable_registers[0xF] = 0;
+                }
             }
             // VX += VY
             (8, _, _, 4) => {
@@ -185,6 +263,10 @@ impl Cpu {
             }
             // VX >>= 1
             (8, _, _, 6) => {
+                if self.quirks.shift_uses_vy {
+                    self.variable_registers[nibble2 as usize] =
+                        self.variable_registers[nibble3 as usize];
+                }
                 let vf = self.variable_registers[nibble2 as usize] & 1;
                 self.variable_registers[nibble2 as usize] >>= 1;
                 self.variable_registers[0xF] = vf;
@@ -200,6 +282,10 @@ impl Cpu {
             }
             // VX <<= 1
             (8, _, _, 0xE) => {
+                if self.quirks.shift_uses_vy {
+                    self.variable_registers[nibble2 as usize] =
+                        self.variable_registers[nibble3 as usize];
+                }
                 let vf = (self.variable_registers[nibble2 as usize] >> 7) & 1;
                 self.variable_registers[nibble2 as usize] <<= 1;
                 self.variable_registers[0xF] = vf;
@@ -209,7 +295,7 @@ impl Cpu {
                 if self.variable_registers[nibble2 as usize]
                     != self.variable_registers[nibble3 as usize]
                 {
-                    self.pc += 2;
+                    self.pc = wrap(self.pc + 2);
                 }
             }
             // I = 0xNNN
@@ -219,18 +305,17 @@ impl Cpu {
             // JMP TO V0 + NNN
             (0xB, _, _, _) => {
                 let nnn = op & 0xFFF;
-                self.pc = (self.variable_registers[0] as u16) + nnn;
+                let x = if self.quirks.jump_uses_vx { nibble2 } else { 0 };
+                self.pc = wrap(self.variable_registers[x as usize] as u16 + nnn);
             }
             // VX = RAND & NN
             (0xC, _, _, _) => {
-                let rand: u8 = random();
+                let rand = self.random_byte();
                 let nn = (op & 0xFF) as u8;
                 self.variable_registers[nibble2 as usize] = rand & nn;
             }
             // DISPLAY SPRITE
             (0xD, _, _, _) => {
-                // self.display_sprite(nibble2, nibble3, nibble4);
-
                 // Get the (x, y) coords for our sprite
                 let x_coord = self.variable_registers[nibble2 as usize] as u16;
                 let y_coord = self.variable_registers[nibble3 as usize] as u16;
@@ -241,15 +326,20 @@ impl Cpu {
                 // Iterate over each row of our sprite
                 for y_line in 0..num_rows {
                     // Determine which memory address our row's data is stored
-                    let addr = self.index_register + y_line;
-                    let pixels = self.memory[addr as usize];
+                    let pixels = self.read(self.index_register.wrapping_add(y_line));
                     // Iterate over each column in our row
                     for x_line in 0..8 {
                         // Use a mask to fetch current pixel's bit. Only flip if a 1
                         if (pixels & (0b1000_0000 >> x_line)) != 0 {
-                            // Sprites should wrap around screen, so apply modulo
-                            let x = (x_coord + x_line) as usize % SCREEN_WIDTH;
-                            let y = (y_coord + y_line) as usize % SCREEN_HEIGHT;
+                            let x = x_coord as usize % SCREEN_WIDTH + x_line as usize;
+                            let y = y_coord as usize % SCREEN_HEIGHT + y_line as usize;
+                            // The starting position always wraps, the rest of
+                            // the sprite either wraps too or is cut off
+                            if self.quirks.clip_sprites && (x >= SCREEN_WIDTH || y >= SCREEN_HEIGHT)
+                            {
+                                continue;
+                            }
+                            let (x, y) = (x % SCREEN_WIDTH, y % SCREEN_HEIGHT);
                             // Get our pixel's index for our 1D screen array
                             let idx = x + SCREEN_WIDTH * y;
                             // Check if we're about to flip the pixel and set
@@ -264,21 +354,18 @@ impl Cpu {
                 } else {
                     self.variable_registers[0xF] = 0;
                 }
+                self.waiting_for_vblank = self.quirks.display_wait;
             }
             // SKIP IF KEY IS PRESSED
             (0xE, _, 9, 0xE) => {
-                let vx = self.variable_registers[nibble2 as usize];
-                let key_pressed = self.keys[vx as usize];
-                if key_pressed {
-                    self.pc += 2;
+                if self.key_held(nibble2) {
+                    self.pc = wrap(self.pc + 2);
                 }
             }
             // SKIP IF KEY IS NOT PRESSED
             (0xE, _, 0xA, 1) => {
-                let vx = self.variable_registers[nibble2 as usize];
-                let key_pressed = self.keys[vx as usize];
-                if !key_pressed {
-                    self.pc += 2;
+                if !self.key_held(nibble2) {
+                    self.pc = wrap(self.pc + 2);
                 }
             }
             // VX = DT
@@ -294,7 +381,7 @@ impl Cpu {
                 }
 
                 if !pressed {
-                    self.pc -= 2;
+                    self.pc = wrap(self.pc.wrapping_sub(2));
                 }
             }
             // (0xF, _, 0, 0xA) => {
@@ -355,52 +442,39 @@ impl Cpu {
                 let tens = ((vx / 10.0) % 10.0).floor() as u8;
                 let ones = (vx % 10.0) as u8;
 
-                self.memory[self.index_register as usize] = hundreds;
-                self.memory[(self.index_register + 1) as usize] = tens;
-                self.memory[(self.index_register + 2) as usize] = ones;
+                let i = self.index_register;
+                self.write(i, hundreds);
+                self.write(i.wrapping_add(1), tens);
+                self.write(i.wrapping_add(2), ones);
             }
             // STORE V0 TO VX INTO I
             (0xF, _, 5, 5) => {
                 let i = self.index_register;
                 for idx in 0..=nibble2 {
-                    self.memory[(i + idx) as usize] = self.variable_registers[idx as usize];
+                    self.write(i.wrapping_add(idx), self.variable_registers[idx as usize]);
+                }
+                if self.quirks.memory_increments_i {
+                    self.index_register = i.wrapping_add(nibble2 + 1);
                 }
             }
             // LOAD V0 TO VX INTO I
             (0xF, _, 6, 5) => {
                 let i = self.index_register;
                 for idx in 0..=nibble2 {
-                    self.variable_registers[idx as usize] = self.memory[(i + idx) as usize];
+                    self.variable_registers[idx as usize] = self.read(i.wrapping_add(idx));
                 }
-            }
-
-            (_, _, _, _) => unimplemented!("Opcode not implemented: {op}"),
-        }
-    }
-
-    fn display_sprite(&mut self, nibble2: u16, nibble3: u16, nibble4: u16) {
-        let x = self.variable_registers[nibble2 as usize] as usize % SCREEN_WIDTH;
-        let y = self.variable_registers[nibble3 as usize] as usize % SCREEN_HEIGHT;
-
-        let mut flipped = false;
-        let mask: u8 = 0b10000000;
-        self.variable_registers[0xF] = 0;
-
-        for row in 0..nibble4 {
-            let sprite_row = self.index_register + row;
-            let pixel = self.memory[sprite_row as usize];
-            for column in 0..8 {
-                if (pixel & (mask >> column)) != 0 {
-                    let idx = x + SCREEN_WIDTH * y;
-                    flipped |= self.display[idx];
-                    self.display[idx] ^= true;
+                if self.quirks.memory_increments_i {
+                    self.index_register = i.wrapping_add(nibble2 + 1);
                 }
             }
-            self.variable_registers[0xF] = if flipped { 1 } else { 0 };
+
+            // Anything else isn't an instruction, and is skipped like a NOOP
+            (_, _, _, _) => (),
         }
     }
 
     pub fn tick_timers(&mut self) {
+        self.waiting_for_vblank = false;
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
         }
@@ -417,29 +491,85 @@ impl Cpu {
         self.display = [false; SCREEN_WIDTH * SCREEN_HEIGHT];
     }
 
-    pub fn load_rom(&mut self, data: &[u8]) -> Result<(), io::Error> {
+    pub fn load_rom(&mut self, data: &[u8]) -> Result<(), RomError> {
+        validate_rom(data)?;
         let start = START_ADDR as usize;
         let end = start + data.len();
 
         self.memory[start..end].copy_from_slice(data);
+        self.rom_len = data.len();
+        let code = self.code();
+        if let Some(profile) = &mut self.profile {
+            profile.set_code(code);
+        }
         Ok(())
     }
 
+    fn code(&self) -> Range<u16> {
+        START_ADDR..START_ADDR + self.rom_len as u16
+    }
+
+    // Count everything run from here on, starting over if already profiling
+    pub fn start_profiling(&mut self) {
+        self.profile = Some(Profile::new(self.code()));
+    }
+
+    // Stop profiling, handing back the counts
+    pub fn stop_profiling(&mut self) -> Option<Profile> {
+        self.profile.take()
+    }
+
+    pub fn get_profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
     pub fn get_display(&self) -> &[bool] {
         &self.display
     }
 
-    pub fn keypress(&mut self, idx: usize, pressed: bool) {
-        // TODO: Return Error if idx >= 16
-        self.keys[idx] = pressed;
+    pub fn get_quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn get_seed(&self) -> u64 {
+        self.seed
+    }
+
+    // Restart the random number sequence from the given seed. reset() goes
+    // back to the start of the same sequence.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = ChaCha12Rng::seed_from_u64(seed);
+        self.rng_draws = 0;
     }
 
+    fn random_byte(&mut self) -> u8 {
+        self.rng_draws = self.rng_draws.wrapping_add(1);
+        self.rng.gen()
+    }
+
+    pub fn keypress(&mut self, idx: usize, pressed: bool) -> Result<(), DebugError> {
+        let key = self.keys.get_mut(idx).ok_or(DebugError::NoSuchKey(idx))?;
+        *key = pressed;
+        Ok(())
+    }
+
+    // Calls past the depth of the stack forget the oldest return address, so
+    // the innermost calls can still return
     fn push(&mut self, val: u16) {
+        if self.sp as usize == STACK_SIZE {
+            self.stack.rotate_left(1);
+            self.sp -= 1;
+        }
         self.stack[self.sp as usize] = val;
         self.sp += 1;
     }
-    fn pop(&mut self) -> u16 {
-        self.sp -= 1;
-        self.stack[self.sp as usize]
+    fn pop(&mut self) -> Option<u16> {
+        self.sp = self.sp.checked_sub(1)?;
+        Some(self.stack[self.sp as usize])
     }
 }
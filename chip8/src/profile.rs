@@ -0,0 +1,330 @@
+use crate::*;
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::ops::Range;
+
+// How many of the busiest addresses the text report lists
+const HOTTEST: usize = 20;
+
+// Counts of what a Cpu ran while profiling: how often each address and each
+// kind of instruction ran, and how many instructions ran inside each
+// subroutine. Subroutines are followed through 2NNN and 00EE, so time is
+// measured in instructions rather than seconds.
+pub struct Profile {
+    // Where the ROM was loaded, to find the parts of it that never ran
+    code: Range<u16>,
+    instructions: u64,
+    counts: Vec<u64>,
+    // The last opcode run from each address, which self-modifying code can
+    // change along the way
+    opcodes: Vec<u16>,
+    classes: BTreeMap<&'static str, u64>,
+    calls: BTreeMap<u16, u64>,
+    // The subroutines being run, outermost first, like the Cpu's stack but
+    // holding where each call went rather than where it returns to
+    stack: Vec<u16>,
+    // Instructions run with each stack
+    stacks: BTreeMap<Vec<u16>, u64>,
+}
+
+// Instructions run inside a subroutine. `instructions` includes those of the
+// subroutines it called, `own` doesn't.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Subroutine {
+    pub address: u16,
+    pub calls: u64,
+    pub instructions: u64,
+    pub own: u64,
+}
+
+impl Profile {
+    pub(crate) fn new(code: Range<u16>) -> Self {
+        Profile {
+            code,
+            instructions: 0,
+            counts: vec![0; MEM_SIZE],
+            opcodes: vec![0; MEM_SIZE],
+            classes: BTreeMap::new(),
+            calls: BTreeMap::new(),
+            stack: Vec::new(),
+            stacks: BTreeMap::new(),
+        }
+    }
+
+    pub(crate) fn set_code(&mut self, code: Range<u16>) {
+        self.code = code;
+    }
+
+    // Count an instruction the Cpu has just run from an address. Calls and
+    // returns count towards the subroutine they were run from.
+    pub(crate) fn record(&mut self, addr: u16, op: u16) {
+        let addr = addr as usize % MEM_SIZE;
+        self.instructions += 1;
+        self.counts[addr] += 1;
+        self.opcodes[addr] = op;
+        *self.classes.entry(class(op)).or_default() += 1;
+        match self.stacks.get_mut(self.stack.as_slice()) {
+            Some(count) => *count += 1,
+            None => {
+                self.stacks.insert(self.stack.clone(), 1);
+            }
+        }
+
+        match op {
+            0x00EE => {
+                self.stack.pop();
+            }
+            _ if op >> 12 == 0x2 => {
+                let target = op & 0xFFF;
+                *self.calls.entry(target).or_default() += 1;
+                // The Cpu forgets the oldest call when its stack is full
+                if self.stack.len() == STACK_SIZE {
+                    self.stack.remove(0);
+                }
+                self.stack.push(target);
+            }
+            _ => (),
+        }
+    }
+
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    // How many times the instruction at an address ran
+    pub fn count(&self, addr: u16) -> u64 {
+        self.counts[addr as usize % MEM_SIZE]
+    }
+
+    // Every address that ran, busiest first
+    pub fn hotspots(&self) -> Vec<(u16, u64)> {
+        let mut hotspots: Vec<_> = (0..MEM_SIZE as u16)
+            .map(|addr| (addr, self.count(addr)))
+            .filter(|&(_, count)| count > 0)
+            .collect();
+        hotspots.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        hotspots
+    }
+
+    // How many instructions ran of each kind, named by their opcode pattern
+    // such as 8XY4
+    pub fn classes(&self) -> &BTreeMap<&'static str, u64> {
+        &self.classes
+    }
+
+    // Every subroutine called, busiest first
+    pub fn subroutines(&self) -> Vec<Subroutine> {
+        let mut subroutines: BTreeMap<u16, Subroutine> = self
+            .calls
+            .iter()
+            .map(|(&address, &calls)| {
+                let subroutine = Subroutine {
+                    address,
+                    calls,
+                    instructions: 0,
+                    own: 0,
+                };
+                (address, subroutine)
+            })
+            .collect();
+        for (stack, &count) in &self.stacks {
+            for (depth, addr) in stack.iter().enumerate() {
+                // Recursive calls only count once
+                if stack[..depth].contains(addr) {
+                    continue;
+                }
+                if let Some(subroutine) = subroutines.get_mut(addr) {
+                    subroutine.instructions += count;
+                }
+            }
+            if let Some(subroutine) = stack.last().and_then(|addr| subroutines.get_mut(addr)) {
+                subroutine.own += count;
+            }
+        }
+        let mut subroutines: Vec<_> = subroutines.into_values().collect();
+        subroutines.sort_by(|a, b| {
+            b.instructions
+                .cmp(&a.instructions)
+                .then(a.address.cmp(&b.address))
+        });
+        subroutines
+    }
+
+    // The parts of the ROM that no instruction ran from. Sprites and other
+    // data turn up here too.
+    pub fn unexecuted(&self) -> Vec<Range<u16>> {
+        let mut ran = vec![false; MEM_SIZE];
+        for addr in (0..MEM_SIZE).filter(|&addr| self.counts[addr] > 0) {
+            ran[addr] = true;
+            ran[(addr + 1) % MEM_SIZE] = true;
+        }
+        let mut ranges: Vec<Range<u16>> = Vec::new();
+        for addr in self.code.clone().filter(|&addr| !ran[addr as usize]) {
+            match ranges.last_mut() {
+                Some(range) if range.end == addr => range.end += 1,
+                _ => ranges.push(addr..addr + 1),
+            }
+        }
+        ranges
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        let total = self.instructions;
+        let percent = |count: u64| 100.0 * count as f64 / total.max(1) as f64;
+
+        writeln!(text, "{total} instructions").unwrap();
+
+        writeln!(text, "\nHottest addresses").unwrap();
+        for (addr, count) in self.hotspots().into_iter().take(HOTTEST) {
+            let op = self.opcodes[addr as usize];
+            writeln!(
+                text,
+                "  {addr:#05X}  {count:>10}  {:5.1}%  {op:04X}  {}",
+                percent(count),
+                disassemble(op)
+            )
+            .unwrap();
+        }
+
+        writeln!(text, "\nOpcodes").unwrap();
+        let mut classes: Vec<_> = self.classes.iter().collect();
+        classes.sort_by(|a, b| b.1.cmp(a.1));
+        for (class, &count) in classes {
+            writeln!(text, "  {class:<7}  {count:>10}  {:5.1}%", percent(count)).unwrap();
+        }
+
+        writeln!(text, "\nSubroutines      calls  instructions        own").unwrap();
+        for subroutine in self.subroutines() {
+            writeln!(
+                text,
+                "  {:#05X}  {:>12}  {:>10} {:5.1}%  {:>10}",
+                subroutine.address,
+                subroutine.calls,
+                subroutine.instructions,
+                percent(subroutine.instructions),
+                subroutine.own
+            )
+            .unwrap();
+        }
+
+        writeln!(text, "\nNever executed").unwrap();
+        for range in self.unexecuted() {
+            let len = range.end - range.start;
+            writeln!(
+                text,
+                "  {:#05X}-{:#05X}  {len} bytes",
+                range.start,
+                range.end - 1
+            )
+            .unwrap();
+        }
+        text
+    }
+
+    pub fn to_json(&self) -> String {
+        let list = |items: Vec<String>| match items.is_empty() {
+            true => "[]".to_string(),
+            false => format!("[\n    {}\n  ]", items.join(",\n    ")),
+        };
+
+        let addresses = self
+            .hotspots()
+            .into_iter()
+            .map(|(addr, count)| {
+                let op = self.opcodes[addr as usize];
+                format!(
+                    "{{\"address\": {addr}, \"count\": {count}, \"opcode\": \"{op:04X}\", \
+                     \"instruction\": \"{}\"}}",
+                    disassemble(op)
+                )
+            })
+            .collect();
+        let classes = self
+            .classes
+            .iter()
+            .map(|(class, count)| format!("{{\"class\": \"{class}\", \"count\": {count}}}"))
+            .collect();
+        let subroutines = self
+            .subroutines()
+            .into_iter()
+            .map(|s| {
+                format!(
+                    "{{\"address\": {}, \"calls\": {}, \"instructions\": {}, \"own\": {}}}",
+                    s.address, s.calls, s.instructions, s.own
+                )
+            })
+            .collect();
+        let unexecuted = self
+            .unexecuted()
+            .into_iter()
+            .map(|range| format!("{{\"start\": {}, \"end\": {}}}", range.start, range.end))
+            .collect();
+
+        format!(
+            "{{\n  \"instructions\": {},\n  \"addresses\": {},\n  \"classes\": {},\n  \
+             \"subroutines\": {},\n  \"unexecuted\": {}\n}}\n",
+            self.instructions,
+            list(addresses),
+            list(classes),
+            list(subroutines),
+            list(unexecuted)
+        )
+    }
+
+    // One line per call stack with the instructions run in it, the format
+    // flamegraph.pl and inferno read. Code outside any subroutine is main.
+    pub fn to_folded(&self) -> String {
+        let mut folded = String::new();
+        for (stack, count) in &self.stacks {
+            let frames: Vec<_> = stack.iter().map(|addr| format!("{addr:#05X}")).collect();
+            let frames = std::iter::once("main".to_string()).chain(frames);
+            writeln!(folded, "{} {count}", frames.collect::<Vec<_>>().join(";")).unwrap();
+        }
+        folded
+    }
+}
+
+// The pattern of the opcode, decoded the same way as `Cpu::execute`
+fn class(op: u16) -> &'static str {
+    let nibbles = (op >> 12, op >> 8 & 0xF, op >> 4 & 0xF, op & 0xF);
+    match nibbles {
+        (0, 0, 0, 0) => "0000",
+        (0, 0, 0xE, 0) => "00E0",
+        (0, 0, 0xE, 0xE) => "00EE",
+        (1, _, _, _) => "1NNN",
+        (2, _, _, _) => "2NNN",
+        (3, _, _, _) => "3XNN",
+        (4, _, _, _) => "4XNN",
+        (5, _, _, _) => "5XY0",
+        (6, _, _, _) => "6XNN",
+        (7, _, _, _) => "7XNN",
+        (8, _, _, 0) => "8XY0",
+        (8, _, _, 1) => "8XY1",
+        (8, _, _, 2) => "8XY2",
+        (8, _, _, 3) => "8XY3",
+        (8, _, _, 4) => "8XY4",
+        (8, _, _, 5) => "8XY5",
+        (8, _, _, 6) => "8XY6",
+        (8, _, _, 7) => "8XY7",
+        (8, _, _, 0xE) => "8XYE",
+        (9, _, _, 0) => "9XY0",
+        (0xA, _, _, _) => "ANNN",
+        (0xB, _, _, _) => "BNNN",
+        (0xC, _, _, _) => "CXNN",
+        (0xD, _, _, _) => "DXYN",
+        (0xE, _, 9, 0xE) => "EX9E",
+        (0xE, _, 0xA, 1) => "EXA1",
+        (0xF, _, 0, 7) => "FX07",
+        (0xF, _, 0, 0xA) => "FX0A",
+        (0xF, _, 1, 5) => "FX15",
+        (0xF, _, 1, 8) => "FX18",
+        (0xF, _, 1, 0xE) => "FX1E",
+        (0xF, _, 2, 9) => "FX29",
+        (0xF, _, 3, 3) => "FX33",
+        (0xF, _, 5, 5) => "FX55",
+        (0xF, _, 6, 5) => "FX65",
+        (_, _, _, _) => "unknown",
+    }
+}
@@ -0,0 +1,158 @@
+use std::fmt;
+use std::str::FromStr;
+
+// Behaviours that differ between Chip-8 interpreters. Games are written for
+// one of them and can break on the others. The default is how this emulator
+// has always behaved, which matches most modern games.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Quirks {
+    // 8XY6 and 8XYE shift VY into VX instead of shifting VX in place
+    pub shift_uses_vy: bool,
+    // FX55 and FX65 leave I pointing past the last register
+    pub memory_increments_i: bool,
+    // BNNN jumps to VX + NNN, X being the top digit of NNN, instead of V0 + NNN
+    pub jump_uses_vx: bool,
+    // Sprites are cut off at the screen edge instead of wrapping around
+    pub clip_sprites: bool,
+    // 8XY1, 8XY2 and 8XY3 set VF to 0
+    pub logic_resets_vf: bool,
+    // DXYN waits for the next frame, so at most one sprite is drawn per frame
+    pub display_wait: bool,
+}
+
+// Names used when listing quirks, in the order of the fields
+const NAMES: [&str; 6] = [
+    "shift",
+    "memory",
+    "jump",
+    "clip",
+    "vf_reset",
+    "display_wait",
+];
+
+impl Quirks {
+    // No quirks at all, the default
+    pub const MODERN: Quirks = Quirks {
+        shift_uses_vy: false,
+        memory_increments_i: false,
+        jump_uses_vx: false,
+        clip_sprites: false,
+        logic_resets_vf: false,
+        display_wait: false,
+    };
+
+    // The original COSMAC VIP interpreter
+    pub const VIP: Quirks = Quirks {
+        shift_uses_vy: true,
+        memory_increments_i: true,
+        jump_uses_vx: false,
+        clip_sprites: true,
+        logic_resets_vf: true,
+        display_wait: true,
+    };
+
+    // SUPER-CHIP on the HP 48
+    pub const SCHIP: Quirks = Quirks {
+        shift_uses_vy: false,
+        memory_increments_i: false,
+        jump_uses_vx: true,
+        clip_sprites: true,
+        logic_resets_vf: false,
+        display_wait: false,
+    };
+
+    // Octo's XO-CHIP
+    pub const XOCHIP: Quirks = Quirks {
+        shift_uses_vy: true,
+        memory_increments_i: true,
+        jump_uses_vx: false,
+        clip_sprites: false,
+        logic_resets_vf: false,
+        display_wait: false,
+    };
+
+    pub const PRESETS: [(&'static str, Quirks); 4] = [
+        ("modern", Quirks::MODERN),
+        ("vip", Quirks::VIP),
+        ("schip", Quirks::SCHIP),
+        ("xochip", Quirks::XOCHIP),
+    ];
+
+    fn flags(&self) -> [bool; 6] {
+        [
+            self.shift_uses_vy,
+            self.memory_increments_i,
+            self.jump_uses_vx,
+            self.clip_sprites,
+            self.logic_resets_vf,
+            self.display_wait,
+        ]
+    }
+
+    // One bit per quirk, in the order of the fields, for save states
+    pub(crate) fn to_bits(self) -> u8 {
+        self.flags()
+            .iter()
+            .enumerate()
+            .fold(0, |bits, (idx, &on)| bits | (on as u8) << idx)
+    }
+
+    pub(crate) fn from_bits(bits: u8) -> Quirks {
+        let mut quirks = Quirks::default();
+        for idx in 0..NAMES.len() {
+            *quirks.flag_mut(idx) = bits & (1 << idx) != 0;
+        }
+        quirks
+    }
+
+    fn flag_mut(&mut self, idx: usize) -> &mut bool {
+        match idx {
+            0 => &mut self.shift_uses_vy,
+            1 => &mut self.memory_increments_i,
+            2 => &mut self.jump_uses_vx,
+            3 => &mut self.clip_sprites,
+            4 => &mut self.logic_resets_vf,
+            _ => &mut self.display_wait,
+        }
+    }
+}
+
+// The preset name if there is one, otherwise the enabled quirks separated by commas
+impl fmt::Display for Quirks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some((name, _)) = Quirks::PRESETS.iter().find(|(_, q)| q == self) {
+            return f.write_str(name);
+        }
+        let enabled: Vec<_> = NAMES
+            .iter()
+            .zip(self.flags())
+            .filter(|&(_, on)| on)
+            .map(|(name, _)| *name)
+            .collect();
+        // No quirks at all is the modern preset, so there is always one here
+        f.write_str(&enabled.join(","))
+    }
+}
+
+// A preset name or a comma separated list of quirks to enable
+impl FromStr for Quirks {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((_, quirks)) = Quirks::PRESETS.iter().find(|(name, _)| *name == s) {
+            return Ok(*quirks);
+        }
+        let mut quirks = Quirks::default();
+        for name in s.split(',').map(str::trim) {
+            let idx = NAMES.iter().position(|&n| n == name).ok_or_else(|| {
+                format!(
+                    "unknown quirk '{name}', expected modern, vip, schip, xochip \
+                     or a list of {}",
+                    NAMES.join(", ")
+                )
+            })?;
+            *quirks.flag_mut(idx) = true;
+        }
+        Ok(quirks)
+    }
+}
@@ -0,0 +1,85 @@
+use crate::*;
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+// The most a ROM can hold: everything from where programs start to the end
+// of memory
+pub const MAX_ROM_SIZE: usize = MEM_SIZE - START_ADDR as usize;
+
+#[derive(Debug)]
+pub enum RomError {
+    Empty,
+    TooLarge { size: usize, max: usize },
+    // A file that should hold a ROM, but couldn't be made sense of
+    Invalid(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RomError::Empty => f.write_str("the ROM is empty"),
+            RomError::TooLarge { size, max } => write!(
+                f,
+                "the ROM is {size} bytes, but at most {max} bytes fit in memory"
+            ),
+            RomError::Invalid(message) => f.write_str(message),
+            RomError::Io(err) => write!(f, "unable to read the ROM: {err}"),
+        }
+    }
+}
+
+impl Error for RomError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RomError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RomError {
+    fn from(err: io::Error) -> Self {
+        RomError::Io(err)
+    }
+}
+
+// Check that a ROM can be loaded
+pub fn validate_rom(data: &[u8]) -> Result<(), RomError> {
+    if data.is_empty() {
+        return Err(RomError::Empty);
+    }
+    if data.len() > MAX_ROM_SIZE {
+        return Err(RomError::TooLarge {
+            size: data.len(),
+            max: MAX_ROM_SIZE,
+        });
+    }
+    Ok(())
+}
+
+// Read a whole ROM, without keeping more than fits in memory
+pub fn read_rom(mut reader: impl Read) -> Result<Vec<u8>, RomError> {
+    let mut data = Vec::new();
+    (&mut reader)
+        .take(MAX_ROM_SIZE as u64 + 1)
+        .read_to_end(&mut data)?;
+    if data.len() > MAX_ROM_SIZE {
+        // Count the rest for the error message
+        let rest = io::copy(&mut reader, &mut io::sink())?;
+        return Err(RomError::TooLarge {
+            size: data.len() + rest as usize,
+            max: MAX_ROM_SIZE,
+        });
+    }
+    validate_rom(&data)?;
+    Ok(data)
+}
+
+pub fn read_rom_file(path: &Path) -> Result<Vec<u8>, RomError> {
+    read_rom(File::open(path)?)
+}
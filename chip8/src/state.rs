@@ -0,0 +1,108 @@
+use crate::*;
+
+use std::io;
+
+const MAGIC: &[u8; 4] = b"C8ST";
+const VERSION: u8 = 1;
+
+// Save states are a flat little endian dump of everything a running Cpu
+// needs, except the keys, which belong to whoever is playing
+impl Cpu {
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(MEM_SIZE + self.display.len() + 128);
+        data.extend_from_slice(MAGIC);
+        data.push(VERSION);
+        data.extend_from_slice(&self.memory);
+        data.extend(self.display.iter().map(|&lit| lit as u8));
+        data.extend_from_slice(&self.pc.to_le_bytes());
+        for addr in self.stack {
+            data.extend_from_slice(&addr.to_le_bytes());
+        }
+        data.extend_from_slice(&self.sp.to_le_bytes());
+        data.extend_from_slice(&self.index_register.to_le_bytes());
+        data.extend_from_slice(&self.variable_registers);
+        data.push(self.delay_timer);
+        data.push(self.sound_timer);
+        data.push(self.quirks.to_bits());
+        data.extend_from_slice(&self.seed.to_le_bytes());
+        data.extend_from_slice(&self.rng_draws.to_le_bytes());
+        data.push(self.waiting_for_vblank as u8);
+        data
+    }
+
+    // Restore a state from save_state. The Cpu is left alone if it is invalid.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), io::Error> {
+        let mut reader = Reader { data };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(invalid("not a save state"));
+        }
+        if reader.u8()? != VERSION {
+            return Err(invalid("unsupported save state version"));
+        }
+
+        let mut cpu = Cpu::setup_cpu();
+        cpu.memory.copy_from_slice(reader.take(MEM_SIZE)?);
+        for (pixel, &lit) in cpu
+            .display
+            .iter_mut()
+            .zip(reader.take(SCREEN_WIDTH * SCREEN_HEIGHT)?)
+        {
+            *pixel = lit != 0;
+        }
+        cpu.pc = reader.u16()?;
+        for addr in cpu.stack.iter_mut() {
+            *addr = reader.u16()?;
+        }
+        cpu.sp = reader.u16()?;
+        cpu.index_register = reader.u16()?;
+        cpu.variable_registers.copy_from_slice(reader.take(V_REGS)?);
+        cpu.delay_timer = reader.u8()?;
+        cpu.sound_timer = reader.u8()?;
+        cpu.quirks = Quirks::from_bits(reader.u8()?);
+        let seed = u64::from_le_bytes(reader.take(8)?.try_into().unwrap());
+        let draws = u64::from_le_bytes(reader.take(8)?.try_into().unwrap());
+        cpu.waiting_for_vblank = reader.u8()? != 0;
+
+        if cpu.sp as usize > STACK_SIZE || cpu.pc as usize >= MEM_SIZE {
+            return Err(invalid("corrupt save state"));
+        }
+        if !reader.data.is_empty() {
+            return Err(invalid("unexpected data after save state"));
+        }
+
+        // Every byte drawn uses up one word of the generator's output
+        cpu.set_seed(seed);
+        cpu.rng.set_word_pos(draws as u128);
+        cpu.rng_draws = draws;
+        cpu.keys = self.keys;
+        *self = cpu;
+        Ok(())
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], io::Error> {
+        if self.data.len() < len {
+            return Err(invalid("save state is cut short"));
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, io::Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, io::Error> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+}
@@ -0,0 +1,55 @@
+// Helpers shared by the integration tests. Each test file only uses some of
+// them, and every file is built on its own.
+#![allow(dead_code)]
+
+use chip8::*;
+
+// Where programs are loaded
+pub const START: u16 = 0x200;
+
+// Turn opcodes into ROM bytes, so programs can be written as a listing
+pub fn rom(program: &[u16]) -> Vec<u8> {
+    program.iter().flat_map(|op| op.to_be_bytes()).collect()
+}
+
+pub fn cpu_with(program: &[u16]) -> Cpu {
+    cpu_with_quirks(program, Quirks::MODERN)
+}
+
+pub fn cpu_with_quirks(program: &[u16], quirks: Quirks) -> Cpu {
+    let mut cpu = Cpu::setup_cpu();
+    cpu.set_quirks(quirks);
+    cpu.load_rom(&rom(program)).unwrap();
+    cpu
+}
+
+pub fn run(cpu: &mut Cpu, instructions: usize) {
+    for _ in 0..instructions {
+        cpu.tick();
+    }
+}
+
+// Run each instruction of a straight line program once, and return the result
+pub fn run_program(program: &[u16]) -> CpuState {
+    run_program_with_quirks(program, Quirks::MODERN)
+}
+
+pub fn run_program_with_quirks(program: &[u16], quirks: Quirks) -> CpuState {
+    let mut cpu = cpu_with_quirks(program, quirks);
+    run(&mut cpu, program.len());
+    cpu.snapshot()
+}
+
+// The lit pixels of a state's display as rows of # and . for easy comparison
+pub fn pixels(state: &CpuState, width: usize, height: usize) -> Vec<String> {
+    (0..height)
+        .map(|y| {
+            (0..width)
+                .map(|x| match state.display[x + SCREEN_WIDTH * y] {
+                    true => '#',
+                    false => '.',
+                })
+                .collect()
+        })
+        .collect()
+}
@@ -0,0 +1,357 @@
+// Runs random programs from random machine states on both the Cpu and the
+// reference model, one instruction at a time, and fails at the first
+// instruction where they disagree. proptest then shrinks the case down to the
+// smallest program and state that still disagree, and prints it as a listing
+// along with what went wrong. Failing cases are saved in
+// differential.proptest-regressions and tried first on every later run.
+//
+// Set PROPTEST_CASES to try more than the default 256 cases.
+
+mod common;
+mod reference;
+
+use chip8::*;
+use common::*;
+use proptest::prelude::*;
+use reference::{decode, Model};
+
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+// Where the random data sprites and loads are likely to point at
+const DATA: u16 = 0x300;
+const MAX_PROGRAM: usize = 32;
+
+// A machine state and program to run from it
+#[derive(Clone)]
+struct Case {
+    quirks: Quirks,
+    registers: [u8; 16],
+    index_register: u16,
+    stack: Vec<u16>,
+    delay_timer: u8,
+    sound_timer: u8,
+    keys: [bool; 16],
+    lit: Vec<(u8, u8)>,
+    seed: u64,
+    data: Vec<u8>,
+    program: Vec<u16>,
+    // The timers tick after every so many instructions
+    timer_period: usize,
+    steps: usize,
+}
+
+impl Case {
+    fn cpu(&self) -> Cpu {
+        let mut cpu = cpu_with_quirks(&self.program, self.quirks);
+        cpu.set_seed(self.seed);
+        for (x, &value) in self.registers.iter().enumerate() {
+            cpu.set_variable_register(x, value).unwrap();
+        }
+        cpu.set_index_register(self.index_register);
+        cpu.set_stack(&self.stack).unwrap();
+        cpu.set_delay_timer(self.delay_timer);
+        cpu.set_sound_timer(self.sound_timer);
+        for (key, &held) in self.keys.iter().enumerate() {
+            cpu.keypress(key, held).unwrap();
+        }
+        for &(x, y) in &self.lit {
+            cpu.set_pixel(x as usize, y as usize, true).unwrap();
+        }
+        cpu.write_memory(DATA, &self.data).unwrap();
+        cpu
+    }
+}
+
+// Printed as a listing so a shrunk case can be read and replayed by hand
+impl fmt::Debug for Case {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "quirks: {}", self.quirks)?;
+        writeln!(f, "V0-VF: {:02X?}", self.registers)?;
+        writeln!(
+            f,
+            "I: {:#05X}, stack: {:03X?}",
+            self.index_register, self.stack
+        )?;
+        writeln!(f, "DT: {}, ST: {}", self.delay_timer, self.sound_timer)?;
+        let held: Vec<_> = (0..16).filter(|&key| self.keys[key]).collect();
+        writeln!(f, "keys held: {held:X?}, lit pixels: {:?}", self.lit)?;
+        writeln!(
+            f,
+            "seed: {}, data at {DATA:#05X}: {:02X?}",
+            self.seed, self.data
+        )?;
+        writeln!(
+            f,
+            "{} instructions, timers every {}",
+            self.steps, self.timer_period
+        )?;
+        for (n, &op) in self.program.iter().enumerate() {
+            writeln!(
+                f,
+                "{:#05X}: {op:04X}  {}",
+                START + 2 * n as u16,
+                disassemble(op)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn register() -> impl Strategy<Value = u16> {
+    0..16u16
+}
+
+// Any byte, with keys, font digits and the edges where arithmetic carries
+// coming up often
+fn value() -> impl Strategy<Value = u8> {
+    prop_oneof![any::<u8>(), 0..16u8, 0xF0..=0xFFu8, Just(0x80)]
+}
+
+// Any instruction the model understands, with addresses that mostly land in
+// the program and the data. BNNN can still jump anywhere, with V0 added.
+fn instruction() -> impl Strategy<Value = u16> {
+    let code = START..START + 2 * MAX_PROGRAM as u16;
+    let near = (0..0x200u16).prop_map(|offset| START + offset);
+    prop_oneof![
+        Just(0x0000),
+        Just(0x00E0),
+        Just(0x00EE),
+        code.clone().prop_map(|addr| 0x1000 | addr & !1),
+        code.clone().prop_map(|addr| 0x2000 | addr & !1),
+        (3..5u16, register(), value()).prop_map(|(op, x, nn)| op << 12 | x << 8 | nn as u16),
+        (prop::sample::select(vec![5u16, 9]), register(), register())
+            .prop_map(|(op, x, y)| op << 12 | x << 8 | y << 4),
+        (6..8u16, register(), value()).prop_map(|(op, x, nn)| op << 12 | x << 8 | nn as u16),
+        (
+            register(),
+            register(),
+            prop::sample::select(vec![0u16, 1, 2, 3, 4, 5, 6, 7, 0xE])
+        )
+            .prop_map(|(x, y, n)| 0x8000 | x << 8 | y << 4 | n),
+        near.prop_map(|addr| 0xA000 | addr),
+        code.prop_map(|addr| 0xB000 | addr & !1),
+        (register(), any::<u8>()).prop_map(|(x, nn)| 0xC000 | x << 8 | nn as u16),
+        (register(), register(), 0..16u16).prop_map(|(x, y, n)| 0xD000 | x << 8 | y << 4 | n),
+        (register(), prop::sample::select(vec![0x9Eu16, 0xA1]))
+            .prop_map(|(x, low)| 0xE000 | x << 8 | low),
+        (
+            register(),
+            prop::sample::select(vec![
+                0x07u16, 0x0A, 0x15, 0x18, 0x1E, 0x29, 0x33, 0x55, 0x65
+            ])
+        )
+            .prop_map(|(x, low)| 0xF000 | x << 8 | low),
+    ]
+}
+
+fn quirks() -> impl Strategy<Value = Quirks> {
+    any::<[bool; 6]>().prop_map(|flags| Quirks {
+        shift_uses_vy: flags[0],
+        memory_increments_i: flags[1],
+        jump_uses_vx: flags[2],
+        clip_sprites: flags[3],
+        logic_resets_vf: flags[4],
+        display_wait: flags[5],
+    })
+}
+
+fn case() -> impl Strategy<Value = Case> {
+    let machine = (
+        quirks(),
+        prop::array::uniform16(value()),
+        prop_oneof![0..0x50u16, DATA..DATA + 0x40, 0..0x1000u16],
+        prop::collection::vec((START..START + 0x40).prop_map(|addr| addr & !1), 0..4),
+        (any::<u8>(), any::<u8>()),
+    );
+    let inputs = (
+        prop::array::uniform16(prop::bool::weighted(0.1)),
+        prop::collection::vec((0..64u8, 0..32u8), 0..16),
+        any::<u64>(),
+        prop::collection::vec(any::<u8>(), 0..64),
+    );
+    let run = (
+        prop::collection::vec(instruction(), 1..MAX_PROGRAM),
+        1..20usize,
+        1..200usize,
+    );
+    (machine, inputs, run).prop_map(|(machine, inputs, run)| {
+        let (quirks, registers, index_register, stack, (delay_timer, sound_timer)) = machine;
+        let (keys, lit, seed, data) = inputs;
+        let (mut program, timer_period, steps) = run;
+        // Keep jumps and calls inside the program, and go round again at the
+        // end rather than running off into empty memory
+        let len = program.len() as u16;
+        for op in program
+            .iter_mut()
+            .filter(|op| matches!(**op >> 12, 0x1 | 0x2 | 0xB))
+        {
+            let target = (*op & 0xFFF).saturating_sub(START) / 2 % len;
+            *op = *op & 0xF000 | (START + 2 * target);
+        }
+        program.push(0x1000 | START);
+        Case {
+            quirks,
+            registers,
+            index_register,
+            stack,
+            delay_timer,
+            sound_timer,
+            keys,
+            lit,
+            seed,
+            data,
+            program,
+            timer_period,
+            steps,
+        }
+    })
+}
+
+// What's different between two states, field by field
+fn differences(model: &CpuState, cpu: &CpuState) -> Vec<String> {
+    let mut found = Vec::new();
+    let mut compare = |name: &str, model: String, cpu: String| {
+        if model != cpu {
+            found.push(format!("{name}: model {model}, cpu {cpu}"));
+        }
+    };
+    compare(
+        "PC",
+        format!("{:#05X}", model.pc),
+        format!("{:#05X}", cpu.pc),
+    );
+    compare(
+        "I",
+        format!("{:#05X}", model.index_register),
+        format!("{:#05X}", cpu.index_register),
+    );
+    compare(
+        "V0-VF",
+        format!("{:02X?}", model.variable_registers),
+        format!("{:02X?}", cpu.variable_registers),
+    );
+    compare(
+        "stack",
+        format!("{:03X?}", model.stack),
+        format!("{:03X?}", cpu.stack),
+    );
+    compare(
+        "DT",
+        model.delay_timer.to_string(),
+        cpu.delay_timer.to_string(),
+    );
+    compare(
+        "ST",
+        model.sound_timer.to_string(),
+        cpu.sound_timer.to_string(),
+    );
+    compare(
+        "waiting for vblank",
+        model.waiting_for_vblank.to_string(),
+        cpu.waiting_for_vblank.to_string(),
+    );
+    if let Some(addr) = (0..model.memory.len()).find(|&a| model.memory[a] != cpu.memory[a]) {
+        found.push(format!(
+            "memory from {addr:#05X}: model {:02X}, cpu {:02X}",
+            model.memory[addr], cpu.memory[addr]
+        ));
+    }
+    let pixels: Vec<_> = (0..model.display.len())
+        .filter(|&p| model.display[p] != cpu.display[p])
+        .map(|p| (p % SCREEN_WIDTH, p / SCREEN_WIDTH))
+        .collect();
+    if !pixels.is_empty() {
+        found.push(format!("{} pixels, first at {:?}", pixels.len(), pixels[0]));
+    }
+    found
+}
+
+// Run both side by side until the case's instructions are used up, or the
+// program does something the model leaves undefined. The Cpu's own choices
+// there are checked on their own in opcodes.rs. Returns where and how they
+// first disagreed.
+fn run_case(case: &Case) -> Result<(), String> {
+    let mut cpu = case.cpu();
+    let mut model = Model::new(cpu.snapshot(), case.seed);
+
+    for step in 0..case.steps {
+        if step > 0 && step % case.timer_period == 0 {
+            cpu.tick_timers();
+            model.tick_timers();
+        }
+        let pc = model.state.pc;
+        let op = match model.fetch() {
+            Ok(op) => op,
+            Err(_) => return Ok(()),
+        };
+        // Carrying on from past the end of memory is undefined too
+        if model.step().is_err() || model.fetch().is_err() {
+            return Ok(());
+        }
+        let at = format!(
+            "instruction {step}, {op:04X} ({}) at {pc:#05X}",
+            disassemble(op)
+        );
+        if panic::catch_unwind(AssertUnwindSafe(|| cpu.tick())).is_err() {
+            return Err(format!("{at}: the Cpu panicked"));
+        }
+        let found = differences(&model.state, &cpu.snapshot());
+        if !found.is_empty() {
+            return Err(format!("{at} disagrees\n  {}", found.join("\n  ")));
+        }
+    }
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn cpu_matches_the_reference_model(case in case()) {
+        if let Err(divergence) = run_case(&case) {
+            prop_assert!(false, "{}", divergence);
+        }
+    }
+}
+
+#[test]
+fn model_decodes_what_the_disassembler_knows() {
+    for op in 0..=u16::MAX {
+        let known = !disassemble(op).starts_with("DW ");
+        assert_eq!(decode(op).is_some(), known, "{op:04X}");
+    }
+}
+
+// The harness has to notice a difference to be worth anything, so break the
+// Cpu's state by hand and check it's reported
+#[test]
+fn divergences_are_reported() {
+    let case = Case {
+        quirks: Quirks::MODERN,
+        registers: [0; 16],
+        index_register: 0,
+        stack: Vec::new(),
+        delay_timer: 0,
+        sound_timer: 0,
+        keys: [false; 16],
+        lit: Vec::new(),
+        seed: 0,
+        data: Vec::new(),
+        program: vec![0x6A2A],
+        timer_period: 1,
+        steps: 1,
+    };
+    assert_eq!(run_case(&case), Ok(()));
+
+    let mut model = Model::new(case.cpu().snapshot(), case.seed);
+    model.step().unwrap();
+    let mut cpu = case.cpu();
+    cpu.tick();
+    cpu.set_variable_register(0xA, 0x2B).unwrap();
+    assert_eq!(
+        differences(&model.state, &cpu.snapshot()),
+        [
+            "V0-VF: model [00, 00, 00, 00, 00, 00, 00, 00, 00, 00, 2A, 00, 00, 00, 00, 00], \
+          cpu [00, 00, 00, 00, 00, 00, 00, 00, 00, 00, 2B, 00, 00, 00, 00, 00]"
+        ]
+    );
+}
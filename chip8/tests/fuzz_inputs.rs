@@ -0,0 +1,34 @@
+// Replays the fuzz targets' seeds and every crash they've found, so they're
+// checked on every test run and not only while fuzzing.
+
+#[path = "../fuzz/harness/mod.rs"]
+mod harness;
+
+use std::fs;
+use std::panic;
+use std::path::Path;
+
+fn replay(target: &str, run: fn(&[u8])) {
+    let fuzz = Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz");
+    let mut count = 0;
+    for dir in ["seeds", "regressions"] {
+        for entry in fs::read_dir(fuzz.join(dir).join(target)).unwrap() {
+            let path = entry.unwrap().path();
+            let data = fs::read(&path).unwrap();
+            let result = panic::catch_unwind(|| run(&data));
+            assert!(result.is_ok(), "{} failed", path.display());
+            count += 1;
+        }
+    }
+    assert!(count > 0, "no inputs for {target}");
+}
+
+#[test]
+fn run_rom_inputs() {
+    replay("run_rom", harness::run_rom);
+}
+
+#[test]
+fn run_state_inputs() {
+    replay("run_state", harness::run_state);
+}
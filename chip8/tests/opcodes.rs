@@ -0,0 +1,497 @@
+// The exact effect of every instruction, one small program at a time. VF is
+// written after the result for every arithmetic instruction, so when X is F
+// the flag is what's left in VF.
+
+mod common;
+
+use chip8::*;
+use common::*;
+
+fn v(state: &CpuState, x: usize) -> u8 {
+    state.variable_registers[x]
+}
+
+// Whether the last instruction of the program skipped the one after it
+fn skipped(program: &[u16]) -> bool {
+    let state = run_program(program);
+    state.pc == START + 2 * program.len() as u16 + 2
+}
+
+#[test]
+fn noop_only_moves_on() {
+    let mut cpu = cpu_with(&[0x0000]);
+    let before = cpu.snapshot();
+    cpu.tick();
+    assert_eq!(
+        cpu.snapshot(),
+        CpuState {
+            pc: START + 2,
+            ..before
+        }
+    );
+}
+
+#[test]
+fn clear_screen() {
+    let mut cpu = cpu_with(&[0x00E0]);
+    cpu.set_pixel(0, 0, true).unwrap();
+    cpu.set_pixel(63, 31, true).unwrap();
+    cpu.tick();
+    assert!(cpu.get_display().iter().all(|&lit| !lit));
+}
+
+#[test]
+fn jump() {
+    let state = run_program(&[0x1ABC]);
+    assert_eq!(state.pc, 0xABC);
+}
+
+#[test]
+fn call_and_return() {
+    let program = [
+        0x2206, // call 0x206
+        0x6001, // V0 = 1
+        0x1204, // loop
+        0x6102, // V1 = 2
+        0x00EE, // return
+    ];
+    let mut cpu = cpu_with(&program);
+    cpu.tick();
+    assert_eq!(cpu.get_pc(), 0x206);
+    assert_eq!(cpu.get_stack(), [0x202]);
+
+    run(&mut cpu, 2);
+    assert_eq!(cpu.get_pc(), 0x202);
+    assert!(cpu.get_stack().is_empty());
+
+    cpu.tick();
+    let state = cpu.snapshot();
+    assert_eq!((v(&state, 0), v(&state, 1)), (1, 2));
+}
+
+#[test]
+fn stack_holds_sixteen_calls() {
+    // Call itself over and over
+    let mut cpu = cpu_with(&[0x2200]);
+    run(&mut cpu, 16);
+    assert_eq!(cpu.get_stack(), [0x202; 16]);
+}
+
+#[test]
+fn skips_compare_registers_and_values() {
+    assert!(skipped(&[0x6042, 0x3042]));
+    assert!(!skipped(&[0x6042, 0x3043]));
+    assert!(skipped(&[0x6042, 0x4043]));
+    assert!(!skipped(&[0x6042, 0x4042]));
+    assert!(skipped(&[0x6042, 0x6142, 0x5010]));
+    assert!(!skipped(&[0x6042, 0x6143, 0x5010]));
+    assert!(skipped(&[0x6042, 0x6143, 0x9010]));
+    assert!(!skipped(&[0x6042, 0x6142, 0x9010]));
+}
+
+#[test]
+fn load_and_add_values() {
+    let state = run_program(&[0x6AFE, 0x7A03, 0x6F05, 0x6B10, 0x7B20]);
+    // Adding a value wraps around without touching VF
+    assert_eq!(v(&state, 0xA), 0x01);
+    assert_eq!(v(&state, 0xB), 0x30);
+    assert_eq!(v(&state, 0xF), 0x05);
+}
+
+#[test]
+fn copy_and_logic() {
+    let setup = [0x60F0, 0x613C, 0x6F07];
+    let result = |op: u16, quirks: Quirks| {
+        let mut program = setup.to_vec();
+        program.push(op);
+        let state = run_program_with_quirks(&program, quirks);
+        (v(&state, 0), v(&state, 0xF))
+    };
+
+    assert_eq!(result(0x8010, Quirks::MODERN), (0x3C, 0x07));
+    assert_eq!(result(0x8011, Quirks::MODERN), (0xFC, 0x07));
+    assert_eq!(result(0x8012, Quirks::MODERN), (0x30, 0x07));
+    assert_eq!(result(0x8013, Quirks::MODERN), (0xCC, 0x07));
+
+    // The original interpreter left VF at 0 after the logic instructions
+    let vf_reset = Quirks {
+        logic_resets_vf: true,
+        ..Quirks::MODERN
+    };
+    assert_eq!(result(0x8010, vf_reset), (0x3C, 0x07));
+    assert_eq!(result(0x8011, vf_reset), (0xFC, 0));
+    assert_eq!(result(0x8012, vf_reset), (0x30, 0));
+    assert_eq!(result(0x8013, vf_reset), (0xCC, 0));
+}
+
+// VX and VF after VX = x, VY = y and the given 8XY_ instruction, X being 0
+// and Y being 1
+fn arithmetic(op: u16, x: u8, y: u8) -> (u8, u8) {
+    let state = run_program(&[0x6000 | x as u16, 0x6100 | y as u16, op]);
+    (v(&state, 0), v(&state, 0xF))
+}
+
+#[test]
+fn add_sets_carry() {
+    assert_eq!(arithmetic(0x8014, 0x10, 0x20), (0x30, 0));
+    assert_eq!(arithmetic(0x8014, 0xFF, 0x01), (0x00, 1));
+    assert_eq!(arithmetic(0x8014, 0xFF, 0xFF), (0xFE, 1));
+}
+
+#[test]
+fn subtract_sets_not_borrow() {
+    assert_eq!(arithmetic(0x8015, 5, 3), (2, 1));
+    assert_eq!(arithmetic(0x8015, 3, 5), (0xFE, 0));
+    // Equal values don't borrow
+    assert_eq!(arithmetic(0x8015, 7, 7), (0, 1));
+
+    assert_eq!(arithmetic(0x8017, 3, 5), (2, 1));
+    assert_eq!(arithmetic(0x8017, 5, 3), (0xFE, 0));
+    assert_eq!(arithmetic(0x8017, 7, 7), (0, 1));
+}
+
+#[test]
+fn shifts_set_the_bit_shifted_out() {
+    assert_eq!(arithmetic(0x8016, 0b1000_0011, 0), (0b0100_0001, 1));
+    assert_eq!(arithmetic(0x8016, 0b1000_0010, 0), (0b0100_0001, 0));
+    assert_eq!(arithmetic(0x801E, 0b1000_0001, 0), (0b0000_0010, 1));
+    assert_eq!(arithmetic(0x801E, 0b0100_0001, 0), (0b1000_0010, 0));
+
+    // Shifting VY into VX, as the original interpreter did
+    let shift = Quirks {
+        shift_uses_vy: true,
+        ..Quirks::MODERN
+    };
+    let state = run_program_with_quirks(&[0x60FF, 0x6103, 0x8016], shift);
+    assert_eq!((v(&state, 0), v(&state, 1), v(&state, 0xF)), (1, 3, 1));
+    let state = run_program_with_quirks(&[0x60FF, 0x6140, 0x801E], shift);
+    assert_eq!(
+        (v(&state, 0), v(&state, 1), v(&state, 0xF)),
+        (0x80, 0x40, 0)
+    );
+}
+
+#[test]
+fn flag_wins_when_vf_is_the_target() {
+    // VF = 0xFF, V1 = 1: the sum is 0 but the carry is what's kept
+    let state = run_program(&[0x6FFF, 0x6101, 0x8F14]);
+    assert_eq!(v(&state, 0xF), 1);
+    let state = run_program(&[0x6F10, 0x6101, 0x8F14]);
+    assert_eq!(v(&state, 0xF), 0);
+
+    let state = run_program(&[0x6F05, 0x6103, 0x8F15]);
+    assert_eq!(v(&state, 0xF), 1);
+    let state = run_program(&[0x6F03, 0x6105, 0x8F15]);
+    assert_eq!(v(&state, 0xF), 0);
+
+    let state = run_program(&[0x6F03, 0x6105, 0x8F17]);
+    assert_eq!(v(&state, 0xF), 1);
+    let state = run_program(&[0x6F05, 0x6103, 0x8F17]);
+    assert_eq!(v(&state, 0xF), 0);
+
+    let state = run_program(&[0x6F02, 0x8F06]);
+    assert_eq!(v(&state, 0xF), 0);
+    let state = run_program(&[0x6F81, 0x8F0E]);
+    assert_eq!(v(&state, 0xF), 1);
+}
+
+#[test]
+fn vf_as_an_operand_is_read_before_the_flag() {
+    // V0 = 0xFF, VF = 1: the sum wraps to 0, then VF becomes the carry
+    let state = run_program(&[0x60FF, 0x6F01, 0x80F4]);
+    assert_eq!((v(&state, 0), v(&state, 0xF)), (0x00, 1));
+    let state = run_program(&[0x6001, 0x6F02, 0x80F5]);
+    assert_eq!((v(&state, 0), v(&state, 0xF)), (0xFF, 0));
+    let state = run_program(&[0x6001, 0x6F02, 0x80F7]);
+    assert_eq!((v(&state, 0), v(&state, 0xF)), (0x01, 1));
+}
+
+#[test]
+fn set_index_and_jump_with_offset() {
+    let state = run_program(&[0xA123]);
+    assert_eq!(state.index_register, 0x123);
+
+    let state = run_program(&[0x6010, 0x6220, 0xB300]);
+    assert_eq!(state.pc, 0x310);
+
+    // SUPER-CHIP adds VX, X being the top digit of the address
+    let jump = Quirks {
+        jump_uses_vx: true,
+        ..Quirks::MODERN
+    };
+    let state = run_program_with_quirks(&[0x6010, 0x6320, 0xB300], jump);
+    assert_eq!(state.pc, 0x320);
+}
+
+#[test]
+fn random_numbers_are_masked_and_repeatable() {
+    let program = [0xC00F, 0xC1F0, 0xC200];
+    let mut cpu = cpu_with(&program);
+    cpu.set_seed(1234);
+    run(&mut cpu, 3);
+    let first = cpu.snapshot();
+    assert_eq!(v(&first, 0) & 0xF0, 0);
+    assert_eq!(v(&first, 1) & 0x0F, 0);
+    assert_eq!(v(&first, 2), 0);
+
+    // The same seed gives the same numbers
+    let mut again = cpu_with(&program);
+    again.set_seed(1234);
+    run(&mut again, 3);
+    assert_eq!(
+        again.snapshot().variable_registers,
+        first.variable_registers
+    );
+}
+
+#[test]
+fn draw_sprites_and_detect_collisions() {
+    // Draw the font's 0 at (1, 2), then draw it again to erase it
+    let program = [0x6001, 0x6102, 0xA000, 0xD015, 0xD015];
+    let mut cpu = cpu_with(&program);
+    run(&mut cpu, 4);
+    let state = cpu.snapshot();
+    assert_eq!(
+        pixels(&state, 6, 7),
+        ["......", "......", ".####.", ".#..#.", ".#..#.", ".#..#.", ".####.",]
+    );
+    assert_eq!(v(&state, 0xF), 0);
+
+    cpu.tick();
+    let state = cpu.snapshot();
+    assert!(state.display.iter().all(|&lit| !lit));
+    assert_eq!(v(&state, 0xF), 1);
+}
+
+#[test]
+fn collisions_only_count_pixels_turned_off() {
+    // The top row of a 1 at (0, 0), then the top row of a 0 beside it at
+    // (4, 0): the sprites' bytes overlap but none of the lit pixels do
+    let program = [0x6000, 0x6100, 0xA005, 0xD011, 0x6004, 0xA000, 0xD011];
+    let state = run_program(&program);
+    assert_eq!(v(&state, 0xF), 0);
+    assert_eq!(pixels(&state, 8, 1), ["..#.####"]);
+
+    // The 0 drawn over the 1 erases its one pixel and lights the rest
+    let program = [0x6000, 0x6100, 0xA005, 0xD011, 0xA000, 0xD011];
+    let state = run_program(&program);
+    assert_eq!(v(&state, 0xF), 1);
+    assert_eq!(pixels(&state, 8, 1), ["##.#...."]);
+}
+
+#[test]
+fn sprites_start_wrapped_and_wrap_or_clip_past_the_edge() {
+    // Start at (66, 33), which wraps to (2, 1), and draw one byte
+    let state = run_program(&[0x6042, 0x6121, 0xA000, 0xD011]);
+    assert_eq!(pixels(&state, 8, 2), ["........", "..####.."]);
+
+    // Start at (62, 31): the rest of the row and the next row go off screen
+    let program = [0x603E, 0x611F, 0xA000, 0xD012];
+    let state = run_program(&program);
+    let lit = |state: &CpuState, x: usize, y: usize| state.display[x + SCREEN_WIDTH * y];
+    assert!(lit(&state, 62, 31) && lit(&state, 63, 31));
+    assert!(lit(&state, 0, 31) && lit(&state, 1, 31));
+    assert!(lit(&state, 62, 0) && lit(&state, 1, 0));
+
+    let clip = Quirks {
+        clip_sprites: true,
+        ..Quirks::MODERN
+    };
+    let state = run_program_with_quirks(&program, clip);
+    assert!(lit(&state, 62, 31) && lit(&state, 63, 31));
+    assert_eq!(state.display.iter().filter(|&&lit| lit).count(), 2);
+}
+
+#[test]
+fn display_wait_holds_until_the_next_frame() {
+    let display_wait = Quirks {
+        display_wait: true,
+        ..Quirks::MODERN
+    };
+    let mut cpu = cpu_with_quirks(&[0xD005, 0x6001], display_wait);
+    run(&mut cpu, 3);
+    assert!(cpu.is_waiting_for_vblank());
+    assert_eq!(cpu.get_pc(), START + 2);
+
+    cpu.tick_timers();
+    cpu.tick();
+    assert_eq!(cpu.get_variable_registers()[0], 1);
+}
+
+#[test]
+fn key_skips() {
+    let program = [0x6007, 0xE09E];
+    let mut cpu = cpu_with(&program);
+    run(&mut cpu, 2);
+    assert_eq!(cpu.get_pc(), START + 4);
+
+    let mut cpu = cpu_with(&program);
+    cpu.keypress(7, true).unwrap();
+    run(&mut cpu, 2);
+    assert_eq!(cpu.get_pc(), START + 6);
+
+    let program = [0x6007, 0xE0A1];
+    let mut cpu = cpu_with(&program);
+    run(&mut cpu, 2);
+    assert_eq!(cpu.get_pc(), START + 6);
+
+    let mut cpu = cpu_with(&program);
+    cpu.keypress(7, true).unwrap();
+    // Other keys don't count
+    cpu.keypress(8, true).unwrap();
+    run(&mut cpu, 2);
+    assert_eq!(cpu.get_pc(), START + 4);
+}
+
+#[test]
+fn wait_for_key_blocks_but_timers_run() {
+    let mut cpu = cpu_with(&[0x6F03, 0xFF15, 0xF30A, 0x6101]);
+    run(&mut cpu, 10);
+    assert_eq!(cpu.get_pc(), START + 4);
+    cpu.tick_timers();
+    assert_eq!(cpu.get_delay_timer(), 2);
+
+    cpu.keypress(0xB, true).unwrap();
+    run(&mut cpu, 2);
+    let state = cpu.snapshot();
+    assert_eq!(v(&state, 3), 0xB);
+    assert_eq!(v(&state, 1), 1);
+}
+
+#[test]
+fn timers_count_down_to_zero() {
+    let mut cpu = cpu_with(&[0x6002, 0xF015, 0x6103, 0xF118, 0xF207]);
+    run(&mut cpu, 5);
+    assert_eq!(cpu.get_variable_registers()[2], 2);
+    assert_eq!((cpu.get_delay_timer(), cpu.get_sound_timer()), (2, 3));
+
+    for expected in [(1, 2), (0, 1), (0, 0), (0, 0)] {
+        cpu.tick_timers();
+        assert_eq!((cpu.get_delay_timer(), cpu.get_sound_timer()), expected);
+    }
+}
+
+#[test]
+fn index_arithmetic_and_font() {
+    let state = run_program(&[0xA0FF, 0x6002, 0xF01E]);
+    assert_eq!(state.index_register, 0x101);
+    // Adding doesn't touch VF
+    let state = run_program(&[0xAFFF, 0x6001, 0x6F07, 0xF01E]);
+    assert_eq!((state.index_register, v(&state, 0xF)), (0x1000, 7));
+
+    let state = run_program(&[0x600A, 0xF029]);
+    assert_eq!(state.index_register, 50);
+    assert_eq!(state.memory[50..55], [0xF0, 0x90, 0xF0, 0x90, 0x90]);
+}
+
+#[test]
+fn bcd_splits_into_digits() {
+    for (value, digits) in [
+        (0, [0, 0, 0]),
+        (9, [0, 0, 9]),
+        (10, [0, 1, 0]),
+        (99, [0, 9, 9]),
+        (100, [1, 0, 0]),
+        (255, [2, 5, 5]),
+    ] {
+        let state = run_program(&[0xA300, 0x6500 | value, 0xF533]);
+        assert_eq!(state.memory[0x300..0x303], digits, "BCD of {value}");
+        assert_eq!(state.index_register, 0x300);
+    }
+}
+
+#[test]
+fn store_and_load_registers() {
+    let program = [0x6011, 0x6122, 0x6233, 0xA300, 0xF155, 0xA300, 0xF265];
+    let state = run_program(&program);
+    // Only V0 to V1 were stored, so V2 is loaded from empty memory
+    assert_eq!(state.memory[0x300..0x303], [0x11, 0x22, 0]);
+    assert_eq!(state.variable_registers[..3], [0x11, 0x22, 0]);
+    assert_eq!(state.index_register, 0x300);
+
+    // The original interpreter moved I along as it went
+    let memory = Quirks {
+        memory_increments_i: true,
+        ..Quirks::MODERN
+    };
+    let state = run_program_with_quirks(&[0xA300, 0xF155], memory);
+    assert_eq!(state.index_register, 0x302);
+    let state = run_program_with_quirks(&[0xA300, 0xF065], memory);
+    assert_eq!(state.index_register, 0x301);
+}
+
+#[test]
+fn setters_are_checked() {
+    let mut cpu = cpu_with(&[0x0000]);
+    assert!(cpu.set_pc(0xFFE).is_ok());
+    let err = cpu.set_pc(0xFFF).unwrap_err();
+    assert_eq!(err, DebugError::NoRoomForInstruction { addr: 0xFFF });
+    assert_eq!(
+        err.to_string(),
+        "an instruction at 0xFFF would run past the end of memory"
+    );
+    assert_eq!(cpu.get_pc(), 0xFFE);
+    assert!(cpu.set_variable_register(0xF, 1).is_ok());
+    assert_eq!(
+        cpu.set_variable_register(16, 1),
+        Err(DebugError::NoSuchRegister(16))
+    );
+    assert!(cpu.set_stack(&[0x200; 16]).is_ok());
+    assert!(cpu.set_stack(&[0x200; 17]).is_err());
+    assert_eq!(cpu.get_stack().len(), 16);
+    assert!(cpu.write_memory(0xFFE, &[1, 2]).is_ok());
+    assert!(cpu.write_memory(0xFFE, &[1, 2, 3]).is_err());
+    assert_eq!(cpu.get_memory()[0xFFE..], [1, 2]);
+    assert!(cpu.set_pixel(63, 31, true).is_ok());
+    assert_eq!(
+        cpu.set_pixel(70, 40, true),
+        Err(DebugError::OffScreen { x: 70, y: 40 })
+    );
+    assert!(cpu.set_pixel(64, 0, true).is_err());
+    assert!(cpu.set_pixel(0, 32, true).is_err());
+    assert_eq!(cpu.get_display().iter().filter(|&&lit| lit).count(), 1);
+}
+
+#[test]
+fn unknown_opcodes_are_skipped() {
+    let state = run_program(&[0xFFFF, 0x8008, 0xE000, 0x6001]);
+    assert_eq!(state.pc, START + 8);
+    assert_eq!(v(&state, 0), 1);
+}
+
+#[test]
+fn stack_keeps_the_innermost_calls() {
+    // Seventeen calls deep, then back out of one
+    let mut cpu = cpu_with(&[0x2202, 0x2204]);
+    cpu.set_stack(&[0x300; 16]).unwrap();
+    cpu.tick();
+    assert_eq!(cpu.get_stack().len(), 16);
+    assert_eq!(cpu.get_stack()[15], 0x202);
+
+    // Returning with nothing to return to carries on
+    let state = run_program(&[0x00EE, 0x6001]);
+    assert_eq!((state.pc, v(&state, 0)), (START + 4, 1));
+}
+
+#[test]
+fn memory_wraps_around() {
+    // BCD of 123 at I = 0xFFF writes the last byte and the first two
+    let state = run_program(&[0xAFFF, 0x607B, 0xF033]);
+    assert_eq!(state.memory[0xFFF], 1);
+    assert_eq!(state.memory[..2], [2, 3]);
+
+    // Running off the end carries on from the start
+    let mut cpu = cpu_with(&[0x1FFE]);
+    run(&mut cpu, 2);
+    assert_eq!(cpu.get_pc(), 0);
+}
+
+#[test]
+fn keys_past_f_are_never_held() {
+    let mut cpu = cpu_with(&[0x6010, 0xE09E, 0xE0A1]);
+    assert_eq!(cpu.keypress(16, true), Err(DebugError::NoSuchKey(16)));
+    run(&mut cpu, 2);
+    assert_eq!(cpu.get_pc(), START + 4);
+    cpu.tick();
+    assert_eq!(cpu.get_pc(), START + 8);
+}
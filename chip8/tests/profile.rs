@@ -0,0 +1,161 @@
+mod common;
+
+use chip8::*;
+use common::*;
+
+// Calls a subroutine that calls another, then loops. The word at 0x206 is
+// data and never runs.
+const PROGRAM: [u16; 9] = [
+    0x6000, // 0x200
+    0x2208, // 0x202
+    0x1204, // 0x204
+    0xF0F0, // 0x206
+    0x7001, // 0x208
+    0x220E, // 0x20A
+    0x00EE, // 0x20C
+    0x7002, // 0x20E
+    0x00EE, // 0x210
+];
+
+fn profiled(program: &[u16], instructions: usize) -> Profile {
+    let mut cpu = cpu_with(program);
+    cpu.start_profiling();
+    run(&mut cpu, instructions);
+    cpu.stop_profiling().unwrap()
+}
+
+#[test]
+fn profiling_is_off_until_started() {
+    let mut cpu = cpu_with(&PROGRAM);
+    run(&mut cpu, 4);
+    assert!(cpu.get_profile().is_none());
+
+    cpu.start_profiling();
+    run(&mut cpu, 3);
+    assert_eq!(cpu.get_profile().unwrap().instructions(), 3);
+    assert_eq!(cpu.stop_profiling().unwrap().instructions(), 3);
+    assert!(cpu.get_profile().is_none());
+}
+
+#[test]
+fn addresses_and_opcodes_are_counted() {
+    let profile = profiled(&PROGRAM, 10);
+
+    assert_eq!(profile.instructions(), 10);
+    assert_eq!(profile.count(0x204), 3);
+    assert_eq!(profile.count(0x206), 0);
+    assert_eq!(profile.hotspots()[0], (0x204, 3));
+    assert_eq!(profile.hotspots().len(), 8);
+
+    let classes: Vec<_> = profile
+        .classes()
+        .iter()
+        .map(|(&class, &count)| (class, count))
+        .collect();
+    assert_eq!(
+        classes,
+        [
+            ("00EE", 2),
+            ("1NNN", 3),
+            ("2NNN", 2),
+            ("6XNN", 1),
+            ("7XNN", 2)
+        ]
+    );
+}
+
+#[test]
+fn subroutines_are_followed_through_calls_and_returns() {
+    let profile = profiled(&PROGRAM, 10);
+
+    assert_eq!(
+        profile.subroutines(),
+        [
+            Subroutine {
+                address: 0x208,
+                calls: 1,
+                instructions: 5,
+                own: 3,
+            },
+            Subroutine {
+                address: 0x20E,
+                calls: 1,
+                instructions: 2,
+                own: 2,
+            },
+        ]
+    );
+    assert_eq!(
+        profile.to_folded(),
+        "main 5\nmain;0x208 3\nmain;0x208;0x20E 2\n"
+    );
+}
+
+#[test]
+fn recursive_calls_count_once() {
+    // Calls itself until V0 reaches 3
+    let program = [0x2202, 0x7001, 0x3003, 0x2202, 0x00EE];
+    let profile = profiled(&program, 12);
+
+    let subroutine = &profile.subroutines()[0];
+    assert_eq!(subroutine.calls, 3);
+    assert_eq!(subroutine.instructions, 11);
+    assert_eq!(subroutine.own, 11);
+}
+
+#[test]
+fn code_that_never_ran_is_found() {
+    let profile = profiled(&PROGRAM, 10);
+    let unexecuted = profile.unexecuted();
+    assert_eq!((unexecuted.len(), &unexecuted[0]), (1, &(0x206..0x208)));
+
+    // Only the ROM counts, not the empty memory after it
+    let profile = profiled(&PROGRAM, 5);
+    assert_eq!(
+        profile.unexecuted(),
+        [0x204..0x208, 0x20C..0x20E, 0x210..0x212]
+    );
+}
+
+#[test]
+fn waiting_for_vblank_is_not_counted() {
+    let mut cpu = cpu_with_quirks(&[0xD001, 0x1202], Quirks::VIP);
+    cpu.start_profiling();
+    run(&mut cpu, 5);
+    assert_eq!(cpu.get_profile().unwrap().instructions(), 1);
+}
+
+#[test]
+fn reports() {
+    let profile = profiled(&PROGRAM, 10);
+
+    let text = profile.to_text();
+    assert!(text.starts_with("10 instructions\n"), "{text}");
+    assert!(
+        text.contains("  0x204           3   30.0%  1204  JP 0x204\n"),
+        "{text}"
+    );
+    assert!(text.contains("  1NNN              3   30.0%\n"), "{text}");
+    assert!(
+        text.contains("  0x208             1           5  50.0%           3\n"),
+        "{text}"
+    );
+    assert!(text.contains("  0x206-0x207  2 bytes\n"), "{text}");
+
+    let json = profile.to_json();
+    assert!(json.starts_with("{\n  \"instructions\": 10,\n"), "{json}");
+    assert!(
+        json.contains(
+            "{\"address\": 516, \"count\": 3, \"opcode\": \"1204\", \"instruction\": \"JP 0x204\"}"
+        ),
+        "{json}"
+    );
+    assert!(
+        json.contains("{\"address\": 520, \"calls\": 1, \"instructions\": 5, \"own\": 3}"),
+        "{json}"
+    );
+    assert!(
+        json.contains("\"unexecuted\": [\n    {\"start\": 518, \"end\": 520}\n  ]"),
+        "{json}"
+    );
+}
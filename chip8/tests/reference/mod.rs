@@ -0,0 +1,333 @@
+// A reference model of Chip-8, written to be obviously right rather than
+// fast. Instructions are decoded into their own type before anything runs,
+// every memory access is checked, and each instruction works out all of its
+// results before writing any of them back. The differential tests hold the
+// Cpu to whatever this does.
+//
+// Programs can do things real interpreters disagree on or crash over, like
+// returning with an empty stack. The model doesn't pick an answer for those,
+// it stops and says what happened.
+
+use chip8::*;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const MEM_SIZE: usize = 4096;
+const STACK_SIZE: usize = 16;
+const NUM_KEYS: u8 = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    Nop,
+    Clear,
+    Return,
+    Jump(u16),
+    Call(u16),
+    SkipIfEqual(usize, u8),
+    SkipIfNotEqual(usize, u8),
+    SkipIfRegistersEqual(usize, usize),
+    SkipIfRegistersNotEqual(usize, usize),
+    Load(usize, u8),
+    Add(usize, u8),
+    Copy(usize, usize),
+    Or(usize, usize),
+    And(usize, usize),
+    Xor(usize, usize),
+    AddRegisters(usize, usize),
+    Subtract(usize, usize),
+    ShiftRight(usize, usize),
+    SubtractFrom(usize, usize),
+    ShiftLeft(usize, usize),
+    LoadIndex(u16),
+    JumpWithOffset(u16),
+    Random(usize, u8),
+    Draw(usize, usize, u8),
+    SkipIfKey(usize),
+    SkipUnlessKey(usize),
+    ReadDelay(usize),
+    WaitForKey(usize),
+    SetDelay(usize),
+    SetSound(usize),
+    AddToIndex(usize),
+    Font(usize),
+    Bcd(usize),
+    Store(usize),
+    Restore(usize),
+}
+
+pub fn decode(op: u16) -> Option<Instruction> {
+    use Instruction::*;
+
+    let digit = |n: u32| ((op >> (12 - 4 * n)) & 0xF) as usize;
+    let (x, y, n) = (digit(1), digit(2), digit(3) as u8);
+    let nn = (op & 0xFF) as u8;
+    let nnn = op & 0xFFF;
+
+    let instruction = match (digit(0), x, y, n) {
+        (0x0, 0, 0x0, 0x0) => Nop,
+        (0x0, 0, 0xE, 0x0) => Clear,
+        (0x0, 0, 0xE, 0xE) => Return,
+        (0x1, ..) => Jump(nnn),
+        (0x2, ..) => Call(nnn),
+        (0x3, ..) => SkipIfEqual(x, nn),
+        (0x4, ..) => SkipIfNotEqual(x, nn),
+        (0x5, ..) => SkipIfRegistersEqual(x, y),
+        (0x6, ..) => Load(x, nn),
+        (0x7, ..) => Add(x, nn),
+        (0x8, _, _, 0x0) => Copy(x, y),
+        (0x8, _, _, 0x1) => Or(x, y),
+        (0x8, _, _, 0x2) => And(x, y),
+        (0x8, _, _, 0x3) => Xor(x, y),
+        (0x8, _, _, 0x4) => AddRegisters(x, y),
+        (0x8, _, _, 0x5) => Subtract(x, y),
+        (0x8, _, _, 0x6) => ShiftRight(x, y),
+        (0x8, _, _, 0x7) => SubtractFrom(x, y),
+        (0x8, _, _, 0xE) => ShiftLeft(x, y),
+        (0x9, _, _, 0x0) => SkipIfRegistersNotEqual(x, y),
+        (0xA, ..) => LoadIndex(nnn),
+        (0xB, ..) => JumpWithOffset(nnn),
+        (0xC, ..) => Random(x, nn),
+        (0xD, ..) => Draw(x, y, n),
+        (0xE, _, 0x9, 0xE) => SkipIfKey(x),
+        (0xE, _, 0xA, 0x1) => SkipUnlessKey(x),
+        (0xF, _, 0x0, 0x7) => ReadDelay(x),
+        (0xF, _, 0x0, 0xA) => WaitForKey(x),
+        (0xF, _, 0x1, 0x5) => SetDelay(x),
+        (0xF, _, 0x1, 0x8) => SetSound(x),
+        (0xF, _, 0x1, 0xE) => AddToIndex(x),
+        (0xF, _, 0x2, 0x9) => Font(x),
+        (0xF, _, 0x3, 0x3) => Bcd(x),
+        (0xF, _, 0x5, 0x5) => Store(x),
+        (0xF, _, 0x6, 0x5) => Restore(x),
+        _ => return None,
+    };
+    Some(instruction)
+}
+
+// Why the model stopped instead of running an instruction
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Undefined {
+    UnknownOpcode(u16),
+    StackOverflow,
+    StackUnderflow,
+    OutOfMemory(usize),
+    NoSuchKey(u8),
+}
+
+pub struct Model {
+    pub state: CpuState,
+    rng: StdRng,
+}
+
+impl Model {
+    // Carry on from a Cpu's state. CXNN draws from the same generator as the
+    // Cpu, which has to be freshly seeded with the same seed.
+    pub fn new(state: CpuState, seed: u64) -> Self {
+        Model {
+            state,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    fn read(&self, addr: usize) -> Result<u8, Undefined> {
+        self.state
+            .memory
+            .get(addr)
+            .copied()
+            .ok_or(Undefined::OutOfMemory(addr))
+    }
+
+    fn write(&mut self, addr: usize, value: u8) -> Result<(), Undefined> {
+        if addr >= MEM_SIZE {
+            return Err(Undefined::OutOfMemory(addr));
+        }
+        self.state.memory[addr] = value;
+        Ok(())
+    }
+
+    fn key(&self, x: usize) -> Result<bool, Undefined> {
+        let key = self.state.variable_registers[x];
+        if key >= NUM_KEYS {
+            return Err(Undefined::NoSuchKey(key));
+        }
+        Ok(self.state.keys[key as usize])
+    }
+
+    // The opcode at the program counter
+    pub fn fetch(&self) -> Result<u16, Undefined> {
+        let pc = self.state.pc as usize;
+        Ok(u16::from_be_bytes([self.read(pc)?, self.read(pc + 1)?]))
+    }
+
+    pub fn tick_timers(&mut self) {
+        self.state.waiting_for_vblank = false;
+        self.state.delay_timer = self.state.delay_timer.saturating_sub(1);
+        self.state.sound_timer = self.state.sound_timer.saturating_sub(1);
+    }
+
+    // Run one instruction. When it's undefined nothing is changed.
+    pub fn step(&mut self) -> Result<(), Undefined> {
+        use Instruction::*;
+
+        if self.state.waiting_for_vblank {
+            return Ok(());
+        }
+        let op = self.fetch()?;
+        let instruction = decode(op).ok_or(Undefined::UnknownOpcode(op))?;
+        let next = self.state.pc + 2;
+        let skip = next + 2;
+        let quirks = self.state.quirks;
+        let v = self.state.variable_registers;
+        let i = self.state.index_register as usize;
+
+        // Where to carry on from, and the new value and flag of an
+        // arithmetic instruction
+        let mut pc = next;
+        let mut result: Option<(usize, u8, Option<u8>)> = None;
+        let skip_if = |condition: bool| if condition { skip } else { next };
+
+        match instruction {
+            Nop => (),
+            Clear => self.state.display.iter_mut().for_each(|p| *p = false),
+            Return => {
+                pc = self.state.stack.pop().ok_or(Undefined::StackUnderflow)?;
+            }
+            Jump(addr) => pc = addr,
+            Call(addr) => {
+                if self.state.stack.len() == STACK_SIZE {
+                    return Err(Undefined::StackOverflow);
+                }
+                self.state.stack.push(next);
+                pc = addr;
+            }
+            SkipIfEqual(x, nn) => pc = skip_if(v[x] == nn),
+            SkipIfNotEqual(x, nn) => pc = skip_if(v[x] != nn),
+            SkipIfRegistersEqual(x, y) => pc = skip_if(v[x] == v[y]),
+            SkipIfRegistersNotEqual(x, y) => pc = skip_if(v[x] != v[y]),
+            Load(x, nn) => result = Some((x, nn, None)),
+            Add(x, nn) => result = Some((x, v[x].wrapping_add(nn), None)),
+            Copy(x, y) => result = Some((x, v[y], None)),
+            Or(x, y) | And(x, y) | Xor(x, y) => {
+                let value = match instruction {
+                    Or(..) => v[x] | v[y],
+                    And(..) => v[x] & v[y],
+                    _ => v[x] ^ v[y],
+                };
+                let flag = quirks.logic_resets_vf.then_some(0);
+                result = Some((x, value, flag));
+            }
+            AddRegisters(x, y) => {
+                let sum = v[x] as u16 + v[y] as u16;
+                result = Some((x, sum as u8, Some((sum > 0xFF) as u8)));
+            }
+            Subtract(x, y) => {
+                let flag = (v[x] >= v[y]) as u8;
+                result = Some((x, v[x].wrapping_sub(v[y]), Some(flag)));
+            }
+            SubtractFrom(x, y) => {
+                let flag = (v[y] >= v[x]) as u8;
+                result = Some((x, v[y].wrapping_sub(v[x]), Some(flag)));
+            }
+            ShiftRight(x, y) | ShiftLeft(x, y) => {
+                let value = if quirks.shift_uses_vy { v[y] } else { v[x] };
+                result = Some(match instruction {
+                    ShiftRight(..) => (x, value / 2, Some(value % 2)),
+                    _ => (x, value.wrapping_mul(2), Some(value / 128)),
+                });
+            }
+            LoadIndex(addr) => self.state.index_register = addr,
+            JumpWithOffset(addr) => {
+                let x = if quirks.jump_uses_vx {
+                    (addr >> 8) as usize
+                } else {
+                    0
+                };
+                pc = addr + v[x] as u16;
+            }
+            Random(x, nn) => {
+                let byte: u8 = self.rng.gen();
+                result = Some((x, byte & nn, None));
+            }
+            Draw(x, y, rows) => {
+                let sprite = (0..rows as usize)
+                    .map(|row| self.read(i + row))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let erased = self.draw(v[x] as usize, v[y] as usize, &sprite);
+                result = Some((0xF, erased as u8, None));
+                self.state.waiting_for_vblank = quirks.display_wait;
+            }
+            SkipIfKey(x) => pc = skip_if(self.key(x)?),
+            SkipUnlessKey(x) => pc = skip_if(!self.key(x)?),
+            ReadDelay(x) => result = Some((x, self.state.delay_timer, None)),
+            WaitForKey(x) => match self.state.keys.iter().position(|&held| held) {
+                Some(key) => result = Some((x, key as u8, None)),
+                None => pc = self.state.pc,
+            },
+            SetDelay(x) => self.state.delay_timer = v[x],
+            SetSound(x) => self.state.sound_timer = v[x],
+            AddToIndex(x) => {
+                self.state.index_register = self.state.index_register.wrapping_add(v[x] as u16)
+            }
+            Font(x) => self.state.index_register = 5 * v[x] as u16,
+            Bcd(x) => {
+                let digits = [v[x] / 100, v[x] / 10 % 10, v[x] % 10];
+                self.read(i + 2)?;
+                for (offset, digit) in digits.into_iter().enumerate() {
+                    self.write(i + offset, digit)?;
+                }
+            }
+            Store(x) => {
+                self.read(i + x)?;
+                for (r, &value) in v[..=x].iter().enumerate() {
+                    self.write(i + r, value)?;
+                }
+                if quirks.memory_increments_i {
+                    self.state.index_register += x as u16 + 1;
+                }
+            }
+            Restore(x) => {
+                self.read(i + x)?;
+                for r in 0..=x {
+                    self.state.variable_registers[r] = self.read(i + r)?;
+                }
+                if quirks.memory_increments_i {
+                    self.state.index_register += x as u16 + 1;
+                }
+            }
+        }
+
+        // The flag goes last, so it's what's left when X is F
+        if let Some((x, value, flag)) = result {
+            self.state.variable_registers[x] = value;
+            if let Some(flag) = flag {
+                self.state.variable_registers[0xF] = flag;
+            }
+        }
+        self.state.pc = pc;
+        Ok(())
+    }
+
+    // XOR a sprite onto the display, returning whether any pixel was erased
+    fn draw(&mut self, x: usize, y: usize, sprite: &[u8]) -> bool {
+        let clip = self.state.quirks.clip_sprites;
+        let (left, top) = (x % SCREEN_WIDTH, y % SCREEN_HEIGHT);
+        let mut erased = false;
+        for (row, bits) in sprite.iter().enumerate() {
+            for col in 0..8 {
+                if bits & (0x80 >> col) == 0 {
+                    continue;
+                }
+                let (px, py) = (left + col, top + row);
+                if clip && (px >= SCREEN_WIDTH || py >= SCREEN_HEIGHT) {
+                    continue;
+                }
+                let pixel = &mut self.state.display
+                    [px % SCREEN_WIDTH + SCREEN_WIDTH * (py % SCREEN_HEIGHT)];
+                erased |= *pixel;
+                *pixel = !*pixel;
+            }
+        }
+        erased
+    }
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use chip8::*;
+use common::*;
+
+// Draws a random byte into V0 forever
+const RANDOM: [u16; 2] = [0xC0FF, 0x1200];
+
+// Where the count of random numbers drawn is kept, just before the last byte
+fn draws_at(state: &[u8]) -> usize {
+    state.len() - 9
+}
+
+#[test]
+fn random_numbers_carry_on_after_loading() {
+    let mut cpu = cpu_with(&RANDOM);
+    cpu.set_seed(7);
+    run(&mut cpu, 2000);
+    let state = cpu.save_state();
+    run(&mut cpu, 20);
+    let expected = cpu.snapshot();
+
+    let mut loaded = Cpu::setup_cpu();
+    loaded.load_state(&state).unwrap();
+    run(&mut loaded, 20);
+    assert_eq!(loaded.snapshot(), expected);
+}
+
+#[test]
+fn any_number_of_draws_loads_straight_away() {
+    let mut cpu = cpu_with(&RANDOM);
+    let mut state = cpu.save_state();
+    let at = draws_at(&state);
+    state[at..at + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+
+    cpu.load_state(&state).unwrap();
+    run(&mut cpu, 4);
+    let state = cpu.save_state();
+    assert_eq!(state[draws_at(&state)..][..8], 1u64.to_le_bytes());
+}
+
+#[test]
+fn corrupt_states_are_turned_down() {
+    let mut cpu = cpu_with(&RANDOM);
+    run(&mut cpu, 3);
+    let before = cpu.snapshot();
+    let state = cpu.save_state();
+
+    // A stack deeper than the Cpu's
+    let mut deep = state.clone();
+    let sp = 4 + 1 + 4096 + SCREEN_WIDTH * SCREEN_HEIGHT + 2 + 2 * 16;
+    deep[sp] = 17;
+    let err = cpu.load_state(&deep).unwrap_err();
+    assert_eq!(err.to_string(), "corrupt save state");
+
+    assert!(cpu.load_state(&state[..state.len() - 1]).is_err());
+    assert!(cpu.load_state(&[&state[..], &[0]].concat()).is_err());
+    assert!(cpu.load_state(b"C8ST\x09").is_err());
+    assert_eq!(cpu.snapshot(), before);
+}
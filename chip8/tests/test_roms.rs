@@ -0,0 +1,248 @@
+// The checks made by the community's opcode and flags test ROMs, written out
+// as programs here since the ROMs can't be downloaded while testing. Like the
+// ROMs, each program checks itself: every failed check jumps to a loop at
+// FAIL with the number of the test in VD, and a program that gets to the end
+// loops at PASS.
+
+mod common;
+
+use chip8::*;
+use common::*;
+
+const FAIL: u16 = START + 2;
+const PASS: u16 = START + 4;
+
+// The register holding the number of the test running
+const TEST_REGISTER: u16 = 0xD;
+
+struct SelfTest {
+    program: Vec<u16>,
+}
+
+impl SelfTest {
+    fn new() -> Self {
+        SelfTest {
+            program: vec![0x1000 | (START + 6), 0x1000 | FAIL, 0x1000 | PASS],
+        }
+    }
+
+    // The address of the next instruction
+    fn here(&self) -> u16 {
+        START + 2 * self.program.len() as u16
+    }
+
+    fn emit(&mut self, ops: &[u16]) -> &mut Self {
+        self.program.extend_from_slice(ops);
+        self
+    }
+
+    // Start a test by recording its number
+    fn test(&mut self, number: u8) -> &mut Self {
+        self.emit(&[0x6000 | TEST_REGISTER << 8 | number as u16])
+    }
+
+    // Fail the test unless VX holds the value
+    fn expect(&mut self, x: u16, value: u8) -> &mut Self {
+        self.emit(&[0x3000 | x << 8 | value as u16, 0x1000 | FAIL])
+    }
+
+    // Fail the test if the next instruction isn't skipped
+    fn expect_skip(&mut self, op: u16) -> &mut Self {
+        self.emit(&[op, 0x1000 | FAIL])
+    }
+
+    // Fail the test if the next instruction is skipped
+    fn expect_no_skip(&mut self, op: u16) -> &mut Self {
+        let after = self.here() + 6;
+        self.emit(&[op, 0x1000 | after, 0x1000 | FAIL])
+    }
+
+    fn run(&mut self, quirks: Quirks) {
+        self.emit(&[0x1000 | PASS]);
+        let mut cpu = cpu_with_quirks(&self.program, quirks);
+        for _ in 0..10_000 {
+            cpu.tick();
+            if cpu.get_pc() == FAIL || cpu.get_pc() == PASS {
+                break;
+            }
+        }
+        assert_eq!(
+            cpu.get_pc(),
+            PASS,
+            "test {} failed",
+            cpu.get_variable_registers()[TEST_REGISTER as usize]
+        );
+    }
+}
+
+#[test]
+fn opcode_test() {
+    let mut rom = SelfTest::new();
+
+    rom.test(1)
+        .emit(&[0x6A2A])
+        .expect_skip(0x3A2A)
+        .expect_no_skip(0x3A2B)
+        .expect_skip(0x4A2B)
+        .expect_no_skip(0x4A2A);
+
+    rom.test(2)
+        .emit(&[0x6A2A, 0x6B2A, 0x6C2B])
+        .expect_skip(0x5AB0)
+        .expect_no_skip(0x5AC0)
+        .expect_skip(0x9AC0)
+        .expect_no_skip(0x9AB0);
+
+    rom.test(3)
+        .emit(&[0x6AFF, 0x7A0A, 0x6F05, 0x7F00])
+        .expect(0xA, 0x09)
+        .expect(0xF, 0x05);
+
+    rom.test(4)
+        .emit(&[0x6A55, 0x6BF0, 0x8AB0])
+        .expect(0xA, 0xF0)
+        .emit(&[0x6A55, 0x8AB1])
+        .expect(0xA, 0xF5)
+        .emit(&[0x6A55, 0x8AB2])
+        .expect(0xA, 0x50)
+        .emit(&[0x6A55, 0x8AB3])
+        .expect(0xA, 0xA5);
+
+    rom.test(5)
+        .emit(&[0x6AC8, 0x6B64, 0x8AB4])
+        .expect(0xA, 0x2C)
+        .expect(0xF, 1)
+        .emit(&[0x6A0A, 0x8AB4])
+        .expect(0xA, 0x6E)
+        .expect(0xF, 0);
+
+    rom.test(6)
+        .emit(&[0x6A64, 0x6B0A, 0x8AB5])
+        .expect(0xA, 0x5A)
+        .expect(0xF, 1)
+        .emit(&[0x6A0A, 0x6B64, 0x8AB5])
+        .expect(0xA, 0xA6)
+        .expect(0xF, 0);
+
+    rom.test(7)
+        .emit(&[0x6A0A, 0x6B64, 0x8AB7])
+        .expect(0xA, 0x5A)
+        .expect(0xF, 1)
+        .emit(&[0x6A64, 0x6B0A, 0x8AB7])
+        .expect(0xA, 0xA6)
+        .expect(0xF, 0);
+
+    rom.test(8)
+        .emit(&[0x6A81, 0x8AB6])
+        .expect(0xA, 0x40)
+        .expect(0xF, 1)
+        .emit(&[0x6A81, 0x8ABE])
+        .expect(0xA, 0x02)
+        .expect(0xF, 1)
+        .emit(&[0x6A40, 0x8ABE])
+        .expect(0xA, 0x80)
+        .expect(0xF, 0);
+
+    // Store three registers, then load them back into others through I
+    rom.test(9)
+        .emit(&[0xA400, 0x6011, 0x6122, 0x6233, 0xF255])
+        .emit(&[0x6000, 0x6100, 0x6200, 0xA400, 0xF265])
+        .expect(0, 0x11)
+        .expect(1, 0x22)
+        .expect(2, 0x33);
+
+    rom.test(10)
+        .emit(&[0xA400, 0x6002, 0xF01E, 0xF065])
+        .expect(0, 0x33);
+
+    rom.test(11)
+        .emit(&[0xA400, 0x6AE5, 0xFA33, 0xF265])
+        .expect(0, 2)
+        .expect(1, 2)
+        .expect(2, 9);
+
+    // Call a subroutine, which sets V0 and returns to a jump over it
+    let subroutine = rom.here() + 10;
+    rom.test(12)
+        .emit(&[0x6000, 0x2000 | subroutine, 0x1000 | (subroutine + 4)])
+        .emit(&[0x1000 | FAIL, 0x6001, 0x00EE])
+        .expect(0, 1);
+
+    // The font's 5 starts with a full row
+    rom.test(13).emit(&[0x6005, 0xF029, 0xF065]).expect(0, 0xF0);
+
+    rom.run(Quirks::MODERN);
+}
+
+// The flags test checks the result and VF of each arithmetic instruction,
+// then does it all again with VF as the target, where the flag has to win
+#[test]
+fn flags_test() {
+    let mut rom = SelfTest::new();
+    // Each case is the instruction, VX, VY, the result and the flag
+    let cases: [(u16, u8, u8, u8, u8); 12] = [
+        (0x4, 0x10, 0x20, 0x30, 0),
+        (0x4, 0xFF, 0x02, 0x01, 1),
+        (0x5, 0x20, 0x10, 0x10, 1),
+        (0x5, 0x10, 0x20, 0xF0, 0),
+        (0x5, 0x10, 0x10, 0x00, 1),
+        (0x7, 0x10, 0x20, 0x10, 1),
+        (0x7, 0x20, 0x10, 0xF0, 0),
+        (0x7, 0x10, 0x10, 0x00, 1),
+        (0x6, 0x03, 0x00, 0x01, 1),
+        (0x6, 0x02, 0x00, 0x01, 0),
+        (0xE, 0x81, 0x00, 0x02, 1),
+        (0xE, 0x41, 0x00, 0x82, 0),
+    ];
+
+    for (number, &(op, x, y, result, flag)) in cases.iter().enumerate() {
+        rom.test(number as u8 + 1)
+            .emit(&[0x6000 | x as u16, 0x6100 | y as u16, 0x8010 | op])
+            .expect(0, result)
+            .expect(0xF, flag);
+    }
+
+    for (number, &(op, x, y, _, flag)) in cases.iter().enumerate() {
+        rom.test(number as u8 + 0x41)
+            .emit(&[0x6F00 | x as u16, 0x6100 | y as u16, 0x8F10 | op])
+            .expect(0xF, flag);
+    }
+
+    rom.run(Quirks::MODERN);
+}
+
+#[test]
+fn quirks_test() {
+    // The original interpreter: VF reset by logic, shifts of VY and I moved
+    // along by the memory instructions
+    let mut rom = SelfTest::new();
+    rom.test(1)
+        .emit(&[0x6F05, 0x6003, 0x6105, 0x8011])
+        .expect(0, 0x07)
+        .expect(0xF, 0);
+    rom.test(2)
+        .emit(&[0x6000, 0x6106, 0x8016])
+        .expect(0, 0x03)
+        .expect(0xF, 0);
+    rom.test(3)
+        .emit(&[0xA400, 0x6011, 0x6122, 0xF155, 0xF065, 0x6F00])
+        .expect(0, 0);
+    // Jump past two failures with V0 = 2, rather than with VX
+    let target = rom.here() + 12;
+    rom.test(4)
+        .emit(&[0x6002, 0x6A22, 0xB000 | (target - 2)])
+        .emit(&[0x1000 | FAIL, 0x1000 | FAIL, 0x6A33])
+        .expect(0xA, 0x33);
+    rom.run(Quirks::VIP);
+
+    // The modern defaults leave all of that alone
+    let mut rom = SelfTest::new();
+    rom.test(1)
+        .emit(&[0x6F05, 0x6003, 0x6105, 0x8011])
+        .expect(0xF, 5);
+    rom.test(2).emit(&[0x6000, 0x6106, 0x8016]).expect(0, 0);
+    rom.test(3)
+        .emit(&[0xA400, 0x6011, 0x6122, 0xF155, 0x6000, 0xF065])
+        .expect(0, 0x11);
+    rom.run(Quirks::MODERN);
+}
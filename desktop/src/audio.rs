@@ -0,0 +1,63 @@
+use frontend::Audio;
+
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::AudioSubsystem;
+
+pub const DEFAULT_TONE_HZ: f32 = 440.0;
+pub const DEFAULT_VOLUME: f32 = 0.25;
+
+struct SquareWave {
+    phase_inc: f32,
+    phase: f32,
+    volume: f32,
+}
+
+impl AudioCallback for SquareWave {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for x in out.iter_mut() {
+            *x = if self.phase <= 0.5 {
+                self.volume
+            } else {
+                -self.volume
+            };
+            self.phase = (self.phase + self.phase_inc) % 1.0;
+        }
+    }
+}
+
+pub struct SdlAudio {
+    device: AudioDevice<SquareWave>,
+}
+
+impl SdlAudio {
+    // A square wave at tone_hz, with volume from 0 (silent) to 1
+    pub fn new(
+        audio_subsystem: &AudioSubsystem,
+        tone_hz: f32,
+        volume: f32,
+    ) -> Result<Self, String> {
+        let desired_spec = AudioSpecDesired {
+            freq: Some(44100),
+            channels: Some(1),
+            samples: None,
+        };
+        let device = audio_subsystem.open_playback(None, &desired_spec, |spec| SquareWave {
+            phase_inc: tone_hz / spec.freq as f32,
+            phase: 0.0,
+            volume,
+        })?;
+        Ok(SdlAudio { device })
+    }
+}
+
+impl Audio for SdlAudio {
+    fn set_beeping(&mut self, beeping: bool) {
+        if beeping {
+            self.device.resume();
+        } else {
+            self.device.pause();
+        }
+    }
+}
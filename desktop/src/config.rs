@@ -0,0 +1,409 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use chip8::Quirks;
+use frontend::{
+    default_quirks, Bloom, Filters, Palette, Persistence, RomInfo, Screenshots,
+    DEFAULT_INSTRUCTIONS_PER_SECOND,
+};
+use serde::Deserialize;
+
+use crate::controller::ButtonMap;
+use crate::keymap::Keymap;
+use crate::video::DEFAULT_SCALE;
+
+const CONFIG_DIR: &str = "rusty-chip-8";
+const CONFIG_FILE: &str = "config.toml";
+const ROM_DATABASE_FILE: &str = "romdb.json";
+const RECENT_FILE: &str = "recent.txt";
+
+const DEFAULT_FILTER_SCALE: usize = 4;
+const DEFAULT_BLOOM_RADIUS: usize = 2;
+
+// A single key name or a list of them
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum Bindings {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Bindings {
+    pub fn names(&self) -> &[String] {
+        match self {
+            Bindings::One(name) => std::slice::from_ref(name),
+            Bindings::Many(names) => names,
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    keys: BTreeMap<String, Bindings>,
+    hotkeys: BTreeMap<String, Bindings>,
+    controller: ControllerConfig,
+    palette: Option<String>,
+    persistence: Option<String>,
+    quirks: Option<String>,
+    // Emulation speed in Chip-8 instructions per second
+    ips: Option<u32>,
+    // Window size on startup, as a multiple of the Chip-8 screen
+    scale: Option<u32>,
+    integer_scaling: Option<bool>,
+    fullscreen: bool,
+    filters: FilterConfig,
+    screenshot_dir: Option<PathBuf>,
+    // Screenshot size, as a multiple of the Chip-8 screen
+    screenshot_scale: Option<usize>,
+    // Where the ROM browser looks for games
+    rom_dir: Option<PathBuf>,
+    // Overrides for a single game, keyed by ROM file name
+    roms: BTreeMap<String, RomConfig>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RomConfig {
+    keys: BTreeMap<String, Bindings>,
+    hotkeys: BTreeMap<String, Bindings>,
+    controller: ControllerConfig,
+    palette: Option<String>,
+    persistence: Option<String>,
+    quirks: Option<String>,
+    ips: Option<u32>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct FilterConfig {
+    scale: Option<usize>,
+    grid: Option<f32>,
+    scanlines: Option<f32>,
+    bloom: Option<f32>,
+    bloom_radius: Option<usize>,
+    curvature: Option<f32>,
+}
+
+impl FilterConfig {
+    fn to_filters(&self) -> Result<Filters, String> {
+        let strengths = [
+            ("grid", self.grid),
+            ("scanlines", self.scanlines),
+            ("bloom", self.bloom),
+            ("curvature", self.curvature),
+        ];
+        for (name, strength) in strengths {
+            if let Some(strength) = strength.filter(|s| !(0.0..=1.0).contains(s)) {
+                return Err(format!("{name} must be between 0 and 1, got {strength}"));
+            }
+        }
+        if self.scale == Some(0) {
+            return Err("scale must be at least 1".to_string());
+        }
+
+        // Effects need room to work below the size of a Chip-8 pixel
+        let any_effect = strengths.iter().any(|(_, strength)| strength.is_some());
+        let default_scale = if any_effect { DEFAULT_FILTER_SCALE } else { 1 };
+        Ok(Filters {
+            scale: self.scale.unwrap_or(default_scale),
+            grid: self.grid,
+            scanlines: self.scanlines,
+            bloom: self.bloom.map(|strength| Bloom {
+                strength,
+                radius: self.bloom_radius.unwrap_or(DEFAULT_BLOOM_RADIUS),
+            }),
+            curvature: self.curvature,
+        })
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ControllerConfig {
+    buttons: BTreeMap<String, String>,
+    stick_threshold: Option<f32>,
+}
+
+#[derive(Debug)]
+pub struct ConfigError {
+    path: PathBuf,
+    message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+pub struct Config {
+    pub keymap: Keymap,
+    pub buttons: ButtonMap,
+    pub palette: Option<Palette>,
+    pub persistence: Persistence,
+    // Left as whatever the Cpu defaults to when not set
+    pub quirks: Option<Quirks>,
+    pub instructions_per_second: u32,
+    pub scale: u32,
+    pub integer_scaling: bool,
+    pub fullscreen: bool,
+    pub filters: Filters,
+    pub screenshots: Screenshots,
+    pub rom_dir: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            keymap: Keymap::default(),
+            buttons: ButtonMap::default(),
+            palette: None,
+            persistence: Persistence::default(),
+            quirks: None,
+            instructions_per_second: DEFAULT_INSTRUCTIONS_PER_SECOND,
+            scale: DEFAULT_SCALE,
+            integer_scaling: true,
+            fullscreen: false,
+            filters: Filters::default(),
+            screenshots: default_screenshots(),
+            rom_dir: None,
+        }
+    }
+}
+
+// Screenshots go in the user's pictures folder where there is one
+fn default_screenshots() -> Screenshots {
+    match dirs::picture_dir() {
+        Some(dir) => Screenshots {
+            dir: dir.join(CONFIG_DIR),
+            ..Screenshots::default()
+        },
+        None => Screenshots::default(),
+    }
+}
+
+impl Config {
+    // Where the config file lives when no other path is given
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join(CONFIG_DIR).join(CONFIG_FILE))
+    }
+
+    // User additions to the bundled ROM database, next to the config file
+    pub fn rom_database_path(config_path: &Path) -> PathBuf {
+        config_path.with_file_name(ROM_DATABASE_FILE)
+    }
+
+    // The recently played games, also next to the config file
+    pub fn recent_path(config_path: &Path) -> PathBuf {
+        config_path.with_file_name(RECENT_FILE)
+    }
+
+    // Load the global settings plus any overrides for the given ROM. What the
+    // ROM database knows about the game comes between the two, and the quirks
+    // the file extension suggests only count when none of them set any. No
+    // config file, or a missing one, is not an error, it just means all
+    // defaults.
+    pub fn load(
+        path: Option<&Path>,
+        rom_name: &str,
+        rom_info: Option<&RomInfo>,
+    ) -> Result<Self, ConfigError> {
+        let error = |message: String| ConfigError {
+            path: path.unwrap_or(Path::new(CONFIG_FILE)).to_path_buf(),
+            message,
+        };
+
+        let file: ConfigFile = match path.map(fs::read_to_string) {
+            Some(Ok(text)) => toml::from_str(&text).map_err(|err| error(err.to_string()))?,
+            Some(Err(err)) if err.kind() != io::ErrorKind::NotFound => {
+                return Err(error(err.to_string()))
+            }
+            _ => ConfigFile::default(),
+        };
+
+        if file.scale == Some(0) {
+            return Err(error("scale must be at least 1".to_string()));
+        }
+        if file.screenshot_scale == Some(0) {
+            return Err(error("screenshot_scale must be at least 1".to_string()));
+        }
+
+        let filters = file
+            .filters
+            .to_filters()
+            .map_err(|message| error(format!("in [filters]: {message}")))?;
+
+        let mut keymap = Keymap::default();
+        keymap.apply(&file.keys, &file.hotkeys).map_err(error)?;
+        let mut buttons = ButtonMap::default();
+        let controller = &file.controller;
+        buttons
+            .apply(&controller.buttons, controller.stick_threshold)
+            .map_err(|message| error(format!("in [controller]: {message}")))?;
+        let mut palette = parse_field("palette", &file.palette).map_err(error)?;
+        let mut persistence = parse_field("persistence", &file.persistence).map_err(error)?;
+        let mut quirks = parse_field("quirks", &file.quirks).map_err(error)?;
+        let mut ips = file.ips;
+
+        if let Some(info) = rom_info {
+            keymap.bind_directions(&info.keys);
+            buttons.bind_directions(&info.keys);
+            palette = info.palette.clone().or(palette);
+            quirks = info.quirks.or(quirks);
+            ips = info.instructions_per_second().or(ips);
+        }
+
+        let defaults = default_screenshots();
+        if let Some((name, rom)) = file.roms.get_key_value(rom_name) {
+            let in_rom = |message| error(format!("in [roms.\"{name}\"]: {message}"));
+            keymap.apply(&rom.keys, &rom.hotkeys).map_err(in_rom)?;
+            buttons
+                .apply(&rom.controller.buttons, rom.controller.stick_threshold)
+                .map_err(in_rom)?;
+            palette = parse_field("palette", &rom.palette)
+                .map_err(in_rom)?
+                .or(palette);
+            persistence = parse_field("persistence", &rom.persistence)
+                .map_err(in_rom)?
+                .or(persistence);
+            quirks = parse_field("quirks", &rom.quirks)
+                .map_err(in_rom)?
+                .or(quirks);
+            ips = rom.ips.or(ips);
+        }
+        // SUPER-CHIP and XO-CHIP ROMs are told apart by their extension
+        quirks = quirks.or_else(|| default_quirks(rom_name));
+
+        if ips == Some(0) {
+            return Err(error("ips must be at least 1".to_string()));
+        }
+
+        Ok(Config {
+            keymap,
+            buttons,
+            palette,
+            persistence: persistence.unwrap_or_default(),
+            quirks,
+            instructions_per_second: ips.unwrap_or(DEFAULT_INSTRUCTIONS_PER_SECOND),
+            scale: file.scale.unwrap_or(DEFAULT_SCALE),
+            integer_scaling: file.integer_scaling.unwrap_or(true),
+            fullscreen: file.fullscreen,
+            filters,
+            screenshots: Screenshots {
+                dir: file.screenshot_dir.unwrap_or(defaults.dir),
+                scale: file.screenshot_scale.unwrap_or(defaults.scale),
+            },
+            rom_dir: file.rom_dir,
+        })
+    }
+}
+
+// Parse an optional setting, naming it in any error
+fn parse_field<T: FromStr<Err = String>>(
+    name: &str,
+    value: &Option<String>,
+) -> Result<Option<T>, String> {
+    value
+        .as_deref()
+        .map(str::parse)
+        .transpose()
+        .map_err(|err| format!("{name}: {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::keymap::Target;
+    use sdl2::keyboard::Keycode;
+
+    fn load(toml: &str, rom_name: &str) -> Result<Config, ConfigError> {
+        let path = std::env::temp_dir().join(format!(
+            "chip8-config-{}-{}.toml",
+            std::process::id(),
+            rom_name
+        ));
+        fs::write(&path, toml).unwrap();
+        let config = Config::load(Some(&path), rom_name, None);
+        fs::remove_file(path).unwrap();
+        config
+    }
+
+    const CONFIG: &str = r#"
+        ips = 700
+
+        [keys]
+        4 = "Up"
+
+        [roms."pong.ch8"]
+        ips = 900
+
+        [roms."pong.ch8".keys]
+        4 = "Down"
+    "#;
+
+    #[test]
+    fn rom_sections_override_the_global_settings() {
+        let key = |config: &Config, keycode| config.keymap.lookup(Some(keycode), None);
+
+        let config = load(CONFIG, "pong.ch8").unwrap();
+        assert_eq!(config.instructions_per_second, 900);
+        assert_eq!(key(&config, Keycode::Down), Some(Target::Key(0x4)));
+        assert_eq!(key(&config, Keycode::Up), None);
+
+        let config = load(CONFIG, "tetris.ch8").unwrap();
+        assert_eq!(config.instructions_per_second, 700);
+        assert_eq!(key(&config, Keycode::Up), Some(Target::Key(0x4)));
+        assert_eq!(key(&config, Keycode::Down), None);
+    }
+
+    #[test]
+    fn errors_name_the_rom_section() {
+        let toml = r#"
+            [roms."pong.ch8".keys]
+            4 = "Q"
+
+            [roms."pong.ch8".hotkeys]
+            reset = "Q"
+        "#;
+        let err = load(toml, "pong.ch8").err().unwrap().to_string();
+        assert!(
+            err.ends_with(
+                "in [roms.\"pong.ch8\"]: key 'Q' is bound to both Chip-8 key 4 and hotkey 'reset'"
+            ),
+            "{err}"
+        );
+        assert!(load(toml, "tetris.ch8").is_ok());
+    }
+
+    #[test]
+    fn extensions_only_pick_quirks_when_nothing_else_does() {
+        let config = load("", "blinky.sc8").unwrap();
+        assert_eq!(config.quirks, Some(Quirks::SCHIP));
+
+        let toml = r#"
+            quirks = "vip"
+
+            [roms."tetris.sc8"]
+            quirks = "xochip"
+        "#;
+        let config = load(toml, "blinky.sc8").unwrap();
+        assert_eq!(config.quirks, Some(Quirks::VIP));
+        let config = load(toml, "tetris.sc8").unwrap();
+        assert_eq!(config.quirks, Some(Quirks::XOCHIP));
+
+        let info = RomInfo {
+            quirks: Some(Quirks::MODERN),
+            ..RomInfo::default()
+        };
+        let config = Config::load(None, "blinky.sc8", Some(&info)).unwrap();
+        assert_eq!(config.quirks, Some(Quirks::MODERN));
+    }
+}
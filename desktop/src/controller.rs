@@ -0,0 +1,259 @@
+use frontend::Action;
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use sdl2::controller::{Axis, Button, GameController};
+use sdl2::event::Event;
+use sdl2::GameControllerSubsystem;
+
+use crate::keymap::{parse_target, Target};
+
+// How far an analog stick has to be pushed before it counts as a D-pad press
+const DEFAULT_STICK_THRESHOLD: f32 = 0.5;
+
+// The D-pad and face buttons cover the usual 2/4/6/8 movement and 5 action keys
+const DEFAULT_BUTTONS: [(Button, Target); 10] = [
+    (Button::DPadUp, Target::Key(0x2)),
+    (Button::DPadDown, Target::Key(0x8)),
+    (Button::DPadLeft, Target::Key(0x4)),
+    (Button::DPadRight, Target::Key(0x6)),
+    (Button::A, Target::Key(0x5)),
+    (Button::B, Target::Key(0x6)),
+    (Button::X, Target::Key(0x4)),
+    (Button::Y, Target::Key(0x2)),
+    (Button::Start, Target::Action(Action::TogglePause)),
+    (Button::Back, Target::Action(Action::Reset)),
+];
+
+// The buttons for each direction or button named in the ROM database
+const DIRECTION_BUTTONS: [(&str, Button); 6] = [
+    ("up", Button::DPadUp),
+    ("down", Button::DPadDown),
+    ("left", Button::DPadLeft),
+    ("right", Button::DPadRight),
+    ("a", Button::A),
+    ("b", Button::B),
+];
+
+pub struct ButtonMap {
+    targets: HashMap<Button, Target>,
+    stick_threshold: f32,
+}
+
+impl Default for ButtonMap {
+    fn default() -> Self {
+        ButtonMap {
+            targets: DEFAULT_BUTTONS.into_iter().collect(),
+            stick_threshold: DEFAULT_STICK_THRESHOLD,
+        }
+    }
+}
+
+impl ButtonMap {
+    // Layer settings from a config file on top of the current ones. Buttons use
+    // SDL's names ("a", "dpup", "leftshoulder"...) and map to a Chip-8 key, a
+    // hotkey, or "none" to unbind them.
+    pub fn apply(
+        &mut self,
+        buttons: &BTreeMap<String, String>,
+        stick_threshold: Option<f32>,
+    ) -> Result<(), String> {
+        for (name, target) in buttons {
+            let button = Button::from_string(name)
+                .ok_or_else(|| format!("unknown controller button '{name}'"))?;
+            if target == "none" {
+                self.targets.remove(&button);
+            } else {
+                self.targets.insert(button, parse_target(target)?);
+            }
+        }
+
+        if let Some(threshold) = stick_threshold {
+            if !(threshold > 0.0 && threshold < 1.0) {
+                return Err(format!(
+                    "stick_threshold must be between 0 and 1, got {threshold}"
+                ));
+            }
+            self.stick_threshold = threshold;
+        }
+        Ok(())
+    }
+
+    // Bind the D-pad and face buttons to a game's keys, named as in the ROM
+    // database
+    pub fn bind_directions(&mut self, keys: &BTreeMap<String, usize>) {
+        for (name, button) in DIRECTION_BUTTONS {
+            if let Some(&key) = keys.get(name) {
+                self.targets.insert(button, Target::Key(key));
+            }
+        }
+    }
+}
+
+struct OpenController {
+    // Kept alive so SDL keeps sending events for it
+    _controller: GameController,
+    held: HashSet<Button>,
+    // The D-pad direction each left stick axis is pushed towards, if any
+    stick: [Option<Button>; 2],
+}
+
+// Tracks connected game controllers and turns their events into key presses
+pub struct Controllers {
+    subsystem: GameControllerSubsystem,
+    open: HashMap<u32, OpenController>,
+    buttons: ButtonMap,
+}
+
+impl Controllers {
+    pub fn new(subsystem: GameControllerSubsystem, buttons: ButtonMap) -> Self {
+        Controllers {
+            subsystem,
+            open: HashMap::new(),
+            buttons,
+        }
+    }
+
+    pub fn set_buttons(&mut self, buttons: ButtonMap) {
+        self.buttons = buttons;
+    }
+
+    // Returns every target that was pressed (true) or released (false)
+    pub fn handle(&mut self, event: &Event) -> Vec<(Target, bool)> {
+        let mut changes = Vec::new();
+        match *event {
+            // SDL also sends this for controllers already plugged in at startup,
+            // so it can come twice for the same one. The first stays open so
+            // the buttons it's holding aren't forgotten.
+            Event::ControllerDeviceAdded { which, .. } => {
+                if let Ok(controller) = self.subsystem.open(which) {
+                    if self.open.contains_key(&controller.instance_id()) {
+                        return changes;
+                    }
+                    self.open.insert(
+                        controller.instance_id(),
+                        OpenController {
+                            _controller: controller,
+                            held: HashSet::new(),
+                            stick: [None; 2],
+                        },
+                    );
+                }
+            }
+            Event::ControllerDeviceRemoved { which, .. } => {
+                // Let go of everything the controller was holding down
+                if let Some(removed) = self.open.remove(&which) {
+                    let held = removed.held.iter().chain(removed.stick.iter().flatten());
+                    for button in held {
+                        if let Some(&target) = self.buttons.targets.get(button) {
+                            changes.push((target, false));
+                        }
+                    }
+                }
+            }
+            Event::ControllerButtonDown { which, button, .. } => {
+                if let Some(controller) = self.open.get_mut(&which) {
+                    controller.held.insert(button);
+                    if let Some(&target) = self.buttons.targets.get(&button) {
+                        changes.push((target, true));
+                    }
+                }
+            }
+            Event::ControllerButtonUp { which, button, .. } => {
+                if let Some(controller) = self.open.get_mut(&which) {
+                    if controller.held.remove(&button) {
+                        if let Some(&target) = self.buttons.targets.get(&button) {
+                            changes.push((target, false));
+                        }
+                    }
+                }
+            }
+            Event::ControllerAxisMotion {
+                which, axis, value, ..
+            } => {
+                let Some(controller) = self.open.get_mut(&which) else {
+                    return changes;
+                };
+                let (idx, negative, positive) = match axis {
+                    Axis::LeftX => (0, Button::DPadLeft, Button::DPadRight),
+                    Axis::LeftY => (1, Button::DPadUp, Button::DPadDown),
+                    _ => return changes,
+                };
+
+                let position = value as f32 / i16::MAX as f32;
+                let direction = if position <= -self.buttons.stick_threshold {
+                    Some(negative)
+                } else if position >= self.buttons.stick_threshold {
+                    Some(positive)
+                } else {
+                    None
+                };
+
+                let previous = controller.stick[idx];
+                if direction != previous {
+                    controller.stick[idx] = direction;
+                    if let Some(&target) = previous.and_then(|b| self.buttons.targets.get(&b)) {
+                        changes.push((target, false));
+                    }
+                    if let Some(&target) = direction.and_then(|b| self.buttons.targets.get(&b)) {
+                        changes.push((target, true));
+                    }
+                }
+            }
+            _ => (),
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buttons(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|&(name, target)| (name.to_string(), target.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn config_rebinds_and_unbinds_buttons() {
+        let mut map = ButtonMap::default();
+        map.apply(
+            &buttons(&[("a", "F"), ("start", "none"), ("leftshoulder", "reset")]),
+            Some(0.25),
+        )
+        .unwrap();
+        assert_eq!(map.targets[&Button::A], Target::Key(0xF));
+        assert!(!map.targets.contains_key(&Button::Start));
+        assert_eq!(
+            map.targets[&Button::LeftShoulder],
+            Target::Action(Action::Reset)
+        );
+        assert_eq!(map.stick_threshold, 0.25);
+    }
+
+    #[test]
+    fn bad_settings_are_errors() {
+        let mut map = ButtonMap::default();
+        let err = map.apply(&buttons(&[("z", "1")]), None).unwrap_err();
+        assert_eq!(err, "unknown controller button 'z'");
+        assert!(map.apply(&buttons(&[("a", "G")]), None).is_err());
+        assert!(map.apply(&buttons(&[("a", "jump")]), None).is_err());
+        for threshold in [0.0, 1.0, f32::NAN] {
+            assert!(map.apply(&BTreeMap::new(), Some(threshold)).is_err());
+        }
+        assert_eq!(map.stick_threshold, DEFAULT_STICK_THRESHOLD);
+    }
+
+    #[test]
+    fn rom_database_keys_go_on_the_dpad_and_face_buttons() {
+        let mut map = ButtonMap::default();
+        let keys = [("up".to_string(), 0x5), ("a".to_string(), 0xA)];
+        map.bind_directions(&keys.into_iter().collect());
+        assert_eq!(map.targets[&Button::DPadUp], Target::Key(0x5));
+        assert_eq!(map.targets[&Button::A], Target::Key(0xA));
+        assert_eq!(map.targets[&Button::DPadDown], Target::Key(0x8));
+    }
+}
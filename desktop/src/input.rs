@@ -0,0 +1,124 @@
+use frontend::{Action, Input, InputEvent, Navigation};
+
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::EventPump;
+
+use std::path::PathBuf;
+
+use crate::controller::{ButtonMap, Controllers};
+use crate::keymap::{Keymap, Target};
+
+const NUM_KEYS: usize = 16;
+
+pub struct SdlInput {
+    event_pump: EventPump,
+    keymap: Keymap,
+    controllers: Controllers,
+    // How many keys and buttons are holding down each Chip-8 key, so it is
+    // only released once the last of them lets go
+    held: [u32; NUM_KEYS],
+}
+
+impl SdlInput {
+    pub fn new(event_pump: EventPump, keymap: Keymap, controllers: Controllers) -> Self {
+        SdlInput {
+            event_pump,
+            keymap,
+            controllers,
+            held: [0; NUM_KEYS],
+        }
+    }
+
+    // Switch to the bindings for another game
+    pub fn set_bindings(&mut self, keymap: Keymap, buttons: ButtonMap) {
+        self.keymap = keymap;
+        self.controllers.set_buttons(buttons);
+    }
+
+    fn change(&mut self, target: Target, pressed: bool, events: &mut Vec<InputEvent>) {
+        match target {
+            Target::Key(key) => {
+                let was_held = self.held[key] > 0;
+                if pressed {
+                    self.held[key] += 1;
+                } else {
+                    self.held[key] = self.held[key].saturating_sub(1);
+                }
+                let is_held = self.held[key] > 0;
+                if was_held != is_held {
+                    events.push(InputEvent::Key {
+                        key,
+                        pressed: is_held,
+                    });
+                }
+            }
+            Target::Action(action) => {
+                if pressed {
+                    events.push(InputEvent::Action(action));
+                } else {
+                    events.push(InputEvent::ActionReleased(action));
+                }
+            }
+        }
+    }
+}
+
+impl Input for SdlInput {
+    fn poll(&mut self) -> Vec<InputEvent> {
+        let mut events = Vec::new();
+        let sdl_events: Vec<Event> = self.event_pump.poll_iter().collect();
+        for evt in sdl_events {
+            match evt {
+                Event::Quit { .. } => events.push(InputEvent::Action(Action::Quit)),
+                Event::KeyDown {
+                    keycode,
+                    scancode,
+                    repeat,
+                    ..
+                } => {
+                    // Moving around the browser repeats while the key is held
+                    if let Some(navigation) = keycode.and_then(navigation) {
+                        events.push(InputEvent::Navigate(navigation));
+                    }
+                    if repeat {
+                        continue;
+                    }
+                    if let Some(target) = self.keymap.lookup(keycode, scancode) {
+                        self.change(target, true, &mut events);
+                    }
+                }
+                Event::DropFile { filename, .. } => {
+                    events.push(InputEvent::Open(PathBuf::from(filename)))
+                }
+                Event::KeyUp {
+                    keycode, scancode, ..
+                } => {
+                    if let Some(target) = self.keymap.lookup(keycode, scancode) {
+                        self.change(target, false, &mut events);
+                    }
+                }
+                _ => {
+                    for (target, pressed) in self.controllers.handle(&evt) {
+                        self.change(target, pressed, &mut events);
+                    }
+                }
+            }
+        }
+        events
+    }
+}
+
+// The keys for the ROM browser. They are only used while it is open, so they
+// can be bound to Chip-8 keys or hotkeys as well.
+fn navigation(keycode: Keycode) -> Option<Navigation> {
+    match keycode {
+        Keycode::Up => Some(Navigation::Up),
+        Keycode::Down => Some(Navigation::Down),
+        Keycode::PageUp => Some(Navigation::PageUp),
+        Keycode::PageDown => Some(Navigation::PageDown),
+        Keycode::Return | Keycode::KpEnter | Keycode::Right => Some(Navigation::Select),
+        Keycode::Left | Keycode::Backspace => Some(Navigation::Back),
+        _ => None,
+    }
+}
@@ -0,0 +1,303 @@
+use frontend::Action;
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use sdl2::keyboard::{Keycode, Scancode};
+
+use crate::config::Bindings;
+
+const SCANCODE_PREFIX: &str = "scancode:";
+
+// A physical key, either by the symbol it produces in the current keyboard
+// layout or by its position on the keyboard
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Keycode(Keycode),
+    Scancode(Scancode),
+}
+
+impl Binding {
+    // Key names are SDL's, e.g. "Q", "Left Shift" or "scancode:Q"
+    pub fn parse(name: &str) -> Result<Self, String> {
+        if let Some(scancode) = name.strip_prefix(SCANCODE_PREFIX) {
+            Scancode::from_name(scancode)
+                .map(Binding::Scancode)
+                .ok_or_else(|| format!("unknown scancode '{scancode}'"))
+        } else {
+            Keycode::from_name(name)
+                .map(Binding::Keycode)
+                .ok_or_else(|| format!("unknown key '{name}'"))
+        }
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Binding::Keycode(keycode) => write!(f, "'{}'", keycode.name()),
+            Binding::Scancode(scancode) => write!(f, "'{SCANCODE_PREFIX}{}'", scancode.name()),
+        }
+    }
+}
+
+// What a binding does when pressed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Target {
+    Key(usize),
+    Action(Action),
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Key(key) => write!(f, "Chip-8 key {key:X}"),
+            Target::Action(action) => write!(f, "hotkey '{action}'"),
+        }
+    }
+}
+
+/*
+    Keyboard                    Chip-8
+    +---+---+---+---+           +---+---+---+---+
+    | 1 | 2 | 3 | 4 |           | 1 | 2 | 3 | C |
+    +---+---+---+---+           +---+---+---+---+
+    | Q | W | E | R |           | 4 | 5 | 6 | D |
+    +---+---+---+---+     =>    +---+---+---+---+
+    | A | S | D | F |           | 7 | 8 | 9 | E |
+    +---+---+---+---+           +---+---+---+---+
+    | Z | X | C | V |           | A | 0 | B | F |
+    +---+---+---+---+           +---+---+---+---+
+*/
+
+const DEFAULT_KEYS: [(Keycode, usize); 16] = [
+    (Keycode::Num1, 0x1),
+    (Keycode::Num2, 0x2),
+    (Keycode::Num3, 0x3),
+    (Keycode::Num4, 0xC),
+    (Keycode::Q, 0x4),
+    (Keycode::W, 0x5),
+    (Keycode::E, 0x6),
+    (Keycode::R, 0xD),
+    (Keycode::A, 0x7),
+    (Keycode::S, 0x8),
+    (Keycode::D, 0x9),
+    (Keycode::F, 0xE),
+    (Keycode::Z, 0xA),
+    (Keycode::X, 0x0),
+    (Keycode::C, 0xB),
+    (Keycode::V, 0xF),
+];
+
+const DEFAULT_HOTKEYS: [(Keycode, Action); 21] = [
+    (Keycode::Escape, Action::Quit),
+    (Keycode::P, Action::TogglePause),
+    (Keycode::Backspace, Action::Reset),
+    (Keycode::Minus, Action::SpeedDown),
+    (Keycode::Equals, Action::SpeedUp),
+    (Keycode::Tab, Action::CyclePalette),
+    (Keycode::F10, Action::ToggleFilters),
+    (Keycode::F11, Action::ToggleFullscreen),
+    (Keycode::F12, Action::Screenshot),
+    (Keycode::F9, Action::ToggleRecording),
+    (Keycode::F7, Action::ToggleMovieRecording),
+    (Keycode::F8, Action::TakeOver),
+    (Keycode::Period, Action::FrameAdvance),
+    (Keycode::Space, Action::Turbo),
+    (Keycode::F1, Action::ToggleTurbo),
+    (Keycode::F2, Action::ToggleSlowMotion),
+    (Keycode::LeftBracket, Action::IpfDown),
+    (Keycode::RightBracket, Action::IpfUp),
+    (Keycode::F5, Action::SaveState),
+    (Keycode::F6, Action::LoadState),
+    (Keycode::F3, Action::ToggleBrowser),
+];
+
+// The arrow keys, for games whose ROM database entry says which Chip-8 keys
+// they use for directions
+const DIRECTION_KEYS: [(&str, Keycode); 4] = [
+    ("up", Keycode::Up),
+    ("down", Keycode::Down),
+    ("left", Keycode::Left),
+    ("right", Keycode::Right),
+];
+
+pub struct Keymap {
+    targets: HashMap<Binding, Target>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let keys = DEFAULT_KEYS
+            .iter()
+            .map(|&(keycode, key)| (Binding::Keycode(keycode), Target::Key(key)));
+        let hotkeys = DEFAULT_HOTKEYS
+            .iter()
+            .map(|&(keycode, action)| (Binding::Keycode(keycode), Target::Action(action)));
+        Keymap {
+            targets: keys.chain(hotkeys).collect(),
+        }
+    }
+}
+
+impl Keymap {
+    // Layer bindings from a config file on top of the current ones. Every key
+    // or hotkey mentioned replaces all of its previous bindings, and any key
+    // the layer binds is taken away from whatever it was bound to before.
+    pub fn apply(
+        &mut self,
+        keys: &BTreeMap<String, Bindings>,
+        hotkeys: &BTreeMap<String, Bindings>,
+    ) -> Result<(), String> {
+        let mut layer: BTreeMap<Target, Vec<Binding>> = BTreeMap::new();
+        for (name, bindings) in keys {
+            layer.insert(
+                Target::Key(parse_chip8_key(name)?),
+                parse_bindings(bindings)?,
+            );
+        }
+        for (name, bindings) in hotkeys {
+            let action = name.parse()?;
+            layer.insert(Target::Action(action), parse_bindings(bindings)?);
+        }
+
+        // Within one layer, a key can only do one thing
+        let mut claimed: HashMap<Binding, Target> = HashMap::new();
+        for (&target, bindings) in &layer {
+            for &binding in bindings {
+                if let Some(other) = claimed.insert(binding, target) {
+                    if other != target {
+                        return Err(format!(
+                            "key {binding} is bound to both {other} and {target}"
+                        ));
+                    }
+                }
+            }
+        }
+
+        self.targets.retain(|binding, target| {
+            !layer.contains_key(target) && !claimed.contains_key(binding)
+        });
+        self.targets.extend(claimed);
+        Ok(())
+    }
+
+    // Bind the arrow keys to a game's direction keys, named as in the ROM
+    // database. The keys stay bound to whatever else they were too.
+    pub fn bind_directions(&mut self, keys: &BTreeMap<String, usize>) {
+        for (name, keycode) in DIRECTION_KEYS {
+            if let Some(&key) = keys.get(name) {
+                self.targets
+                    .insert(Binding::Keycode(keycode), Target::Key(key));
+            }
+        }
+    }
+
+    pub fn lookup(&self, keycode: Option<Keycode>, scancode: Option<Scancode>) -> Option<Target> {
+        // Positional bindings are more specific, so they win over symbolic ones
+        scancode
+            .and_then(|scancode| self.targets.get(&Binding::Scancode(scancode)))
+            .or_else(|| keycode.and_then(|keycode| self.targets.get(&Binding::Keycode(keycode))))
+            .copied()
+    }
+}
+
+// Either a Chip-8 key's hex digit or a hotkey name
+pub fn parse_target(name: &str) -> Result<Target, String> {
+    if name.len() == 1 {
+        parse_chip8_key(name).map(Target::Key)
+    } else {
+        name.parse().map(Target::Action)
+    }
+}
+
+fn parse_chip8_key(name: &str) -> Result<usize, String> {
+    match usize::from_str_radix(name, 16) {
+        Ok(key) if name.len() == 1 => Ok(key),
+        _ => Err(format!(
+            "unknown Chip-8 key '{name}', expected a hex digit from 0 to F"
+        )),
+    }
+}
+
+fn parse_bindings(bindings: &Bindings) -> Result<Vec<Binding>, String> {
+    bindings
+        .names()
+        .iter()
+        .map(|name| Binding::parse(name))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bindings(pairs: &[(&str, &str)]) -> BTreeMap<String, Bindings> {
+        pairs
+            .iter()
+            .map(|&(name, key)| (name.to_string(), Bindings::One(key.to_string())))
+            .collect()
+    }
+
+    fn key(keymap: &Keymap, keycode: Keycode) -> Option<Target> {
+        keymap.lookup(Some(keycode), None)
+    }
+
+    #[test]
+    fn layers_replace_earlier_bindings() {
+        let mut keymap = Keymap::default();
+        assert_eq!(key(&keymap, Keycode::Q), Some(Target::Key(0x4)));
+
+        // Moving key 4 to Up frees Q, and taking P for key 5 frees W and
+        // takes P away from pausing
+        keymap
+            .apply(&bindings(&[("4", "Up"), ("5", "P")]), &BTreeMap::new())
+            .unwrap();
+        assert_eq!(key(&keymap, Keycode::Up), Some(Target::Key(0x4)));
+        assert_eq!(key(&keymap, Keycode::Q), None);
+        assert_eq!(key(&keymap, Keycode::W), None);
+        assert_eq!(key(&keymap, Keycode::P), Some(Target::Key(0x5)));
+
+        keymap
+            .apply(&BTreeMap::new(), &bindings(&[("pause", "scancode:Q")]))
+            .unwrap();
+        assert_eq!(
+            keymap.lookup(Some(Keycode::A), Some(Scancode::Q)),
+            Some(Target::Action(Action::TogglePause))
+        );
+    }
+
+    #[test]
+    fn one_key_cant_do_two_things() {
+        let mut keymap = Keymap::default();
+        let err = keymap
+            .apply(&bindings(&[("4", "Q")]), &bindings(&[("reset", "Q")]))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            "key 'Q' is bound to both Chip-8 key 4 and hotkey 'reset'"
+        );
+        // Nothing changes when a layer is turned down
+        assert_eq!(
+            key(&keymap, Keycode::Backspace),
+            Some(Target::Action(Action::Reset))
+        );
+    }
+
+    #[test]
+    fn bad_names_are_errors() {
+        let mut keymap = Keymap::default();
+        let err = keymap
+            .apply(&bindings(&[("G", "Q")]), &BTreeMap::new())
+            .unwrap_err();
+        assert!(err.starts_with("unknown Chip-8 key 'G'"), "{err}");
+        assert!(parse_chip8_key("10").is_err());
+        assert_eq!(parse_chip8_key("f"), Ok(0xF));
+
+        let err = keymap
+            .apply(&bindings(&[("4", "NotAKey")]), &BTreeMap::new())
+            .unwrap_err();
+        assert_eq!(err, "unknown key 'NotAKey'");
+        assert!(Binding::parse("scancode:Nope").is_err());
+    }
+}
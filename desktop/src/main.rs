@@ -1,141 +1,347 @@
+mod audio;
+mod config;
+mod controller;
+mod input;
+mod keymap;
+mod video;
+
 use chip8::*;
+use frontend::{
+    list_roms, select_rom, GameSettings, Library, LoadedRom, Machine, Movie, Palette, ReloadMode,
+    RomChoice, RomDb, RomInfo, Runner, Watch, FRAME_RATE,
+};
+
+use clap::Parser;
+
+use std::fmt::Display;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use audio::{SdlAudio, DEFAULT_TONE_HZ, DEFAULT_VOLUME};
+use config::{Config, ConfigError};
+use controller::Controllers;
+use input::SdlInput;
+use video::SdlVideo;
+
+// Options given here take over from the config file
+#[derive(Parser, Clone)]
+#[command(version, about = "A Chip-8 emulator")]
+struct Cli {
+    #[arg(
+        help = "The ROM to run, an Octo cartridge .gif or .8o source file, or a .zip or \
+                directory of ROMs"
+    )]
+    rom: PathBuf,
+
+    #[arg(long, value_name = "ROM")]
+    #[arg(help = "Which ROM in the .zip or directory to run, by name or number")]
+    select: Option<String>,
+
+    #[arg(long, value_name = "FILE")]
+    #[arg(help = "Config file to use instead of the one in the user's config folder")]
+    config: Option<PathBuf>,
+
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    #[arg(help = "Window size as a multiple of the 64x32 screen")]
+    scale: Option<u32>,
+
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    #[arg(
+        conflicts_with = "ips",
+        help = "Instructions run per frame, at 60 frames a second"
+    )]
+    ipf: Option<u32>,
+
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    #[arg(help = "Instructions run per second [default: 600]")]
+    ips: Option<u32>,
+
+    #[arg(long, value_name = "QUIRKS")]
+    #[arg(
+        help = "Interpreter quirks: modern, vip, schip, xochip, or a comma separated \
+                  list of shift, memory, jump, clip, vf_reset and display_wait"
+    )]
+    quirks: Option<Quirks>,
+
+    #[arg(long, value_name = "PALETTE")]
+    #[arg(
+        help = "Display colors: classic, green, amber, lcd, octo, or two to four \
+                  comma separated colors"
+    )]
+    palette: Option<Palette>,
+
+    #[arg(long, value_name = "LEVEL", default_value_t = DEFAULT_VOLUME)]
+    #[arg(value_parser = parse_volume, help = "Beep volume from 0 to 1")]
+    volume: f32,
+
+    #[arg(long, value_name = "HZ", default_value_t = DEFAULT_TONE_HZ)]
+    #[arg(value_parser = parse_tone, help = "Beep pitch")]
+    tone: f32,
+
+    #[arg(long, help = "Turn the beep off")]
+    mute: bool,
+
+    #[arg(long, help = "Start paused")]
+    paused: bool,
 
-use std::env;
-use std::fs::File;
-use std::io::Read;
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["movie", "record_movie"])]
+    #[arg(help = "Start from a save state made with the save_state hotkey")]
+    load_state: Option<PathBuf>,
 
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
-use sdl2::rect::Rect;
-use sdl2::render::Canvas;
-use sdl2::video::Window;
+    #[arg(long, value_name = "FILE", conflicts_with = "record_movie")]
+    #[arg(help = "Replay an input movie")]
+    movie: Option<PathBuf>,
 
-const SCALE: u32 = 15;
-const WINDOW_WIDTH: u32 = (SCREEN_WIDTH as u32) * SCALE;
-const WINDOW_HEIGHT: u32 = (SCREEN_HEIGHT as u32) * SCALE;
-const TICKS_PER_FRAME: usize = 10;
+    #[arg(long, value_name = "FILE")]
+    #[arg(help = "Record an input movie from the start, saved to FILE on quit")]
+    record_movie: Option<PathBuf>,
+
+    #[arg(long, help = "Start in fullscreen")]
+    fullscreen: bool,
+
+    #[arg(long, value_name = "MODE", num_args = 0..=1, require_equals = true)]
+    #[arg(default_missing_value = "reset")]
+    #[arg(
+        help = "Reload the ROM whenever its file changes, starting it over, or with \
+                --watch=patch writing it over the running game and keeping registers and display"
+    )]
+    watch: Option<ReloadMode>,
+}
+
+fn parse_volume(value: &str) -> Result<f32, String> {
+    match value.parse() {
+        Ok(volume) if (0.0..=1.0).contains(&volume) => Ok(volume),
+        _ => Err("expected a number from 0 to 1".to_string()),
+    }
+}
+
+fn parse_tone(value: &str) -> Result<f32, String> {
+    match value.parse() {
+        Ok(hz) if hz > 0.0 && hz <= 20_000.0 => Ok(hz),
+        _ => Err("expected a frequency from 1 to 20000".to_string()),
+    }
+}
+
+// Report a problem with the files given and give up
+fn fail(path: &Path, err: impl Display) -> ! {
+    eprintln!("{}: {err}", path.display());
+    process::exit(1);
+}
+
+// Ask on the terminal which of several ROMs to run
+fn choose_rom(names: &[String]) -> &str {
+    for (number, name) in names.iter().enumerate() {
+        println!("{:>3}: {name}", number + 1);
+    }
+    loop {
+        print!("ROM to run: ");
+        io::stdout().flush().unwrap();
+        let mut line = String::new();
+        if io::stdin().read_line(&mut line).unwrap_or(0) == 0 {
+            process::exit(1);
+        }
+        match select_rom(names, line.trim()) {
+            Ok(name) => return name,
+            Err(err) => println!("{err}"),
+        }
+    }
+}
+
+// Load the ROM given, or one from an archive or directory of them
+fn open_rom(path: &Path, selection: Option<&str>) -> (LoadedRom, RomChoice) {
+    let names = match list_roms(path) {
+        Ok(Some(names)) => names,
+        Ok(None) => {
+            let choice = RomChoice::file(path);
+            return (choice.load().unwrap_or_else(|err| fail(path, err)), choice);
+        }
+        Err(err) => fail(path, err),
+    };
+    let name = match (selection, names.as_slice()) {
+        (_, []) => fail(path, "no ROMs found"),
+        (Some(selection), _) => select_rom(&names, selection).unwrap_or_else(|err| fail(path, err)),
+        (None, [name]) => name,
+        (None, _) => choose_rom(&names),
+    };
+    let choice = RomChoice {
+        path: path.to_path_buf(),
+        name: Some(name.to_string()),
+    };
+    (choice.load().unwrap_or_else(|err| fail(path, err)), choice)
+}
+
+// The settings for a game: the config file's, with the ROM database's and
+// the command line's on top. Games switched to while running get theirs the
+// same way.
+fn game_config(
+    cli: &Cli,
+    config_path: Option<&Path>,
+    rom_name: &str,
+    rom_info: Option<&RomInfo>,
+) -> Result<Config, ConfigError> {
+    let mut config = Config::load(config_path, rom_name, rom_info)?;
+    if let Some(scale) = cli.scale {
+        config.scale = scale;
+    }
+    if let Some(ipf) = cli.ipf {
+        config.instructions_per_second = ipf.saturating_mul(FRAME_RATE as u32);
+    }
+    if let Some(ips) = cli.ips {
+        config.instructions_per_second = ips;
+    }
+    if cli.palette.is_some() {
+        config.palette = cli.palette.clone();
+    }
+    if cli.quirks.is_some() {
+        config.quirks = cli.quirks;
+    }
+    config.fullscreen |= cli.fullscreen;
+    Ok(config)
+}
 
 fn main() {
-    let args: Vec<_> = env::args().collect();
-    if args.len() != 2 {
-        println!("Usage: cargo run path/to/game");
-        return;
+    let cli = Cli::parse();
+    let rom_path = cli.rom.as_path();
+    let movie: Option<Movie> = cli.movie.as_deref().map(|path| {
+        let text = fs::read_to_string(path).unwrap_or_else(|err| fail(path, err));
+        text.parse()
+            .unwrap_or_else(|err| fail(path, format!("invalid movie: {err}")))
+    });
+    let state = cli
+        .load_state
+        .as_deref()
+        .map(|path| fs::read(path).unwrap_or_else(|err| fail(path, err)));
+
+    // A config file that was asked for has to be there
+    let config_path = match &cli.config {
+        Some(path) if !path.is_file() => fail(path, "config file not found"),
+        Some(path) => Some(path.clone()),
+        None => Config::default_path(),
+    };
+
+    let (rom, choice) = open_rom(rom_path, cli.select.as_deref());
+    let mut rom_db = RomDb::bundled();
+    if let Some(path) = config_path.as_deref().map(Config::rom_database_path) {
+        match fs::read_to_string(&path) {
+            Ok(json) => rom_db
+                .merge(&json)
+                .unwrap_or_else(|err| fail(&path, format!("invalid ROM database: {err}"))),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => fail(&path, err),
+        }
+    }
+    // Settings that came with the ROM win over the database's
+    let rom_info = rom
+        .info
+        .clone()
+        .or_else(|| rom_db.lookup(&rom.data).cloned());
+
+    let config = game_config(&cli, config_path.as_deref(), &rom.name, rom_info.as_ref())
+        .unwrap_or_else(|err| {
+            eprintln!("Invalid config file {err}");
+            process::exit(1);
+        });
+
+    let mut machine = Machine::new(rom.data).unwrap_or_else(|err| fail(rom_path, err));
+    machine.set_instructions_per_second(config.instructions_per_second);
+    if let Some(name) = Path::new(&rom.name)
+        .file_stem()
+        .and_then(|name| name.to_str())
+    {
+        machine.set_name(name);
+    }
+    if let Some(quirks) = config.quirks {
+        machine.cpu_mut().set_quirks(quirks);
     }
 
     // Setup SDL
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
-    let window = video_subsystem
-        .window("Chip-8 Emulator", WINDOW_WIDTH, WINDOW_HEIGHT)
-        .position_centered()
-        .opengl()
-        .build()
-        .unwrap();
+    let window_width = SCREEN_WIDTH as u32 * config.scale;
+    let window_height = SCREEN_HEIGHT as u32 * config.scale;
+    let mut window_builder = video_subsystem.window("Chip-8 Emulator", window_width, window_height);
+    window_builder.position_centered().resizable().opengl();
+    if config.fullscreen {
+        window_builder.fullscreen_desktop();
+    }
+    let window = window_builder.build().unwrap();
 
     let mut canvas = window.into_canvas().present_vsync().build().unwrap();
     canvas.clear();
     canvas.present();
+    let texture_creator = canvas.texture_creator();
 
-    let mut event_pump = sdl_context.event_pump().unwrap();
-
-    let mut chip8 = Cpu::setup_cpu();
-
-    let mut rom = File::open(&args[1]).expect("Unable to open file");
-    let mut buffer = Vec::new();
-
-    rom.read_to_end(&mut buffer).unwrap();
-    chip8.load_rom(&buffer);
-
-    'gameloop: loop {
-        for evt in event_pump.poll_iter() {
-            match evt {
-                Event::Quit { .. }
-                | Event::KeyDown {
-                    keycode: Some(Keycode::Escape),
-                    ..
-                } => {
-                    break 'gameloop;
-                }
-                Event::KeyDown {
-                    keycode: Some(key), ..
-                } => {
-                    if let Some(k) = key2btn(key) {
-                        chip8.keypress(k, true);
-                    }
-                }
-                Event::KeyUp {
-                    keycode: Some(key), ..
-                } => {
-                    if let Some(k) = key2btn(key) {
-                        chip8.keypress(k, false);
-                    }
-                }
-                _ => (),
-            }
-        }
+    let audio_subsystem = sdl_context.audio().unwrap();
+    let controller_subsystem = sdl_context.game_controller().unwrap();
+    let event_pump = sdl_context.event_pump().unwrap();
 
-        for _ in 0..TICKS_PER_FRAME {
-            chip8.tick();
-        }
-        chip8.tick_timers();
-        draw_screen(&chip8, &mut canvas);
+    let mut runner = Runner::new(
+        machine,
+        SdlVideo::new(canvas, &texture_creator, config.integer_scaling),
+        SdlAudio::new(
+            &audio_subsystem,
+            cli.tone,
+            if cli.mute { 0.0 } else { cli.volume },
+        )
+        .unwrap(),
+        SdlInput::new(
+            event_pump,
+            config.keymap,
+            Controllers::new(controller_subsystem, config.buttons),
+        ),
+    );
+    if let Some(palette) = config.palette {
+        runner.set_palette(palette);
+    }
+    let title = rom_info.map(|info| info.title).unwrap_or_default();
+    if !title.is_empty() {
+        runner.set_game_title(&title);
     }
-}
-
-fn draw_screen(emu: &Cpu, canvas: &mut Canvas<Window>) {
-    // Clear canvas as black
-    canvas.set_draw_color(Color::RGB(0, 0, 0));
-    canvas.clear();
 
-    let screen_buf = emu.get_display();
-    // Now set draw color to white, iterate through each point and see if it should be drawn
-    canvas.set_draw_color(Color::RGB(255, 255, 255));
-    for (i, pixel) in screen_buf.iter().enumerate() {
-        if *pixel {
-            // Convert our 1D array's index into a 2D (x,y) position
-            let x = (i % SCREEN_WIDTH) as u32;
-            let y = (i / SCREEN_WIDTH) as u32;
-
-            // Draw a rectangle at (x,y), scaled up by our SCALE value
-            let rect = Rect::new((x * SCALE) as i32, (y * SCALE) as i32, SCALE, SCALE);
-            canvas.fill_rect(rect).unwrap();
+    let mut library = Library {
+        rom_dir: config.rom_dir,
+        db: rom_db,
+        ..Library::default()
+    };
+    if let Some(path) = config_path.as_deref().map(Config::recent_path) {
+        if let Err(err) = library.load_recent(&path) {
+            eprintln!("Ignoring recent games in {}: {err}", path.display());
         }
     }
-    canvas.present();
-}
-
-/*
-    Keyboard                    Chip-8
-    +---+---+---+---+           +---+---+---+---+
-    | 1 | 2 | 3 | 4 |           | 1 | 2 | 3 | C |
-    +---+---+---+---+           +---+---+---+---+
-    | Q | W | E | R |           | 4 | 5 | 6 | D |
-    +---+---+---+---+     =>    +---+---+---+---+
-    | A | S | D | F |           | 7 | 8 | 9 | E |
-    +---+---+---+---+           +---+---+---+---+
-    | Z | X | C | V |           | A | 0 | B | F |
-    +---+---+---+---+           +---+---+---+---+
-*/
-
-fn key2btn(key: Keycode) -> Option<usize> {
-    match key {
-        Keycode::Num1 => Some(0x1),
-        Keycode::Num2 => Some(0x2),
-        Keycode::Num3 => Some(0x3),
-        Keycode::Num4 => Some(0xC),
-        Keycode::Q => Some(0x4),
-        Keycode::W => Some(0x5),
-        Keycode::E => Some(0x6),
-        Keycode::R => Some(0xD),
-        Keycode::A => Some(0x7),
-        Keycode::S => Some(0x8),
-        Keycode::D => Some(0x9),
-        Keycode::F => Some(0xE),
-        Keycode::Z => Some(0xA),
-        Keycode::X => Some(0x0),
-        Keycode::C => Some(0xB),
-        Keycode::V => Some(0xF),
-        _ => None,
+    library.remember(&choice, &title);
+    runner.set_library(library);
+    let (setup_cli, setup_path) = (cli.clone(), config_path.clone());
+    runner.set_game_setup(Box::new(move |name, info, input: &mut SdlInput| {
+        let config = game_config(&setup_cli, setup_path.as_deref(), name, info)
+            .map_err(|err| format!("invalid config file {err}"))?;
+        input.set_bindings(config.keymap, config.buttons);
+        Ok(GameSettings {
+            quirks: config.quirks.unwrap_or_default(),
+            instructions_per_second: config.instructions_per_second,
+            palette: config.palette,
+            persistence: Some(config.persistence),
+        })
+    }));
+    runner.set_persistence(config.persistence);
+    runner.set_filters(config.filters);
+    runner.set_screenshots(config.screenshots);
+    if let Some(movie) = movie {
+        runner.play_movie(movie);
+    }
+    if let Some(path) = &cli.record_movie {
+        runner.set_movie_path(path);
+        runner.record_movie();
+    }
+    if let (Some(state), Some(path)) = (state, &cli.load_state) {
+        runner
+            .load_state(&state)
+            .unwrap_or_else(|err| fail(path, format!("invalid save state: {err}")));
+    }
+    if let Some(mode) = cli.watch {
+        runner.set_watch(Watch::new(choice, mode));
     }
+    runner.set_paused(cli.paused);
+    runner.run();
 }
@@ -0,0 +1,187 @@
+use frontend::{Framebuffer, Machine, Rgb, Video};
+
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, Texture, TextureCreator};
+use sdl2::video::{FullscreenType, Window, WindowContext};
+
+// Window size on startup, as a multiple of the Chip-8 screen
+pub const DEFAULT_SCALE: u32 = 15;
+
+const BYTES_PER_PIXEL: usize = 3;
+
+// Uploads each frame to a streaming texture and lets SDL scale it to fit the
+// window, keeping the aspect ratio and filling the rest with the background
+pub struct SdlVideo<'a> {
+    canvas: Canvas<Window>,
+    texture_creator: &'a TextureCreator<WindowContext>,
+    texture: Option<Texture<'a>>,
+    // Only scale by whole multiples, so every Chip-8 pixel is the same size
+    integer_scaling: bool,
+    pixel_data: Vec<u8>,
+}
+
+impl<'a> SdlVideo<'a> {
+    pub fn new(
+        canvas: Canvas<Window>,
+        texture_creator: &'a TextureCreator<WindowContext>,
+        integer_scaling: bool,
+    ) -> Self {
+        SdlVideo {
+            canvas,
+            texture_creator,
+            texture: None,
+            integer_scaling,
+            pixel_data: Vec::new(),
+        }
+    }
+
+    // Make sure the texture matches the frame's size, recreating it if needed
+    fn texture_for(&mut self, width: u32, height: u32) -> &mut Texture<'a> {
+        let matches = self.texture.as_ref().is_some_and(|texture| {
+            let query = texture.query();
+            query.width == width && query.height == height
+        });
+        if !matches {
+            let texture = self
+                .texture_creator
+                .create_texture_streaming(PixelFormatEnum::RGB24, width, height)
+                .unwrap();
+            self.texture = Some(texture);
+        }
+        self.texture.as_mut().unwrap()
+    }
+}
+
+impl Video for SdlVideo<'_> {
+    fn draw(&mut self, frame: &Framebuffer, _machine: &Machine) {
+        self.pixel_data.clear();
+        self.pixel_data
+            .extend(frame.pixels.iter().flat_map(|rgb| [rgb.r, rgb.g, rgb.b]));
+
+        let (width, height) = (frame.width as u32, frame.height as u32);
+        let pixel_data = std::mem::take(&mut self.pixel_data);
+        self.texture_for(width, height)
+            .update(None, &pixel_data, frame.width * BYTES_PER_PIXEL)
+            .unwrap();
+        self.pixel_data = pixel_data;
+
+        let (out_width, out_height) = self.canvas.output_size().unwrap();
+        let dest = fit_rect(
+            (width, height),
+            (out_width, out_height),
+            self.integer_scaling,
+        );
+
+        // Clear the letterbox bars with the background color
+        self.canvas.set_draw_color(sdl_color(frame.background));
+        self.canvas.clear();
+        self.canvas
+            .copy(self.texture.as_ref().unwrap(), None, dest)
+            .unwrap();
+        self.canvas.present();
+    }
+
+    fn set_title(&mut self, title: &str) {
+        // Titles are built by us and never contain a nul byte
+        self.canvas.window_mut().set_title(title).unwrap();
+    }
+
+    fn toggle_fullscreen(&mut self) {
+        let window = self.canvas.window_mut();
+        let fullscreen = match window.fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            _ => FullscreenType::Off,
+        };
+        if let Err(err) = window.set_fullscreen(fullscreen) {
+            eprintln!("Unable to toggle fullscreen: {err}");
+        }
+    }
+}
+
+fn sdl_color(rgb: Rgb) -> Color {
+    Color::RGB(rgb.r, rgb.g, rgb.b)
+}
+
+// The largest rectangle with the image's aspect ratio that fits in the output,
+// centered. Integer scaling falls back to fractional if the window is too small.
+fn fit_rect(image: (u32, u32), output: (u32, u32), integer_scaling: bool) -> Rect {
+    let scale_x = output.0 as f32 / image.0 as f32;
+    let scale_y = output.1 as f32 / image.1 as f32;
+    let mut scale = scale_x.min(scale_y);
+    if integer_scaling && scale >= 1.0 {
+        scale = scale.floor();
+    }
+
+    let width = ((image.0 as f32 * scale) as u32).max(1);
+    let height = ((image.1 as f32 * scale) as u32).max(1);
+    let x = (output.0.saturating_sub(width) / 2) as i32;
+    let y = (output.1.saturating_sub(height) / 2) as i32;
+    Rect::new(x, y, width, height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCREEN: (u32, u32) = (64, 32);
+
+    #[test]
+    fn exact_fits_fill_the_output() {
+        for integer_scaling in [false, true] {
+            assert_eq!(
+                fit_rect(SCREEN, (640, 320), integer_scaling),
+                Rect::new(0, 0, 640, 320)
+            );
+        }
+    }
+
+    #[test]
+    fn spare_space_is_split_around_the_image() {
+        // Letterboxed in a window that is too tall
+        assert_eq!(
+            fit_rect(SCREEN, (640, 480), false),
+            Rect::new(0, 80, 640, 320)
+        );
+        // Pillarboxed in one that is too wide
+        assert_eq!(
+            fit_rect(SCREEN, (1000, 320), false),
+            Rect::new(180, 0, 640, 320)
+        );
+    }
+
+    #[test]
+    fn integer_scaling_rounds_down() {
+        assert_eq!(
+            fit_rect(SCREEN, (700, 400), false),
+            Rect::new(0, 25, 700, 350)
+        );
+        assert_eq!(
+            fit_rect(SCREEN, (700, 400), true),
+            Rect::new(30, 40, 640, 320)
+        );
+    }
+
+    #[test]
+    fn windows_smaller_than_the_screen_scale_down() {
+        for integer_scaling in [false, true] {
+            assert_eq!(
+                fit_rect(SCREEN, (48, 48), integer_scaling),
+                Rect::new(0, 12, 48, 24)
+            );
+        }
+    }
+
+    #[test]
+    fn odd_outputs_are_centered() {
+        assert_eq!(fit_rect(SCREEN, (129, 65), true), Rect::new(0, 0, 128, 64));
+        assert_eq!(
+            fit_rect(SCREEN, (641, 401), true),
+            Rect::new(0, 40, 640, 320)
+        );
+        assert_eq!(
+            fit_rect(SCREEN, (641, 401), false),
+            Rect::new(0, 40, 641, 320)
+        );
+    }
+}
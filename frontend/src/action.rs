@@ -0,0 +1,100 @@
+use std::fmt;
+use std::str::FromStr;
+
+// Emulator hotkeys, as opposed to the 16 Chip-8 keys
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Action {
+    Quit,
+    Reset,
+    TogglePause,
+    SpeedUp,
+    SpeedDown,
+    CyclePalette,
+    ToggleFullscreen,
+    ToggleFilters,
+    Screenshot,
+    ToggleRecording,
+    ToggleMovieRecording,
+    TakeOver,
+    FrameAdvance,
+    // Fast forward for as long as it is held
+    Turbo,
+    ToggleTurbo,
+    ToggleSlowMotion,
+    IpfUp,
+    IpfDown,
+    SaveState,
+    LoadState,
+    // Open or close the ROM browser
+    ToggleBrowser,
+}
+
+impl Action {
+    pub const ALL: [Action; 21] = [
+        Action::Quit,
+        Action::Reset,
+        Action::TogglePause,
+        Action::SpeedUp,
+        Action::SpeedDown,
+        Action::CyclePalette,
+        Action::ToggleFullscreen,
+        Action::ToggleFilters,
+        Action::Screenshot,
+        Action::ToggleRecording,
+        Action::ToggleMovieRecording,
+        Action::TakeOver,
+        Action::FrameAdvance,
+        Action::Turbo,
+        Action::ToggleTurbo,
+        Action::ToggleSlowMotion,
+        Action::IpfUp,
+        Action::IpfDown,
+        Action::SaveState,
+        Action::LoadState,
+        Action::ToggleBrowser,
+    ];
+
+    // Name used for the action in config files
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::Reset => "reset",
+            Action::TogglePause => "pause",
+            Action::SpeedUp => "speed_up",
+            Action::SpeedDown => "speed_down",
+            Action::CyclePalette => "cycle_palette",
+            Action::ToggleFullscreen => "fullscreen",
+            Action::ToggleFilters => "filters",
+            Action::Screenshot => "screenshot",
+            Action::ToggleRecording => "record",
+            Action::ToggleMovieRecording => "record_movie",
+            Action::TakeOver => "take_over",
+            Action::FrameAdvance => "frame_advance",
+            Action::Turbo => "turbo",
+            Action::ToggleTurbo => "toggle_turbo",
+            Action::ToggleSlowMotion => "slow_motion",
+            Action::IpfUp => "ipf_up",
+            Action::IpfDown => "ipf_down",
+            Action::SaveState => "save_state",
+            Action::LoadState => "load_state",
+            Action::ToggleBrowser => "browser",
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Action::ALL
+            .into_iter()
+            .find(|action| action.name() == s)
+            .ok_or_else(|| format!("unknown hotkey action '{s}'"))
+    }
+}
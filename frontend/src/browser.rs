@@ -0,0 +1,394 @@
+use crate::font::{draw_text, fill_rect, CHAR_WIDTH, LINE_HEIGHT};
+use crate::loader::{file_name, has_extension, is_rom};
+use crate::{list_roms, load_rom_from, Framebuffer, Library, Palette, RomChoice};
+
+use std::cmp::Ordering;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// The menu is drawn at its own resolution, three times the Chip-8 screen
+pub const MENU_WIDTH: usize = 192;
+pub const MENU_HEIGHT: usize = 96;
+
+const COLUMNS: usize = MENU_WIDTH / CHAR_WIDTH;
+// One line for the title, the rest for the list
+const ROWS: usize = MENU_HEIGHT / LINE_HEIGHT - 1;
+
+// Moving around the browser, independent of which keys do it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Navigation {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Select,
+    Back,
+}
+
+enum Item {
+    // A section title, skipped over when moving
+    Heading,
+    // A directory or zip archive to look in, None being the top of the browser
+    Folder(Option<PathBuf>),
+    Rom(RomChoice),
+}
+
+struct Entry {
+    label: String,
+    item: Item,
+}
+
+// The in-window menu for picking a game. The top shows the recently played
+// games and the ROM directory, and folders and zip archives can be opened to
+// look inside.
+pub struct Browser {
+    location: Option<PathBuf>,
+    title: String,
+    entries: Vec<Entry>,
+    selected: usize,
+    // The first entry shown
+    scroll: usize,
+}
+
+impl Browser {
+    // Show the given directory or zip archive, or the top of the browser
+    pub fn new(library: &Library, location: Option<&Path>) -> Self {
+        let mut entries = Vec::new();
+        let title = match location {
+            None => {
+                if !library.recent.roms.is_empty() {
+                    entries.push(heading("Recently played"));
+                }
+                for recent in &library.recent.roms {
+                    let label = match (&recent.title, &recent.rom.name) {
+                        (title, _) if !title.is_empty() => title.clone(),
+                        (_, Some(name)) => file_name(Path::new(name)),
+                        (_, None) => file_name(&recent.rom.path),
+                    };
+                    entries.push(Entry {
+                        label,
+                        item: Item::Rom(recent.rom.clone()),
+                    });
+                }
+                if let Some(dir) = &library.rom_dir {
+                    entries.push(heading(&dir.display().to_string()));
+                    entries.extend(list_folder(library, dir));
+                }
+                "Load a ROM".to_string()
+            }
+            Some(path) => {
+                // The ROM directory goes back up to the top of the browser
+                let parent = match path.parent() {
+                    Some(parent) if library.rom_dir.as_deref() != Some(path) => {
+                        Some(parent.to_path_buf())
+                    }
+                    _ => None,
+                };
+                entries.push(Entry {
+                    label: "..".to_string(),
+                    item: Item::Folder(parent),
+                });
+                entries.extend(list_folder(library, path));
+                path.display().to_string()
+            }
+        };
+
+        let mut browser = Browser {
+            location: location.map(Path::to_path_buf),
+            title,
+            entries,
+            selected: 0,
+            scroll: 0,
+        };
+        let first = browser.selectable().next();
+        browser.selected = first.unwrap_or(0);
+        browser
+    }
+
+    // Move around the menu. Returns the ROM picked, if one was.
+    pub fn navigate(&mut self, library: &Library, navigation: Navigation) -> Option<RomChoice> {
+        match navigation {
+            Navigation::Up => self.move_by(-1),
+            Navigation::Down => self.move_by(1),
+            Navigation::PageUp => self.move_by(-(ROWS as isize)),
+            Navigation::PageDown => self.move_by(ROWS as isize),
+            Navigation::Select => match &self.entries.get(self.selected)?.item {
+                Item::Rom(rom) => return Some(rom.clone()),
+                Item::Folder(location) => *self = Browser::new(library, location.as_deref()),
+                Item::Heading => (),
+            },
+            Navigation::Back => {
+                // Folders always start with the way back up
+                if self.location.is_some() {
+                    if let Some(Entry {
+                        item: Item::Folder(parent),
+                        ..
+                    }) = self.entries.first()
+                    {
+                        *self = Browser::new(library, parent.as_deref());
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn selectable(&self) -> impl Iterator<Item = usize> + '_ {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| !matches!(entry.item, Item::Heading))
+            .map(|(idx, _)| idx)
+    }
+
+    // Move by a number of selectable entries, stopping at either end
+    fn move_by(&mut self, steps: isize) {
+        let selectable: Vec<usize> = self.selectable().collect();
+        let Some(current) = selectable.iter().position(|&idx| idx == self.selected) else {
+            return;
+        };
+        let target = current
+            .saturating_add_signed(steps)
+            .min(selectable.len() - 1);
+        self.selected = selectable[target];
+
+        // Keep the headings above the first entry in view
+        if target == 0 {
+            self.scroll = 0;
+        } else if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + ROWS {
+            self.scroll = self.selected + 1 - ROWS;
+        }
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    // Draw the menu with the palette's colors
+    pub fn render(&self, palette: &Palette) -> Framebuffer {
+        let background = palette.background();
+        let mut frame = Framebuffer {
+            width: MENU_WIDTH,
+            height: MENU_HEIGHT,
+            background,
+            pixels: vec![background; MENU_WIDTH * MENU_HEIGHT],
+        };
+
+        fill_rect(&mut frame, 0, 0, MENU_WIDTH, LINE_HEIGHT, palette.colors[2]);
+        draw_text(&mut frame, 1, 1, &fit(&self.title, COLUMNS), background);
+
+        if self.selectable().next().is_none() {
+            let mut lines = vec!["No ROMs here"];
+            if self.location.is_none() {
+                lines = vec![
+                    "No ROMs here. Set rom_dir in the config file",
+                    "or drop a ROM on the window.",
+                ];
+            }
+            for (row, line) in lines.iter().enumerate() {
+                let y = (row + 2) * LINE_HEIGHT + 1;
+                draw_text(&mut frame, 1, y, line, palette.foreground());
+            }
+        }
+
+        let shown = self.entries.iter().enumerate().skip(self.scroll).take(ROWS);
+        for (row, (idx, entry)) in shown.enumerate() {
+            let y = (row + 1) * LINE_HEIGHT;
+            match entry.item {
+                Item::Heading => {
+                    let label = fit(&entry.label, COLUMNS);
+                    draw_text(&mut frame, 1, y + 1, &label, palette.colors[2]);
+                }
+                _ => {
+                    let mut color = palette.foreground();
+                    if idx == self.selected {
+                        fill_rect(&mut frame, 0, y, MENU_WIDTH, LINE_HEIGHT, color);
+                        color = background;
+                    }
+                    let label = fit(&entry.label, COLUMNS - 1);
+                    draw_text(&mut frame, CHAR_WIDTH + 1, y + 1, &label, color);
+                }
+            }
+        }
+        frame
+    }
+}
+
+fn heading(label: &str) -> Entry {
+    Entry {
+        label: label.to_string(),
+        item: Item::Heading,
+    }
+}
+
+// Cut text down to a number of characters, keeping the end of long paths
+// since that is the part that tells them apart
+fn fit(text: &str, columns: usize) -> String {
+    let count = text.chars().count();
+    if count <= columns {
+        return text.to_string();
+    }
+    let tail: String = text.chars().skip(count - columns + 3).collect();
+    format!("...{tail}")
+}
+
+// Subdirectories, zip archives and ROMs in a directory, or the ROMs in a zip
+// archive. ROMs the database knows are shown by title.
+fn list_folder(library: &Library, path: &Path) -> Vec<Entry> {
+    let title = |data: Option<Vec<u8>>, name: &str| {
+        data.and_then(|data| library.db.lookup(&data))
+            .map(|info| info.title.clone())
+            .filter(|title| !title.is_empty())
+            .unwrap_or_else(|| file_name(Path::new(name)))
+    };
+
+    if !path.is_dir() {
+        return match list_roms(path) {
+            Ok(names) => names
+                .unwrap_or_default()
+                .into_iter()
+                .map(|name| Entry {
+                    label: title(load_rom_from(path, &name).ok().map(|rom| rom.data), &name),
+                    item: Item::Rom(RomChoice {
+                        path: path.to_path_buf(),
+                        name: Some(name),
+                    }),
+                })
+                .collect(),
+            Err(err) => vec![heading(&format!("Unable to read: {err}"))],
+        };
+    }
+
+    let dir = match fs::read_dir(path) {
+        Ok(dir) => dir,
+        Err(err) => return vec![heading(&format!("Unable to read: {err}"))],
+    };
+    let mut entries: Vec<(bool, String, Entry)> = Vec::new();
+    for path in dir.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+        let name = file_name(&path);
+        if name.starts_with('.') {
+            continue;
+        }
+        if path.is_dir() || has_extension(&path, "zip") {
+            let entry = Entry {
+                label: format!("{name}/"),
+                item: Item::Folder(Some(path)),
+            };
+            entries.push((true, name, entry));
+        } else if is_rom(&path) || has_extension(&path, "gif") || has_extension(&path, "8o") {
+            // Cartridges and sources carry no hash to look up
+            let data = is_rom(&path).then(|| fs::read(&path).ok()).flatten();
+            let entry = Entry {
+                label: title(data, &name),
+                item: Item::Rom(RomChoice::file(&path)),
+            };
+            entries.push((false, name, entry));
+        }
+    }
+    // Folders first, then everything by file name
+    entries.sort_by(|a, b| match b.0.cmp(&a.0) {
+        Ordering::Equal => a.1.to_lowercase().cmp(&b.1.to_lowercase()),
+        order => order,
+    });
+    entries.into_iter().map(|(_, _, entry)| entry).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{sha1_hex, RomDb};
+
+    use std::env;
+    use std::process;
+
+    fn labels(browser: &Browser) -> Vec<&str> {
+        browser
+            .entries
+            .iter()
+            .map(|entry| entry.label.as_str())
+            .collect()
+    }
+
+    fn selected(browser: &Browser) -> &str {
+        &browser.entries[browser.selected].label
+    }
+
+    #[test]
+    fn browses_the_rom_directory() {
+        let dir = env::temp_dir().join(format!("rusty-chip-8-browser-{}", process::id()));
+        fs::create_dir_all(dir.join("more")).unwrap();
+        fs::write(dir.join("b.ch8"), [0x12, 0x00]).unwrap();
+        fs::write(dir.join("a.sc8"), [0x00, 0xE0]).unwrap();
+        fs::write(dir.join("notes.txt"), "").unwrap();
+        fs::write(dir.join("more").join("c.xo8"), [0x00, 0xE0]).unwrap();
+
+        let mut library = Library {
+            rom_dir: Some(dir.clone()),
+            ..Library::default()
+        };
+        let json = format!(
+            r#"[{{"title": "Loop", "roms": {{"{}": {{}}}}}}]"#,
+            sha1_hex(&[0x12, 0x00])
+        );
+        library.db = RomDb::default();
+        library.db.merge(&json).unwrap();
+        library
+            .recent
+            .add(RomChoice::file(&dir.join("b.ch8")), "Loop");
+
+        let mut browser = Browser::new(&library, None);
+        let top = dir.display().to_string();
+        assert_eq!(
+            labels(&browser),
+            vec!["Recently played", "Loop", &top, "more/", "a.sc8", "Loop"]
+        );
+        assert_eq!(selected(&browser), "Loop");
+
+        // Headings are skipped, and moving stops at the ends
+        browser.navigate(&library, Navigation::Down);
+        assert_eq!(selected(&browser), "more/");
+        browser.navigate(&library, Navigation::PageDown);
+        let picked = browser.navigate(&library, Navigation::Select);
+        assert_eq!(picked, Some(RomChoice::file(&dir.join("b.ch8"))));
+
+        browser.navigate(&library, Navigation::PageUp);
+        browser.navigate(&library, Navigation::Down);
+        assert_eq!(browser.navigate(&library, Navigation::Select), None);
+        assert_eq!(labels(&browser), vec!["..", "c.xo8"]);
+        assert_eq!(selected(&browser), "..");
+
+        // Back from the ROM directory goes to the top
+        browser.navigate(&library, Navigation::Back);
+        browser.navigate(&library, Navigation::Back);
+        assert_eq!(browser.title(), "Load a ROM");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn draws_the_selection_inverted() {
+        let library = Library {
+            recent: "\tpong.ch8\n".parse().unwrap(),
+            ..Library::default()
+        };
+        let browser = Browser::new(&library, None);
+        let palette = Palette::default();
+        let frame = browser.render(&palette);
+        assert_eq!((frame.width, frame.height), (MENU_WIDTH, MENU_HEIGHT));
+
+        // The selected game is on the third line, under the title and heading
+        let y = 2 * LINE_HEIGHT;
+        assert_eq!(frame.pixel(0, y), palette.foreground());
+        // The top of the P in PONG
+        assert_eq!(frame.pixel(CHAR_WIDTH + 1, y + 1), palette.background());
+        assert_eq!(frame.pixel(0, 3 * LINE_HEIGHT), palette.background());
+    }
+
+    #[test]
+    fn long_names_keep_their_end() {
+        assert_eq!(fit("short", 10), "short");
+        assert_eq!(fit("/home/user/roms/games", 10), "...s/games");
+        assert_eq!(fit("/home/user/roms/games", 10).len(), 10);
+    }
+}
@@ -0,0 +1,232 @@
+use crate::romdb::tickrate;
+use crate::{assemble, RomInfo};
+
+use chip8::{validate_rom, Quirks, RomError};
+
+use serde::Deserialize;
+
+use std::io::Read;
+
+// A program saved by Octo as a "cartridge": a GIF whose pixels carry the
+// program's source and settings. The low two bits of each pixel's palette
+// index hold two bits of data, most significant first, running through every
+// frame in order. The data is a 32 bit big endian length followed by that
+// many bytes of JSON, holding the Octo source and its options.
+pub struct Cartridge {
+    pub rom: Vec<u8>,
+    // The cartridge has no title, so that is left empty
+    pub info: RomInfo,
+}
+
+impl Cartridge {
+    pub fn decode(reader: impl Read) -> Result<Self, RomError> {
+        let payload = read_payload(reader)?;
+        let payload: Payload = serde_json::from_slice(&payload)
+            .map_err(|err| invalid(format!("invalid cartridge contents: {err}")))?;
+        let rom = assemble(&payload.program)
+            .map_err(|err| invalid(format!("unable to assemble the cartridge: {err}")))?;
+        validate_rom(&rom)?;
+        Ok(Cartridge {
+            rom,
+            info: payload.options.to_info(),
+        })
+    }
+}
+
+fn invalid(message: String) -> RomError {
+    RomError::Invalid(message)
+}
+
+fn read_payload(reader: impl Read) -> Result<Vec<u8>, RomError> {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = options
+        .read_info(reader)
+        .map_err(|err| invalid(format!("not a cartridge image: {err}")))?;
+
+    let mut data = Vec::new();
+    let mut byte = 0;
+    let mut bits = 0;
+    while let Some(frame) = decoder
+        .read_next_frame()
+        .map_err(|err| invalid(format!("broken cartridge image: {err}")))?
+    {
+        for &index in frame.buffer.iter() {
+            byte = byte << 2 | (index & 0b11);
+            bits += 2;
+            if bits == 8 {
+                data.push(byte);
+                byte = 0;
+                bits = 0;
+            }
+        }
+    }
+
+    let too_short = || invalid("the cartridge holds no program".to_string());
+    let (length, rest) = data.split_first_chunk::<4>().ok_or_else(too_short)?;
+    let length = u32::from_be_bytes(*length) as usize;
+    rest.get(..length).map(<[u8]>::to_vec).ok_or_else(too_short)
+}
+
+#[derive(Deserialize)]
+struct Payload {
+    program: String,
+    #[serde(default)]
+    options: Options,
+}
+
+// Octo's settings. Its quirk flags are all off for the original Chip-8
+// behaviour, so they don't line up one to one with Quirks.
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct Options {
+    tickrate: Option<u32>,
+    background_color: Option<String>,
+    fill_color: Option<String>,
+    fill_color2: Option<String>,
+    blend_color: Option<String>,
+    // Shifts change VX in place, ignoring VY
+    shift_quirks: bool,
+    // Loads and stores leave I unchanged
+    load_store_quirks: bool,
+    clip_quirks: bool,
+    jump_quirks: bool,
+    v_blank_quirks: bool,
+    logic_quirks: bool,
+}
+
+impl Options {
+    fn to_info(&self) -> RomInfo {
+        // Plane colors only make sense once there is a background and foreground
+        let colors: Vec<_> = [
+            &self.background_color,
+            &self.fill_color,
+            &self.fill_color2,
+            &self.blend_color,
+        ]
+        .into_iter()
+        .map_while(Option::as_deref)
+        .collect();
+        RomInfo {
+            title: String::new(),
+            quirks: Some(Quirks {
+                shift_uses_vy: !self.shift_quirks,
+                memory_increments_i: !self.load_store_quirks,
+                jump_uses_vx: self.jump_quirks,
+                clip_sprites: self.clip_quirks,
+                logic_resets_vf: self.logic_quirks,
+                display_wait: self.v_blank_quirks,
+            }),
+            ticks_per_frame: tickrate(self.tickrate),
+            palette: colors.join(",").parse().ok(),
+            keys: Default::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cartridge(payload: &str) -> Vec<u8> {
+        let mut data = (payload.len() as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(payload.as_bytes());
+        encode(&data)
+    }
+
+    // Lay data out the way Octo does, over several small frames
+    fn encode(data: &[u8]) -> Vec<u8> {
+        let mut indices: Vec<u8> = data
+            .iter()
+            .flat_map(|&byte| [byte >> 6, byte >> 4, byte >> 2, byte].map(|bits| bits & 0b11))
+            .collect();
+
+        let (width, height) = (16, 8);
+        let frame_size = width * height;
+        indices.resize(indices.len().div_ceil(frame_size) * frame_size, 0);
+        // Four shades of the label image, each with four data values
+        let palette: Vec<u8> = (0..16).flat_map(|i| [i * 16, i * 16, i * 16]).collect();
+        let mut gif = Vec::new();
+        let mut encoder =
+            gif::Encoder::new(&mut gif, width as u16, height as u16, &palette).unwrap();
+        for (n, chunk) in indices.chunks(frame_size).enumerate() {
+            // The label shade goes in the high bits, which decoding ignores
+            let pixels: Vec<u8> = chunk
+                .iter()
+                .map(|&bits| (n as u8 % 4) << 2 | bits)
+                .collect();
+            let frame = gif::Frame::from_indexed_pixels(width as u16, height as u16, pixels, None);
+            encoder.write_frame(&frame).unwrap();
+        }
+        drop(encoder);
+        gif
+    }
+
+    #[test]
+    fn reads_the_program_and_settings() {
+        let payload = r##"{
+            "program": ": main\n  v0 := 1\n  loop again",
+            "options": {
+                "tickrate": 20,
+                "backgroundColor": "#996600",
+                "fillColor": "#FFCC00",
+                "fillColor2": "#FF6600",
+                "blendColor": "#662200",
+                "buzzColor": "#FFAA00",
+                "shiftQuirks": true,
+                "loadStoreQuirks": false,
+                "clipQuirks": true,
+                "vBlankQuirks": true,
+                "screenRotation": 0
+            }
+        }"##;
+        let cartridge = Cartridge::decode(&cartridge(payload)[..]).unwrap();
+        assert_eq!(cartridge.rom, vec![0x60, 0x01, 0x12, 0x02]);
+        let info = cartridge.info;
+        assert_eq!(info.instructions_per_second(), Some(1200));
+        assert_eq!(
+            info.quirks,
+            Some(Quirks {
+                memory_increments_i: true,
+                clip_sprites: true,
+                display_wait: true,
+                ..Quirks::MODERN
+            })
+        );
+        assert_eq!(
+            info.palette.unwrap().colors,
+            "octo".parse::<crate::Palette>().unwrap().colors
+        );
+    }
+
+    #[test]
+    fn defaults_to_original_chip8_behaviour() {
+        let payload = r#"{"program": ": main clear"}"#;
+        let info = Cartridge::decode(&cartridge(payload)[..]).unwrap().info;
+        assert_eq!(info.ticks_per_frame, None);
+        assert_eq!(info.palette, None);
+        let payload = r#"{"program": ": main clear", "options": {"tickrate": 4294967295}}"#;
+        let info = Cartridge::decode(&cartridge(payload)[..]).unwrap().info;
+        assert_eq!(info.ticks_per_frame, None);
+        let quirks = info.quirks.unwrap();
+        assert!(quirks.shift_uses_vy && quirks.memory_increments_i);
+    }
+
+    #[test]
+    fn rejects_broken_cartridges() {
+        let error = |data: &[u8]| Cartridge::decode(data).err().unwrap().to_string();
+        assert!(error(b"GIF89a").starts_with("not a cartridge image"));
+        // Claiming more data than there is
+        assert_eq!(
+            error(&encode(&[0, 0, 1, 0, b'{'])),
+            "the cartridge holds no program"
+        );
+        assert!(error(&cartridge("{}")).starts_with("invalid cartridge contents"));
+        assert!(error(&cartridge(r#"{"program": ": main v0 := 999"}"#))
+            .contains("doesn't fit in a byte"));
+        assert!(matches!(
+            Cartridge::decode(&cartridge(r#"{"program": ""}"#)[..]),
+            Err(RomError::Empty)
+        ));
+    }
+}
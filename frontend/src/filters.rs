@@ -0,0 +1,220 @@
+use crate::{Framebuffer, Rgb};
+
+// Software post-processing for a retro look. The image is first scaled up by
+// a whole factor so effects can work below the size of a Chip-8 pixel, then
+// each enabled effect runs in turn. Strengths go from 0 (no effect) to 1.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Filters {
+    pub scale: usize,
+    // Darken the lines between Chip-8 pixels
+    pub grid: Option<f32>,
+    // Darken every other output line
+    pub scanlines: Option<f32>,
+    // Let bright pixels glow into their neighbours
+    pub bloom: Option<Bloom>,
+    // Bend the image like a CRT tube, as the fraction the corners are pulled in
+    pub curvature: Option<f32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bloom {
+    pub strength: f32,
+    // How far the glow spreads, in output pixels
+    pub radius: usize,
+}
+
+impl Default for Filters {
+    fn default() -> Self {
+        Filters {
+            scale: 1,
+            grid: None,
+            scanlines: None,
+            bloom: None,
+            curvature: None,
+        }
+    }
+}
+
+impl Filters {
+    // Whether applying the filters would change the image at all
+    pub fn is_active(&self) -> bool {
+        self.scale > 1
+            || self.grid.is_some()
+            || self.scanlines.is_some()
+            || self.bloom.is_some()
+            || self.curvature.is_some()
+    }
+
+    // What the filters hotkey steps through: every effect together, then each
+    // effect on its own when there are several, then no filtering at all
+    pub fn choices(&self) -> Vec<Filters> {
+        let none = Filters {
+            scale: self.scale,
+            ..Filters::default()
+        };
+        let mut singles = Vec::new();
+        if let Some(grid) = self.grid {
+            singles.push(Filters {
+                grid: Some(grid),
+                ..none.clone()
+            });
+        }
+        if let Some(scanlines) = self.scanlines {
+            singles.push(Filters {
+                scanlines: Some(scanlines),
+                ..none.clone()
+            });
+        }
+        if let Some(bloom) = self.bloom {
+            singles.push(Filters {
+                bloom: Some(bloom),
+                ..none.clone()
+            });
+        }
+        if let Some(curvature) = self.curvature {
+            singles.push(Filters {
+                curvature: Some(curvature),
+                ..none.clone()
+            });
+        }
+
+        let mut choices = vec![self.clone()];
+        if singles.len() > 1 {
+            choices.extend(singles);
+        }
+        choices.push(Filters::default());
+        choices
+    }
+
+    pub fn apply(&self, frame: &Framebuffer) -> Framebuffer {
+        let scale = self.scale.max(1);
+        let mut out = frame.scaled(scale);
+        if let Some(strength) = self.grid {
+            grid(&mut out, scale, strength);
+        }
+        if let Some(strength) = self.scanlines {
+            scanlines(&mut out, strength);
+        }
+        if let Some(bloom) = self.bloom {
+            out = glow(&out, bloom);
+        }
+        if let Some(amount) = self.curvature {
+            out = curve(&out, amount);
+        }
+        out
+    }
+}
+
+fn darken(rgb: Rgb, strength: f32) -> Rgb {
+    rgb.lerp(Rgb::new(0, 0, 0), strength.clamp(0.0, 1.0))
+}
+
+// Darkens the last row and column of every scaled up pixel
+fn grid(frame: &mut Framebuffer, scale: usize, strength: f32) {
+    if scale < 2 {
+        return;
+    }
+    let width = frame.width;
+    for (i, pixel) in frame.pixels.iter_mut().enumerate() {
+        let (x, y) = (i % width, i / width);
+        if x % scale == scale - 1 || y % scale == scale - 1 {
+            *pixel = darken(*pixel, strength);
+        }
+    }
+}
+
+fn scanlines(frame: &mut Framebuffer, strength: f32) {
+    let width = frame.width;
+    for row in frame.pixels.chunks_mut(width).skip(1).step_by(2) {
+        for pixel in row {
+            *pixel = darken(*pixel, strength);
+        }
+    }
+}
+
+// Adds a box blurred copy of the image on top of itself
+fn glow(frame: &Framebuffer, bloom: Bloom) -> Framebuffer {
+    let (width, height) = (frame.width, frame.height);
+    let channels: Vec<[f32; 3]> = frame
+        .pixels
+        .iter()
+        .map(|p| [p.r as f32, p.g as f32, p.b as f32])
+        .collect();
+
+    // The blur is separable, so do it horizontally then vertically
+    let horizontal = blur(&channels, width, height, bloom.radius, (1, 0));
+    let blurred = blur(&horizontal, width, height, bloom.radius, (0, 1));
+
+    let pixels = frame
+        .pixels
+        .iter()
+        .zip(&blurred)
+        .map(|(p, glow)| {
+            let add = |c: u8, g: f32| (c as f32 + g * bloom.strength).min(255.0) as u8;
+            Rgb::new(add(p.r, glow[0]), add(p.g, glow[1]), add(p.b, glow[2]))
+        })
+        .collect();
+    Framebuffer {
+        width,
+        height,
+        background: frame.background,
+        pixels,
+    }
+}
+
+fn blur(
+    channels: &[[f32; 3]],
+    width: usize,
+    height: usize,
+    radius: usize,
+    step: (usize, usize),
+) -> Vec<[f32; 3]> {
+    let taps = (2 * radius + 1) as f32;
+    (0..width * height)
+        .map(|i| {
+            let (x, y) = (i % width, i / width);
+            let mut sum = [0.0; 3];
+            for offset in 0..=2 * radius {
+                // Samples past the edge count as black
+                let sx = (x + offset * step.0).checked_sub(radius * step.0);
+                let sy = (y + offset * step.1).checked_sub(radius * step.1);
+                if let (Some(sx), Some(sy)) = (sx, sy) {
+                    if sx < width && sy < height {
+                        let sample = channels[sx + width * sy];
+                        for c in 0..3 {
+                            sum[c] += sample[c];
+                        }
+                    }
+                }
+            }
+            sum.map(|c| c / taps)
+        })
+        .collect()
+}
+
+// Barrel distortion: every output pixel samples further out the closer it is
+// to the corners, and anything that lands outside the image is left black
+fn curve(frame: &Framebuffer, amount: f32) -> Framebuffer {
+    let (width, height) = (frame.width, frame.height);
+    let pixels = (0..width * height)
+        .map(|i| {
+            // Map the pixel center to -1..1 in both directions
+            let u = ((i % width) as f32 + 0.5) / width as f32 * 2.0 - 1.0;
+            let v = ((i / width) as f32 + 0.5) / height as f32 * 2.0 - 1.0;
+            let su = u * (1.0 + amount * v * v);
+            let sv = v * (1.0 + amount * u * u);
+            if su.abs() > 1.0 || sv.abs() > 1.0 {
+                return Rgb::new(0, 0, 0);
+            }
+            let sx = (((su + 1.0) / 2.0 * width as f32) as usize).min(width - 1);
+            let sy = (((sv + 1.0) / 2.0 * height as f32) as usize).min(height - 1);
+            frame.pixel(sx, sy)
+        })
+        .collect();
+    Framebuffer {
+        width,
+        height,
+        background: frame.background,
+        pixels,
+    }
+}
@@ -0,0 +1,108 @@
+use std::fmt;
+use std::str::FromStr;
+
+// A Chip-8 key changing state just before the given frame runs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyChange {
+    pub frame: u64,
+    pub key: usize,
+    pub pressed: bool,
+}
+
+// Every key change of a session in frame order. Saved as text with one change
+// per line: "<frame> <hex key> down|up".
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InputLog {
+    pub changes: Vec<KeyChange>,
+}
+
+impl InputLog {
+    pub fn push(&mut self, frame: u64, key: usize, pressed: bool) {
+        self.changes.push(KeyChange {
+            frame,
+            key,
+            pressed,
+        });
+    }
+
+    // The last frame with a key change, after which playback has nothing to add
+    pub fn last_frame(&self) -> u64 {
+        self.changes.last().map_or(0, |change| change.frame)
+    }
+
+    // Parse and add a single "<frame> <key> down|up" line
+    pub(crate) fn push_line(&mut self, line: &str) -> Result<(), String> {
+        let change = parse_change(line)
+            .ok_or_else(|| format!("expected '<frame> <key> down|up', found '{line}'"))?;
+        if change.frame < self.last_frame() {
+            return Err("frames must be in order".to_string());
+        }
+        self.changes.push(change);
+        Ok(())
+    }
+}
+
+impl fmt::Display for InputLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.changes {
+            let state = if change.pressed { "down" } else { "up" };
+            writeln!(f, "{} {:X} {state}", change.frame, change.key)?;
+        }
+        Ok(())
+    }
+}
+
+// Blank lines and lines starting with # are ignored
+impl FromStr for InputLog {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut log = InputLog::default();
+        for (number, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            log.push_line(line)
+                .map_err(|err| format!("line {}: {err}", number + 1))?;
+        }
+        Ok(log)
+    }
+}
+
+fn parse_change(line: &str) -> Option<KeyChange> {
+    let mut fields = line.split_whitespace();
+    let frame = fields.next()?.parse().ok()?;
+    let key = usize::from_str_radix(fields.next()?, 16)
+        .ok()
+        .filter(|&k| k < 16)?;
+    let pressed = match fields.next()? {
+        "down" => true,
+        "up" => false,
+        _ => return None,
+    };
+    fields.next().is_none().then_some(KeyChange {
+        frame,
+        key,
+        pressed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_text() {
+        let mut log = InputLog::default();
+        log.push(3, 0xA, true);
+        log.push(10, 0xA, false);
+        let text = log.to_string();
+        assert_eq!(text, "3 A down\n10 A up\n");
+        assert_eq!(text.parse(), Ok(log));
+
+        assert!("3 G down".parse::<InputLog>().is_err());
+        assert!("5 1 down\n4 1 up".parse::<InputLog>().is_err());
+        assert_eq!("# comment\n\n".parse(), Ok(InputLog::default()));
+    }
+}
@@ -0,0 +1,38 @@
+mod action;
+mod browser;
+mod cartridge;
+mod filters;
+mod font;
+mod input_log;
+mod library;
+mod loader;
+mod machine;
+mod movie;
+mod octo;
+mod pacing;
+mod palette;
+mod persistence;
+mod recording;
+mod romdb;
+mod runner;
+mod screenshot;
+mod watch;
+
+pub use action::*;
+pub use browser::*;
+pub use cartridge::*;
+pub use filters::*;
+pub use input_log::*;
+pub use library::*;
+pub use loader::*;
+pub use machine::*;
+pub use movie::*;
+pub use octo::*;
+pub use pacing::*;
+pub use palette::*;
+pub use persistence::*;
+pub use recording::*;
+pub use romdb::*;
+pub use runner::*;
+pub use screenshot::*;
+pub use watch::*;
@@ -0,0 +1,175 @@
+use crate::{load_rom, load_rom_from, LoadedRom, RomDb};
+
+use chip8::RomError;
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+// How many games the recently played list keeps
+pub const MAX_RECENT: usize = 10;
+
+// A ROM to load: a file, or one of the ROMs in a zip archive or directory
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RomChoice {
+    pub path: PathBuf,
+    // The ROM's name within the archive or directory, as given by list_roms
+    pub name: Option<String>,
+}
+
+impl RomChoice {
+    pub fn file(path: &Path) -> Self {
+        RomChoice {
+            path: path.to_path_buf(),
+            name: None,
+        }
+    }
+
+    pub fn load(&self) -> Result<LoadedRom, RomError> {
+        match &self.name {
+            Some(name) => load_rom_from(&self.path, name),
+            None => load_rom(&self.path),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecentRom {
+    pub rom: RomChoice,
+    // The game's title from the ROM database, empty if it isn't known
+    pub title: String,
+}
+
+// Recently played games, the latest first. Saved as one game per line: its
+// title, path and the name within an archive if any, separated by tabs.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RecentRoms {
+    pub roms: Vec<RecentRom>,
+}
+
+impl RecentRoms {
+    // Move the game to the top of the list, dropping the oldest if full
+    pub fn add(&mut self, rom: RomChoice, title: &str) {
+        self.roms.retain(|recent| recent.rom != rom);
+        self.roms.insert(
+            0,
+            RecentRom {
+                rom,
+                title: title.to_string(),
+            },
+        );
+        self.roms.truncate(MAX_RECENT);
+    }
+}
+
+impl fmt::Display for RecentRoms {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for recent in &self.roms {
+            write!(f, "{}\t{}", recent.title, recent.rom.path.display())?;
+            if let Some(name) = &recent.rom.name {
+                write!(f, "\t{name}")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for RecentRoms {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut recent = RecentRoms::default();
+        for (n, line) in s.lines().enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            let mut fields = line.split('\t');
+            let (Some(title), Some(path)) = (fields.next(), fields.next()) else {
+                return Err(format!("line {}: expected a title and a path", n + 1));
+            };
+            recent.roms.push(RecentRom {
+                rom: RomChoice {
+                    path: PathBuf::from(path),
+                    name: fields.next().map(String::from),
+                },
+                title: title.to_string(),
+            });
+        }
+        recent.roms.truncate(MAX_RECENT);
+        Ok(recent)
+    }
+}
+
+// Everything the ROM browser offers to pick from
+#[derive(Default)]
+pub struct Library {
+    // Where the browser starts looking for ROMs
+    pub rom_dir: Option<PathBuf>,
+    pub db: RomDb,
+    pub recent: RecentRoms,
+    // Where the recently played list is saved whenever it changes
+    pub recent_path: Option<PathBuf>,
+}
+
+impl Library {
+    // Read the recently played list saved at the path, if there is one
+    pub fn load_recent(&mut self, path: &Path) -> Result<(), String> {
+        self.recent_path = Some(path.to_path_buf());
+        match fs::read_to_string(path) {
+            Ok(text) => self.recent = text.parse()?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+            Err(err) => return Err(err.to_string()),
+        }
+        Ok(())
+    }
+
+    // Put the game at the top of the recently played list and save it. Paths
+    // are made absolute so they still work from another working directory.
+    pub fn remember(&mut self, rom: &RomChoice, title: &str) {
+        let rom = RomChoice {
+            path: fs::canonicalize(&rom.path).unwrap_or_else(|_| rom.path.clone()),
+            name: rom.name.clone(),
+        };
+        self.recent.add(rom, title);
+        if let Some(path) = &self.recent_path {
+            let saved = path
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| fs::write(path, self.recent.to_string()));
+            if let Err(err) = saved {
+                eprintln!("Unable to save recent games to {}: {err}", path.display());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recent_games_move_to_the_top() {
+        let mut recent = RecentRoms::default();
+        for n in 0..MAX_RECENT + 2 {
+            recent.add(RomChoice::file(Path::new(&format!("{n}.ch8"))), "");
+        }
+        assert_eq!(recent.roms.len(), MAX_RECENT);
+        recent.add(RomChoice::file(Path::new("5.ch8")), "Five");
+        assert_eq!(recent.roms[0].title, "Five");
+        assert_eq!(recent.roms[1].rom.path, Path::new("11.ch8"));
+        assert_eq!(recent.roms.len(), MAX_RECENT);
+
+        recent.add(
+            RomChoice {
+                path: PathBuf::from("games.zip"),
+                name: Some("pong.ch8".to_string()),
+            },
+            "Pong",
+        );
+        let parsed: RecentRoms = recent.to_string().parse().unwrap();
+        assert_eq!(parsed, recent);
+        assert!("no tabs here".parse::<RecentRoms>().is_err());
+    }
+}
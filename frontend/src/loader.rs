@@ -0,0 +1,211 @@
+use crate::{assemble, Cartridge, RomInfo};
+
+use chip8::{read_rom, read_rom_file, validate_rom, Quirks, RomError};
+
+use std::fs::{self, File};
+use std::path::Path;
+
+// File extensions of ROMs, for picking them out of archives and directories
+pub const ROM_EXTENSIONS: [&str; 3] = ["ch8", "sc8", "xo8"];
+
+// A ROM ready to run, with any settings that came in the same file
+pub struct LoadedRom {
+    pub data: Vec<u8>,
+    pub info: Option<RomInfo>,
+    // The ROM's file name, which is inside the archive for a zipped ROM
+    pub name: String,
+}
+
+// Load a plain ROM, an Octo cartridge if the file is a GIF, or Octo source
+// assembled on the spot if it is a .8o file
+pub fn load_rom(path: &Path) -> Result<LoadedRom, RomError> {
+    let name = file_name(path);
+    if has_extension(path, "8o") {
+        let rom = assemble(&fs::read_to_string(path)?)
+            .map_err(|err| RomError::Invalid(format!("unable to assemble: {err}")))?;
+        validate_rom(&rom)?;
+        return Ok(LoadedRom {
+            data: rom,
+            info: None,
+            name,
+        });
+    }
+    if has_extension(path, "gif") {
+        let cartridge = Cartridge::decode(File::open(path)?)?;
+        return Ok(LoadedRom {
+            data: cartridge.rom,
+            info: Some(cartridge.info),
+            name,
+        });
+    }
+    Ok(LoadedRom {
+        data: read_rom_file(path)?,
+        info: None,
+        name,
+    })
+}
+
+// The ROMs in a zip archive or directory, sorted by name, or None if the path
+// is a ROM itself
+pub fn list_roms(path: &Path) -> Result<Option<Vec<String>>, RomError> {
+    let mut names: Vec<String> = if path.is_dir() {
+        fs::read_dir(path)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_file())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .collect()
+    } else if has_extension(path, "zip") {
+        let archive = zip::ZipArchive::new(File::open(path)?).map_err(zip_error)?;
+        archive.file_names().map(String::from).collect()
+    } else {
+        return Ok(None);
+    };
+    names.retain(|name| is_rom(Path::new(name)));
+    names.sort();
+    Ok(Some(names))
+}
+
+// Pick a ROM from list_roms by name, or by its number in the list counting
+// from 1. A name matches with or without the directories in the archive.
+pub fn select_rom<'a>(names: &'a [String], selection: &str) -> Result<&'a str, RomError> {
+    if let Ok(number) = selection.parse::<usize>() {
+        return number
+            .checked_sub(1)
+            .and_then(|idx| names.get(idx))
+            .map(String::as_str)
+            .ok_or_else(|| {
+                RomError::Invalid(format!(
+                    "there is no ROM number {number}, only {}",
+                    names.len()
+                ))
+            });
+    }
+    names
+        .iter()
+        .find(|name| *name == selection || file_name(Path::new(name)) == selection)
+        .map(String::as_str)
+        .ok_or_else(|| RomError::Invalid(format!("there is no ROM named '{selection}'")))
+}
+
+// Load one of the ROMs given by list_roms
+pub fn load_rom_from(path: &Path, name: &str) -> Result<LoadedRom, RomError> {
+    if path.is_dir() {
+        return load_rom(&path.join(name));
+    }
+    let mut archive = zip::ZipArchive::new(File::open(path)?).map_err(zip_error)?;
+    let file = archive.by_name(name).map_err(zip_error)?;
+    Ok(LoadedRom {
+        data: read_rom(file)?,
+        info: None,
+        name: file_name(Path::new(name)),
+    })
+}
+
+// The quirks a ROM most likely needs going by its file extension, if it isn't
+// a plain Chip-8 ROM
+pub fn default_quirks(name: &str) -> Option<Quirks> {
+    let path = Path::new(name);
+    if has_extension(path, "sc8") {
+        Some(Quirks::SCHIP)
+    } else if has_extension(path, "xo8") {
+        Some(Quirks::XOCHIP)
+    } else {
+        None
+    }
+}
+
+pub(crate) fn is_rom(path: &Path) -> bool {
+    ROM_EXTENSIONS
+        .iter()
+        .any(|extension| has_extension(path, extension))
+}
+
+pub(crate) fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+}
+
+pub(crate) fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn zip_error(err: zip::result::ZipError) -> RomError {
+    match err {
+        zip::result::ZipError::Io(err) => RomError::Io(err),
+        err => RomError::Invalid(format!("invalid zip archive: {err}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::process;
+
+    // A directory of its own for each test, removed afterwards
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = env::temp_dir().join(format!("rusty-chip-8-{name}-{}", process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn loads_roms_from_zip_archives() {
+        let dir = TempDir::new("zip");
+        let path = dir.0.join("roms.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        for (name, rom) in [
+            ("games/pong.ch8", &[0x12, 0x00][..]),
+            ("README.txt", b"not a rom"),
+            ("car.SC8", &[0x00, 0xE0]),
+        ] {
+            zip.start_file(name, options).unwrap();
+            zip.write_all(rom).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let names = list_roms(&path).unwrap().unwrap();
+        assert_eq!(names, vec!["car.SC8", "games/pong.ch8"]);
+        assert_eq!(select_rom(&names, "2").unwrap(), "games/pong.ch8");
+        assert_eq!(select_rom(&names, "pong.ch8").unwrap(), "games/pong.ch8");
+        assert!(select_rom(&names, "3").is_err());
+        assert!(select_rom(&names, "tetris.ch8").is_err());
+
+        let rom = load_rom_from(&path, "games/pong.ch8").unwrap();
+        assert_eq!(rom.data, vec![0x12, 0x00]);
+        assert_eq!(rom.name, "pong.ch8");
+        assert_eq!(default_quirks(&names[0]), Some(Quirks::SCHIP));
+        assert_eq!(default_quirks(&rom.name), None);
+    }
+
+    #[test]
+    fn loads_roms_from_directories() {
+        let dir = TempDir::new("dir");
+        fs::write(dir.0.join("b.xo8"), [0x12, 0x00]).unwrap();
+        fs::write(dir.0.join("a.ch8"), [0x00, 0xE0]).unwrap();
+        fs::write(dir.0.join("notes.txt"), "").unwrap();
+
+        let names = list_roms(&dir.0).unwrap().unwrap();
+        assert_eq!(names, vec!["a.ch8", "b.xo8"]);
+        let rom = load_rom_from(&dir.0, "b.xo8").unwrap();
+        assert_eq!(rom.data, vec![0x12, 0x00]);
+        assert_eq!(default_quirks(&rom.name), Some(Quirks::XOCHIP));
+        assert!(list_roms(&dir.0.join("a.ch8")).unwrap().is_none());
+    }
+}
@@ -0,0 +1,205 @@
+use crate::FRAME_RATE;
+
+use chip8::*;
+
+use std::io;
+
+pub const DEFAULT_TICKS_PER_FRAME: usize = 10;
+pub const DEFAULT_INSTRUCTIONS_PER_SECOND: u32 = DEFAULT_TICKS_PER_FRAME as u32 * 60;
+
+// A Cpu together with the ROM it runs and how fast it runs it. One frame is
+// a batch of instructions followed by a single 60 Hz timer tick. Speeds that
+// don't divide evenly into frames run one extra instruction every few frames.
+pub struct Machine {
+    cpu: Cpu,
+    rom: Vec<u8>,
+    // Used to name screenshots, usually the ROM's file name
+    name: String,
+    instructions_per_second: u32,
+    frame_count: u64,
+}
+
+impl Machine {
+    pub fn new(rom: Vec<u8>) -> Result<Self, RomError> {
+        let mut cpu = Cpu::setup_cpu();
+        cpu.load_rom(&rom)?;
+        Ok(Machine {
+            cpu,
+            rom,
+            name: String::from("chip8"),
+            instructions_per_second: DEFAULT_INSTRUCTIONS_PER_SECOND,
+            frame_count: 0,
+        })
+    }
+
+    pub fn run_frame(&mut self) {
+        // Instructions due by the end of this frame, minus those already run
+        let ips = self.instructions_per_second as u64;
+        let done = self.frame_count * ips / FRAME_RATE;
+        let due = (self.frame_count + 1) * ips / FRAME_RATE;
+        for _ in done..due {
+            self.cpu.tick();
+        }
+        self.cpu.tick_timers();
+        self.frame_count += 1;
+    }
+
+    // Restart the current ROM from a clean Cpu
+    pub fn reset(&mut self) -> Result<(), RomError> {
+        self.cpu.reset();
+        self.frame_count = 0;
+        self.cpu.load_rom(&self.rom)
+    }
+
+    // Swap in another ROM and start it from a clean Cpu. The current ROM is
+    // kept if the new one doesn't fit.
+    pub fn set_rom(&mut self, rom: Vec<u8>) -> Result<(), RomError> {
+        validate_rom(&rom)?;
+        self.rom = rom;
+        self.reset()
+    }
+
+    // Write a new build of the ROM over the old one without resetting, so
+    // the registers, display and timers carry on. Whatever the old ROM had
+    // past the end of the new one is cleared.
+    pub fn patch_rom(&mut self, rom: Vec<u8>) -> Result<(), RomError> {
+        validate_rom(&rom)?;
+        let mut padded = rom.clone();
+        padded.resize(rom.len().max(self.rom.len()), 0);
+        self.cpu.load_rom(&padded)?;
+        self.rom = rom;
+        Ok(())
+    }
+
+    // The Cpu's save state, plus where the machine is in its instruction schedule
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&self.frame_count.to_le_bytes());
+        data.extend_from_slice(&self.instructions_per_second.to_le_bytes());
+        data.extend(self.cpu.save_state());
+        data
+    }
+
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), io::Error> {
+        if data.len() < 12 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "save state is cut short",
+            ));
+        }
+        let (header, cpu_state) = data.split_at(12);
+        self.cpu.load_state(cpu_state)?;
+        self.frame_count = u64::from_le_bytes(header[..8].try_into().unwrap());
+        self.instructions_per_second = u32::from_le_bytes(header[8..].try_into().unwrap());
+        Ok(())
+    }
+
+    pub fn keypress(&mut self, idx: usize, pressed: bool) -> Result<(), DebugError> {
+        self.cpu.keypress(idx, pressed)
+    }
+
+    pub fn is_beeping(&self) -> bool {
+        self.cpu.get_sound_timer() > 0
+    }
+
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut Cpu {
+        &mut self.cpu
+    }
+
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn set_name(&mut self, name: &str) {
+        self.name = name.to_string();
+    }
+
+    // Instructions per frame, rounded down
+    pub fn ticks_per_frame(&self) -> usize {
+        (self.instructions_per_second as u64 / FRAME_RATE) as usize
+    }
+
+    // Speeds too fast to count per second are held at the fastest that can be
+    pub fn set_ticks_per_frame(&mut self, ticks: usize) {
+        let ips = (ticks as u64).saturating_mul(FRAME_RATE);
+        self.instructions_per_second = u32::try_from(ips).unwrap_or(u32::MAX);
+    }
+
+    pub fn instructions_per_second(&self) -> u32 {
+        self.instructions_per_second
+    }
+
+    pub fn set_instructions_per_second(&mut self, ips: u32) {
+        self.instructions_per_second = ips;
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_roms_that_dont_fit() {
+        assert!(matches!(Machine::new(Vec::new()), Err(RomError::Empty)));
+        let err = Machine::new(vec![0; MAX_ROM_SIZE + 1]).err().unwrap();
+        assert!(matches!(err, RomError::TooLarge { size, .. } if size == MAX_ROM_SIZE + 1));
+        assert!(Machine::new(vec![0; MAX_ROM_SIZE]).is_ok());
+
+        let err = read_rom(&[0; 5000][..]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the ROM is 5000 bytes, but at most 3584 bytes fit in memory"
+        );
+        assert_eq!(read_rom(&[0x12, 0x00][..]).unwrap(), vec![0x12, 0x00]);
+
+        let mut machine = Machine::new(vec![0x12, 0x00]).unwrap();
+        assert!(machine.set_rom(Vec::new()).is_err());
+        assert_eq!(machine.rom(), [0x12, 0x00]);
+        machine.set_rom(vec![0x00, 0xE0]).unwrap();
+        assert_eq!(machine.rom(), [0x00, 0xE0]);
+    }
+
+    #[test]
+    fn patching_keeps_the_machine_running() {
+        // V0 += 1, V1 += 1, jump back to the start
+        let mut machine = Machine::new(vec![0x70, 0x01, 0x71, 0x01, 0x12, 0x00]).unwrap();
+        machine.run_frame();
+        let before = machine.cpu().snapshot();
+
+        // V0 += 2, jump back to the start. Only memory changes.
+        machine.patch_rom(vec![0x70, 0x02, 0x12, 0x00]).unwrap();
+        let after = machine.cpu().snapshot();
+        assert_eq!(after.memory[0x200..0x206], [0x70, 0x02, 0x12, 0x00, 0, 0]);
+        assert_eq!(
+            CpuState {
+                memory: before.memory.clone(),
+                ..after
+            },
+            before
+        );
+        assert_eq!(machine.frame_count(), 1);
+        assert!(machine.patch_rom(Vec::new()).is_err());
+        assert_eq!(machine.rom(), [0x70, 0x02, 0x12, 0x00]);
+    }
+
+    #[test]
+    fn huge_speeds_are_held_at_the_fastest() {
+        let mut machine = Machine::new(vec![0x12, 0x00]).unwrap();
+        machine.set_ticks_per_frame(15);
+        assert_eq!(machine.instructions_per_second(), 900);
+        machine.set_ticks_per_frame(100_000_000);
+        assert_eq!(machine.instructions_per_second(), u32::MAX);
+    }
+}
@@ -0,0 +1,109 @@
+use crate::{InputLog, DEFAULT_INSTRUCTIONS_PER_SECOND};
+
+use chip8::Quirks;
+
+use std::fmt;
+use std::str::FromStr;
+
+const HEADER: &str = "# rusty-chip-8 movie";
+
+// Everything needed to replay a session exactly: the machine settings it
+// started with and every key change after that. Movies always start from a
+// freshly reset machine.
+//
+// Saved as text, settings first and then the key changes:
+//
+//     seed 12345
+//     quirks modern
+//     ips 600
+//     120 5 down
+//     131 5 up
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Movie {
+    pub seed: u64,
+    pub quirks: Quirks,
+    pub instructions_per_second: u32,
+    pub inputs: InputLog,
+}
+
+impl Default for Movie {
+    fn default() -> Self {
+        Movie {
+            seed: 0,
+            quirks: Quirks::default(),
+            instructions_per_second: DEFAULT_INSTRUCTIONS_PER_SECOND,
+            inputs: InputLog::default(),
+        }
+    }
+}
+
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{HEADER}")?;
+        writeln!(f, "seed {}", self.seed)?;
+        writeln!(f, "quirks {}", self.quirks)?;
+        writeln!(f, "ips {}", self.instructions_per_second)?;
+        write!(f, "{}", self.inputs)
+    }
+}
+
+// Settings left out keep their defaults, so a plain input log is a valid movie
+impl FromStr for Movie {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut movie = Movie::default();
+        for (number, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, value) = line.split_once(' ').unwrap_or((line, ""));
+            let result = match name {
+                "seed" => parse_value(value).map(|seed| movie.seed = seed),
+                "quirks" => value.parse().map(|quirks| movie.quirks = quirks),
+                "ips" => parse_value(value).map(|ips| movie.instructions_per_second = ips),
+                _ => movie.inputs.push_line(line),
+            };
+            result.map_err(|err| format!("line {}: {err}", number + 1))?;
+        }
+        Ok(movie)
+    }
+}
+
+fn parse_value<T: FromStr>(value: &str) -> Result<T, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid number '{value}'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_text() {
+        let mut movie = Movie {
+            seed: 42,
+            quirks: Quirks::VIP,
+            instructions_per_second: 700,
+            ..Movie::default()
+        };
+        movie.inputs.push(7, 0xF, true);
+        let text = movie.to_string();
+        assert!(text.contains("quirks vip\n"));
+        assert_eq!(text.parse(), Ok(movie));
+    }
+
+    #[test]
+    fn input_logs_are_movies() {
+        let movie: Movie = "3 A down\n10 A up".parse().unwrap();
+        assert_eq!(
+            movie.instructions_per_second,
+            DEFAULT_INSTRUCTIONS_PER_SECOND
+        );
+        assert_eq!(movie.inputs.changes.len(), 2);
+        assert!("seed x".parse::<Movie>().is_err());
+    }
+}
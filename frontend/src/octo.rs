@@ -0,0 +1,571 @@
+use chip8::MAX_ROM_SIZE;
+
+use std::collections::HashMap;
+
+const START_ADDR: u16 = 0x200;
+
+// Assemble Octo source into a ROM. This covers the Chip-8 part of the
+// language: labels, :const, :alias, :byte, every Chip-8 instruction, and
+// if/then, if/begin/else/end and loop/while/again. Macros, :calc and the
+// SUPER-CHIP and XO-CHIP instructions aren't supported.
+pub fn assemble(source: &str) -> Result<Vec<u8>, String> {
+    let tokens = source
+        .lines()
+        .enumerate()
+        .flat_map(|(number, line)| {
+            let code = line.split('#').next().unwrap_or("");
+            code.split_whitespace()
+                .map(move |token| (number + 1, token))
+        })
+        .collect();
+    let mut assembler = Assembler {
+        tokens,
+        pos: 0,
+        line: 0,
+        rom: Vec::new(),
+        labels: HashMap::new(),
+        constants: HashMap::new(),
+        aliases: HashMap::new(),
+        fixups: Vec::new(),
+        blocks: Vec::new(),
+    };
+    assembler.run().map_err(|err| {
+        if assembler.line > 0 {
+            format!("line {}: {err}", assembler.line)
+        } else {
+            err
+        }
+    })?;
+    Ok(assembler.rom)
+}
+
+// A jump or call to a label that hasn't been defined yet
+struct Fixup {
+    offset: usize,
+    label: String,
+    line: usize,
+}
+
+enum Block {
+    // The jump past the block, taken when the condition is false
+    If(usize),
+    // The jump from the end of the if part past the else part
+    Else(usize),
+    Loop { start: u16, breaks: Vec<usize> },
+}
+
+struct Assembler<'a> {
+    tokens: Vec<(usize, &'a str)>,
+    pos: usize,
+    // Line of the token being assembled, for errors
+    line: usize,
+    rom: Vec<u8>,
+    labels: HashMap<String, u16>,
+    constants: HashMap<String, i32>,
+    aliases: HashMap<String, u16>,
+    fixups: Vec<Fixup>,
+    blocks: Vec<Block>,
+}
+
+impl<'a> Assembler<'a> {
+    fn run(&mut self) -> Result<(), String> {
+        while self.pos < self.tokens.len() {
+            self.statement()?;
+            // Stop before addresses run past the end of memory
+            if self.rom.len() > MAX_ROM_SIZE {
+                return Err(format!("the program is over {MAX_ROM_SIZE} bytes"));
+            }
+        }
+        self.line = 0;
+
+        if !self.blocks.is_empty() {
+            return Err("missing end or again at the end of the program".to_string());
+        }
+        for fixup in std::mem::take(&mut self.fixups) {
+            let addr = *self
+                .labels
+                .get(&fixup.label)
+                .ok_or_else(|| format!("line {}: undefined label '{}'", fixup.line, fixup.label))?;
+            self.patch(fixup.offset, addr)
+                .map_err(|err| format!("line {}: {err}", fixup.line))?;
+        }
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<&'a str, String> {
+        let &(line, token) = self
+            .tokens
+            .get(self.pos)
+            .ok_or("unexpected end of program")?;
+        self.pos += 1;
+        self.line = line;
+        Ok(token)
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.pos).map(|&(_, token)| token)
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        let token = self.next()?;
+        if token != expected {
+            return Err(format!("expected '{expected}', found '{token}'"));
+        }
+        Ok(())
+    }
+
+    fn here(&self) -> u16 {
+        START_ADDR + self.rom.len() as u16
+    }
+
+    fn emit(&mut self, op: u16) {
+        self.write(&op.to_be_bytes());
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        // Octo programs start at main, which needs a jump unless it's first
+        if self.rom.is_empty() && !self.labels.contains_key("main") {
+            self.fixups.push(Fixup {
+                offset: 0,
+                label: "main".to_string(),
+                line: self.line,
+            });
+            self.rom.extend_from_slice(&[0x10, 0x00]);
+        }
+        self.rom.extend_from_slice(bytes);
+    }
+
+    // Set the address in the instruction at offset
+    fn patch(&mut self, offset: usize, addr: u16) -> Result<(), String> {
+        if addr > 0xFFF {
+            return Err(format!("address {addr:#X} is past the end of memory"));
+        }
+        self.rom[offset] |= (addr >> 8) as u8;
+        self.rom[offset + 1] = addr as u8;
+        Ok(())
+    }
+
+    // An instruction taking an address, which may be a label defined later
+    fn address_op(&mut self, op: u16, token: &str) -> Result<(), String> {
+        match self.value(token) {
+            Some(addr) if (0..=0xFFF).contains(&addr) => self.emit(op | addr as u16),
+            Some(addr) => return Err(format!("address {addr} is out of range")),
+            None if is_name(token) && self.register(token).is_none() => {
+                self.fixups.push(Fixup {
+                    offset: self.rom.len(),
+                    label: token.to_string(),
+                    line: self.line,
+                });
+                self.emit(op);
+            }
+            None => return Err(format!("expected an address, found '{token}'")),
+        }
+        Ok(())
+    }
+
+    // A jump whose address is filled in later by patch
+    fn placeholder_jump(&mut self) -> usize {
+        let offset = self.rom.len();
+        self.emit(0x1000);
+        offset
+    }
+
+    fn value(&self, token: &str) -> Option<i32> {
+        parse_number(token)
+            .or_else(|| self.constants.get(token).copied())
+            .or_else(|| self.labels.get(token).map(|&addr| addr as i32))
+    }
+
+    fn byte(&mut self) -> Result<u16, String> {
+        let token = self.next()?;
+        match self.value(token) {
+            Some(value) if (-128..=255).contains(&value) => Ok(value as u16 & 0xFF),
+            Some(value) => Err(format!("{value} doesn't fit in a byte")),
+            None => Err(format!("expected a number, found '{token}'")),
+        }
+    }
+
+    fn register(&self, token: &str) -> Option<u16> {
+        if let Some(&reg) = self.aliases.get(token) {
+            return Some(reg);
+        }
+        let digit = token.strip_prefix(['v', 'V'])?;
+        if digit.len() != 1 {
+            return None;
+        }
+        u16::from_str_radix(digit, 16).ok()
+    }
+
+    fn expect_register(&mut self) -> Result<u16, String> {
+        let token = self.next()?;
+        self.register(token)
+            .ok_or_else(|| format!("expected a register, found '{token}'"))
+    }
+
+    fn statement(&mut self) -> Result<(), String> {
+        let token = self.next()?;
+        match token {
+            ":" => {
+                let name = self.next()?;
+                if !is_name(name) || self.labels.contains_key(name) {
+                    return Err(format!("invalid or repeated label '{name}'"));
+                }
+                self.labels.insert(name.to_string(), self.here());
+            }
+            ":const" => {
+                let name = self.next()?;
+                let value = self.next()?;
+                let value = self
+                    .value(value)
+                    .ok_or_else(|| format!("expected a number, found '{value}'"))?;
+                self.constants.insert(name.to_string(), value);
+            }
+            ":alias" => {
+                let name = self.next()?;
+                let reg = self.expect_register()?;
+                self.aliases.insert(name.to_string(), reg);
+            }
+            ":byte" => {
+                let value = self.byte()?;
+                self.write(&[value as u8]);
+            }
+            "clear" => self.emit(0x00E0),
+            "return" | ";" => self.emit(0x00EE),
+            "jump" => {
+                let addr = self.next()?;
+                self.address_op(0x1000, addr)?;
+            }
+            "jump0" => {
+                let addr = self.next()?;
+                self.address_op(0xB000, addr)?;
+            }
+            "native" => {
+                let addr = self.next()?;
+                self.address_op(0x0000, addr)?;
+            }
+            "sprite" => {
+                let x = self.expect_register()?;
+                let y = self.expect_register()?;
+                let height = self.next()?;
+                let height = self
+                    .value(height)
+                    .filter(|height| (0..16).contains(height))
+                    .ok_or_else(|| format!("invalid sprite height '{height}'"))?;
+                self.emit(0xD000 | x << 8 | y << 4 | height as u16);
+            }
+            "save" => {
+                let x = self.expect_register()?;
+                self.emit(0xF055 | x << 8);
+            }
+            "load" => {
+                let x = self.expect_register()?;
+                self.emit(0xF065 | x << 8);
+            }
+            "bcd" => {
+                let x = self.expect_register()?;
+                self.emit(0xF033 | x << 8);
+            }
+            "delay" | "buzzer" => {
+                self.expect(":=")?;
+                let x = self.expect_register()?;
+                let op = if token == "delay" { 0xF015 } else { 0xF018 };
+                self.emit(op | x << 8);
+            }
+            "i" => self.index_statement()?,
+            "if" => self.if_statement()?,
+            "else" => match self.blocks.pop() {
+                Some(Block::If(skip)) => {
+                    let end = self.placeholder_jump();
+                    self.patch(skip, self.here())?;
+                    self.blocks.push(Block::Else(end));
+                }
+                _ => return Err("else without if ... begin".to_string()),
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::If(jump) | Block::Else(jump)) => self.patch(jump, self.here())?,
+                _ => return Err("end without if ... begin".to_string()),
+            },
+            "loop" => self.blocks.push(Block::Loop {
+                start: self.here(),
+                breaks: Vec::new(),
+            }),
+            "while" => {
+                let (_, inverse) = self.condition()?;
+                self.emit(inverse);
+                let jump = self.placeholder_jump();
+                let innermost = self.blocks.iter_mut().rev().find_map(|block| match block {
+                    Block::Loop { breaks, .. } => Some(breaks),
+                    _ => None,
+                });
+                innermost.ok_or("while outside of a loop")?.push(jump);
+            }
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop { start, breaks }) => {
+                    let again = self.placeholder_jump();
+                    self.patch(again, start)?;
+                    for jump in breaks {
+                        self.patch(jump, self.here())?;
+                    }
+                }
+                _ => return Err("again without loop".to_string()),
+            },
+            _ if self.register(token).is_some() => self.register_statement(token)?,
+            // Numbers on their own are data
+            _ if parse_number(token).is_some() || self.constants.contains_key(token) => {
+                self.pos -= 1;
+                let value = self.byte()?;
+                self.write(&[value as u8]);
+            }
+            _ if token.starts_with(':') => {
+                return Err(format!("unsupported directive '{token}'"));
+            }
+            _ if is_name(token) && !KEYWORDS.contains(&token) => self.address_op(0x2000, token)?,
+            _ => return Err(format!("unexpected '{token}'")),
+        }
+        Ok(())
+    }
+
+    fn index_statement(&mut self) -> Result<(), String> {
+        match self.next()? {
+            ":=" => {
+                let token = self.next()?;
+                if token == "hex" {
+                    let x = self.expect_register()?;
+                    self.emit(0xF029 | x << 8);
+                } else {
+                    self.address_op(0xA000, token)?;
+                }
+            }
+            "+=" => {
+                let x = self.expect_register()?;
+                self.emit(0xF01E | x << 8);
+            }
+            op => return Err(format!("unsupported operation 'i {op}'")),
+        }
+        Ok(())
+    }
+
+    fn register_statement(&mut self, token: &str) -> Result<(), String> {
+        let x = self.register(token).unwrap() << 8;
+        let op = self.next()?;
+        let rhs = self.peek().ok_or("unexpected end of program")?;
+
+        let instruction = if let Some(y) = self.register(rhs) {
+            self.pos += 1;
+            let kind = match op {
+                ":=" => 0x0,
+                "|=" => 0x1,
+                "&=" => 0x2,
+                "^=" => 0x3,
+                "+=" => 0x4,
+                "-=" => 0x5,
+                ">>=" => 0x6,
+                "=-" => 0x7,
+                "<<=" => 0xE,
+                _ => return Err(format!("unsupported operation '{token} {op} {rhs}'")),
+            };
+            0x8000 | x | y << 4 | kind
+        } else {
+            match (op, rhs) {
+                (":=", "random") => {
+                    self.pos += 1;
+                    0xC000 | x | self.byte()?
+                }
+                (":=", "key") => {
+                    self.pos += 1;
+                    0xF00A | x
+                }
+                (":=", "delay") => {
+                    self.pos += 1;
+                    0xF007 | x
+                }
+                (":=", _) => 0x6000 | x | self.byte()?,
+                ("+=", _) => 0x7000 | x | self.byte()?,
+                // Adding the two's complement subtracts
+                ("-=", _) => 0x7000 | x | (self.byte()? as u8).wrapping_neg() as u16,
+                _ => return Err(format!("unsupported operation '{token} {op} {rhs}'")),
+            }
+        };
+        self.emit(instruction);
+        Ok(())
+    }
+
+    fn if_statement(&mut self) -> Result<(), String> {
+        let (skip, inverse) = self.condition()?;
+        match self.next()? {
+            "then" => self.emit(skip),
+            "begin" => {
+                self.emit(inverse);
+                let jump = self.placeholder_jump();
+                self.blocks.push(Block::If(jump));
+            }
+            token => return Err(format!("expected 'then' or 'begin', found '{token}'")),
+        }
+        Ok(())
+    }
+
+    // The instruction that skips the next one when the condition is false, and
+    // the one that skips it when the condition is true
+    fn condition(&mut self) -> Result<(u16, u16), String> {
+        let x = self.expect_register()? << 8;
+        let op = self.next()?;
+        let (equal, not_equal) = match op {
+            "key" => return Ok((0xE0A1 | x, 0xE09E | x)),
+            "-key" => return Ok((0xE09E | x, 0xE0A1 | x)),
+            "==" | "!=" => {
+                let rhs = self.peek().ok_or("unexpected end of program")?;
+                match self.register(rhs) {
+                    Some(y) => {
+                        self.pos += 1;
+                        (0x9000 | x | y << 4, 0x5000 | x | y << 4)
+                    }
+                    None => {
+                        let value = self.byte()?;
+                        (0x4000 | x | value, 0x3000 | x | value)
+                    }
+                }
+            }
+            _ => return Err(format!("unsupported condition '{op}'")),
+        };
+        Ok(if op == "==" {
+            (equal, not_equal)
+        } else {
+            (not_equal, equal)
+        })
+    }
+}
+
+const KEYWORDS: [&str; 8] = ["then", "begin", "key", "-key", "hex", "random", ":=", "+="];
+
+fn is_name(token: &str) -> bool {
+    token
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_')
+        && token
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+// Decimal, 0x hex or 0b binary, optionally negative
+fn parse_number(token: &str) -> Option<i32> {
+    let (negative, digits) = match token.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, token),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i32::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i32::from_str_radix(binary, 2).ok()?
+    } else {
+        digits.parse().ok()?
+    };
+    Some(if negative { -value } else { value })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(rom: &[u8]) -> Vec<u16> {
+        rom.chunks(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect()
+    }
+
+    #[test]
+    fn assembles_every_instruction() {
+        let source = "
+            : main
+                clear
+                v0 := 5  v1 := v0  v2 := random 0xFF  v3 := key  v4 := delay
+                v0 += 1  v0 -= 1  v0 += v1  v0 -= v1  v0 =- v1
+                v0 |= v1  v0 &= v1  v0 ^= v1  v0 >>= v1  v0 <<= v1
+                i := face  i := hex v5  i += v6
+                sprite v0 v1 5  save v7  load v8  bcd v9
+                delay := va  buzzer := vb
+                jump0 0x300  native 0x123  sub  jump main
+            : sub ;
+            : face 0b11110000 -1
+        ";
+        assert_eq!(
+            words(&assemble(source).unwrap()),
+            vec![
+                0x00E0, 0x6005, 0x8100, 0xC2FF, 0xF30A, 0xF407, //
+                0x7001, 0x70FF, 0x8014, 0x8015, 0x8017, //
+                0x8011, 0x8012, 0x8013, 0x8016, 0x801E, //
+                0xA23C, 0xF529, 0xF61E, //
+                0xD015, 0xF755, 0xF865, 0xF933, //
+                0xFA15, 0xFB18, //
+                0xB300, 0x0123, 0x223A, 0x1200, //
+                0x00EE, 0xF0FF,
+            ]
+        );
+    }
+
+    #[test]
+    fn jumps_to_main_unless_it_comes_first() {
+        let rom = assemble(": data 1 2 : main jump main").unwrap();
+        assert_eq!(rom, vec![0x12, 0x04, 1, 2, 0x12, 0x04]);
+        assert!(assemble(": start clear")
+            .unwrap_err()
+            .contains("undefined label 'main'"));
+    }
+
+    #[test]
+    fn assembles_control_flow() {
+        let source = "
+            :alias x v1
+            :const LIMIT 10
+            : main
+                if x == LIMIT then x := 0
+                if x != v2 begin
+                    x += 1
+                else
+                    x -= 1
+                end
+                loop
+                    while v3 key
+                    if v3 -key then return
+                again
+        ";
+        assert_eq!(
+            words(&assemble(source).unwrap()),
+            vec![
+                0x410A, 0x6100, // if then
+                0x9120, 0x120C, 0x7101, 0x120E, 0x71FF, // if begin else end
+                0xE39E, 0x1218, 0xE39E, 0x00EE, 0x120E, // loop while again
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_errors_with_line_numbers() {
+        assert_eq!(
+            assemble(": main\n  v0 := 300"),
+            Err("line 2: 300 doesn't fit in a byte".to_string())
+        );
+        assert!(assemble(": main loop").unwrap_err().contains("again"));
+        assert!(assemble(": main :macro x { }")
+            .unwrap_err()
+            .contains("unsupported directive ':macro'"));
+        assert!(assemble(": main\n\nend").unwrap_err().starts_with("line 3"));
+    }
+
+    #[test]
+    fn rejects_programs_too_big_for_memory() {
+        let data = "0 ".repeat(65_000);
+        let source = format!(": main {data} : end jump end");
+        assert!(assemble(&source)
+            .unwrap_err()
+            .contains(&format!("over {MAX_ROM_SIZE} bytes")));
+
+        // A label just past the last byte of memory can't be jumped to
+        let data = "0 ".repeat(MAX_ROM_SIZE - 2);
+        let source = format!(": main jump end {data} : end");
+        assert_eq!(
+            assemble(&source),
+            Err("line 1: address 0x1000 is past the end of memory".to_string())
+        );
+    }
+}
@@ -0,0 +1,143 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Emulated frames per second, which is also how often the timers tick
+pub const FRAME_RATE: u64 = 60;
+
+// After a stall, at most this many frames are run to catch up. Anything
+// beyond that is dropped, so the game slows down instead of fast forwarding.
+const MAX_CATCH_UP: u64 = 6;
+
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+// Where the time comes from, so pacing can be tested without waiting
+pub trait Clock {
+    // Time since some fixed point, which only ever goes forward
+    fn now(&self) -> Duration;
+    fn sleep(&mut self, duration: Duration);
+}
+
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        SystemClock {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        SystemClock::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+// Works out how many frames are due to keep emulation at exactly 60 Hz of
+// real time, however often it is asked
+pub struct Pacer {
+    start: Duration,
+    // Frames handed out since start, the next one is due at frame / 60 s
+    frames: u64,
+    dropped: u64,
+}
+
+impl Pacer {
+    pub fn new(now: Duration) -> Self {
+        Pacer {
+            start: now,
+            frames: 0,
+            dropped: 0,
+        }
+    }
+
+    // Deadlines are computed from the start rather than by adding up frame
+    // times, so 60 Hz doesn't drift from rounding a frame to whole nanoseconds.
+    // They round up, so a frame is always due once its deadline has passed.
+    fn deadline(&self, frame: u64) -> Duration {
+        self.start + Duration::from_nanos((frame * NANOS_PER_SECOND).div_ceil(FRAME_RATE))
+    }
+
+    // The number of frames that should run now
+    pub fn frames_due(&mut self, now: Duration) -> u64 {
+        let elapsed = now.saturating_sub(self.start).as_nanos() as u64;
+        let total = elapsed * FRAME_RATE / NANOS_PER_SECOND + 1;
+        let due = total.saturating_sub(self.frames);
+        if due > MAX_CATCH_UP {
+            // Start counting again from now, forgetting the missed frames
+            self.dropped += due - MAX_CATCH_UP;
+            self.start = now;
+            self.frames = 1;
+            return MAX_CATCH_UP;
+        }
+        self.frames += due;
+        due
+    }
+
+    // How long until the next frame is due
+    pub fn until_next(&self, now: Duration) -> Duration {
+        self.deadline(self.frames).saturating_sub(now)
+    }
+
+    // Frames skipped because emulation couldn't keep up
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn runs_sixty_frames_a_second_however_often_polled() {
+        // A 144 Hz display asks far more often than frames are due
+        let mut pacer = Pacer::new(Duration::ZERO);
+        let frames: u64 = (0..144)
+            .map(|i| pacer.frames_due(Duration::from_nanos(i * NANOS_PER_SECOND / 144)))
+            .sum();
+        assert_eq!(frames, 60);
+
+        // And a 30 Hz one less often, getting two frames at a time
+        let mut pacer = Pacer::new(Duration::ZERO);
+        let batches: Vec<_> = (0..4).map(|i| pacer.frames_due(ms(i * 34))).collect();
+        assert_eq!(batches, vec![1, 2, 2, 2]);
+        assert_eq!(pacer.dropped(), 0);
+    }
+
+    #[test]
+    fn waits_until_the_next_deadline() {
+        let mut pacer = Pacer::new(ms(1000));
+        assert_eq!(pacer.frames_due(ms(1000)), 1);
+        assert_eq!(pacer.until_next(ms(1010)), Duration::from_nanos(6_666_667));
+        assert_eq!(pacer.frames_due(ms(1010)), 0);
+    }
+
+    #[test]
+    fn stalls_catch_up_a_little_then_drop_frames() {
+        let mut pacer = Pacer::new(Duration::ZERO);
+        assert_eq!(pacer.frames_due(Duration::ZERO), 1);
+        // Half a second without running: 30 frames were due
+        assert_eq!(pacer.frames_due(ms(500)), MAX_CATCH_UP);
+        assert_eq!(pacer.dropped(), 30 - MAX_CATCH_UP);
+        // Then back to normal from the new point in time
+        assert_eq!(pacer.frames_due(ms(510)), 0);
+        assert_eq!(pacer.frames_due(ms(517)), 1);
+    }
+}
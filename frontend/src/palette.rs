@@ -0,0 +1,252 @@
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Rgb { r, g, b }
+    }
+
+    const fn hex(rgb: u32) -> Self {
+        Rgb::new((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)
+    }
+
+    // Mix towards another color, where t = 0 is self and t = 1 is other
+    pub fn lerp(self, other: Rgb, t: f32) -> Rgb {
+        let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+        Rgb::new(
+            mix(self.r, other.r),
+            mix(self.g, other.g),
+            mix(self.b, other.b),
+        )
+    }
+}
+
+impl fmt::Display for Rgb {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02X}{:02X}{:02X}", self.r, self.g, self.b)
+    }
+}
+
+// Accepts "#RRGGBB", "RRGGBB" and the "#RGB" shorthand
+impl FromStr for Rgb {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits = s.strip_prefix('#').unwrap_or(s);
+        // from_str_radix would also take a sign
+        if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("invalid color '{s}', expected #RRGGBB"));
+        }
+        let expanded: String = match digits.len() {
+            3 => digits.chars().flat_map(|c| [c, c]).collect(),
+            6 => digits.to_string(),
+            _ => return Err(format!("invalid color '{s}', expected #RRGGBB")),
+        };
+        u32::from_str_radix(&expanded, 16)
+            .map(Rgb::hex)
+            .map_err(|_| format!("invalid color '{s}', expected #RRGGBB"))
+    }
+}
+
+// Colors indexed by which bit planes a pixel is lit in: 0 is the background,
+// 1 and 2 the two planes, and 3 where both overlap. Plain Chip-8 only ever
+// draws to the first plane.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Palette {
+    pub name: String,
+    pub colors: [Rgb; 4],
+}
+
+const PRESETS: [(&str, [u32; 4]); 5] = [
+    ("classic", [0x000000, 0xFFFFFF, 0xAAAAAA, 0x555555]),
+    ("green", [0x001100, 0x33FF33, 0x1A8C1A, 0x99FF99]),
+    ("amber", [0x1A0F00, 0xFFB000, 0xA06E00, 0xFFD870]),
+    ("lcd", [0x9BBC0F, 0x0F380F, 0x306230, 0x8BAC0F]),
+    ("octo", [0x996600, 0xFFCC00, 0xFF6600, 0x662200]),
+];
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::presets().remove(0)
+    }
+}
+
+impl Palette {
+    pub fn presets() -> Vec<Palette> {
+        PRESETS
+            .iter()
+            .map(|(name, colors)| Palette {
+                name: name.to_string(),
+                colors: colors.map(Rgb::hex),
+            })
+            .collect()
+    }
+
+    pub fn background(&self) -> Rgb {
+        self.colors[0]
+    }
+
+    pub fn foreground(&self) -> Rgb {
+        self.colors[1]
+    }
+
+    pub fn render(&self, display: &[bool], width: usize, height: usize) -> Framebuffer {
+        Framebuffer {
+            width,
+            height,
+            background: self.background(),
+            pixels: display
+                .iter()
+                .map(|&lit| self.colors[lit as usize])
+                .collect(),
+        }
+    }
+
+    // Like render, but with each pixel's brightness given from 0 to 1
+    pub fn render_levels(&self, levels: &[f32], width: usize, height: usize) -> Framebuffer {
+        Framebuffer {
+            width,
+            height,
+            background: self.background(),
+            pixels: levels
+                .iter()
+                .map(|&level| self.background().lerp(self.foreground(), level))
+                .collect(),
+        }
+    }
+}
+
+// Either a preset name, or two to four comma separated colors in plane order.
+// Missing plane colors fall back to the foreground color.
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(preset) = Palette::presets().into_iter().find(|p| p.name == s) {
+            return Ok(preset);
+        }
+        if !s.contains(',') {
+            let names: Vec<_> = PRESETS.iter().map(|(name, _)| *name).collect();
+            return Err(format!(
+                "unknown palette '{s}', expected one of {} or a list of colors",
+                names.join(", ")
+            ));
+        }
+
+        let colors = s
+            .split(',')
+            .map(|color| color.trim().parse())
+            .collect::<Result<Vec<Rgb>, _>>()?;
+        if !(2..=4).contains(&colors.len()) {
+            return Err(format!("palette '{s}' needs between 2 and 4 colors"));
+        }
+        let mut palette = [colors[1]; 4];
+        palette[..colors.len()].copy_from_slice(&colors);
+        Ok(Palette {
+            name: "custom".to_string(),
+            colors: palette,
+        })
+    }
+}
+
+// A rendered screen image, one color per Chip-8 pixel
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Framebuffer {
+    pub width: usize,
+    pub height: usize,
+    // Color of unlit pixels, for filling any space around the image
+    pub background: Rgb,
+    pub pixels: Vec<Rgb>,
+}
+
+impl Framebuffer {
+    pub fn pixel(&self, x: usize, y: usize) -> Rgb {
+        self.pixels[x + self.width * y]
+    }
+
+    // Nearest neighbour scaling by a whole factor
+    pub fn scaled(&self, scale: usize) -> Framebuffer {
+        let width = self.width * scale;
+        let height = self.height * scale;
+        let pixels = (0..width * height)
+            .map(|i| self.pixel((i % width) / scale, (i / width) / scale))
+            .collect();
+        Framebuffer {
+            width,
+            height,
+            background: self.background,
+            pixels,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_round_trip() {
+        let color = Rgb::new(0x12, 0xAB, 0xF0);
+        assert_eq!(color.to_string(), "#12ABF0");
+        assert_eq!(color.to_string().parse(), Ok(color));
+        assert_eq!("12abf0".parse(), Ok(color));
+        assert_eq!("#F80".parse(), Ok(Rgb::new(0xFF, 0x88, 0x00)));
+    }
+
+    #[test]
+    fn bad_colors_are_turned_down() {
+        for bad in [
+            "", "#", "#+FFFFF", "+FFF", "-12345", "GGGGGG", "#12345", "#1234567",
+        ] {
+            assert_eq!(
+                bad.parse::<Rgb>(),
+                Err(format!("invalid color '{bad}', expected #RRGGBB"))
+            );
+        }
+    }
+
+    #[test]
+    fn palettes_are_presets_or_colors() {
+        for preset in Palette::presets() {
+            assert_eq!(preset.name.parse(), Ok(preset));
+        }
+
+        let palette: Palette = "#000, #FFF, #F00".parse().unwrap();
+        assert_eq!(palette.name, "custom");
+        assert_eq!(
+            palette.colors,
+            [
+                Rgb::new(0, 0, 0),
+                Rgb::new(255, 255, 255),
+                Rgb::new(255, 0, 0),
+                Rgb::new(255, 255, 255)
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_palettes_are_turned_down() {
+        assert!(Palette::from_str("neon")
+            .unwrap_err()
+            .starts_with("unknown palette 'neon', expected one of classic, green"));
+        assert_eq!(
+            Palette::from_str("#000"),
+            Err("unknown palette '#000', expected one of classic, green, amber, lcd, octo or a list of colors".to_string())
+        );
+        assert_eq!(
+            Palette::from_str("#000,#+FFFFF"),
+            Err("invalid color '#+FFFFF', expected #RRGGBB".to_string())
+        );
+        assert_eq!(
+            Palette::from_str("#000,#111,#222,#333,#444"),
+            Err("palette '#000,#111,#222,#333,#444' needs between 2 and 4 colors".to_string())
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use crate::FRAME_RATE;
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+// Emulated time covered by a single frame
+const FRAME_TIME: Duration = Duration::from_nanos(1_000_000_000 / FRAME_RATE);
+
+// How long lit pixels stay visible after being erased. Games XOR sprites off
+// and back on every frame, so without this they flicker.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Persistence {
+    #[default]
+    Off,
+    // A pixel is lit if it was lit in either of the last two frames
+    Blend,
+    // Pixels fade out like CRT phosphor, losing half their brightness per half-life
+    Decay {
+        half_life: Duration,
+    },
+}
+
+impl fmt::Display for Persistence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Persistence::Off => f.write_str("off"),
+            Persistence::Blend => f.write_str("blend"),
+            Persistence::Decay { half_life } => write!(f, "decay:{}", half_life.as_millis()),
+        }
+    }
+}
+
+// "off", "blend", "decay", or "decay:<half-life in milliseconds>"
+impl FromStr for Persistence {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "off" => Ok(Persistence::Off),
+            None if s == "blend" => Ok(Persistence::Blend),
+            None if s == "decay" => Ok(Persistence::Decay {
+                half_life: FRAME_TIME,
+            }),
+            Some(("decay", ms)) => match ms.parse() {
+                Ok(ms) if ms > 0 => Ok(Persistence::Decay {
+                    half_life: Duration::from_millis(ms),
+                }),
+                _ => Err(format!("invalid half-life '{ms}', expected milliseconds")),
+            },
+            _ => Err(format!(
+                "unknown persistence '{s}', expected off, blend, decay or decay:<ms>"
+            )),
+        }
+    }
+}
+
+// Keeps the brightness of every pixel across frames
+pub struct Phosphor {
+    persistence: Persistence,
+    previous: Vec<bool>,
+    levels: Vec<f32>,
+}
+
+impl Phosphor {
+    pub fn new(persistence: Persistence) -> Self {
+        Phosphor {
+            persistence,
+            previous: Vec::new(),
+            levels: Vec::new(),
+        }
+    }
+
+    pub fn persistence(&self) -> Persistence {
+        self.persistence
+    }
+
+    // Feed the display as it is at the end of an emulated frame
+    pub fn update(&mut self, display: &[bool]) {
+        if self.levels.len() != display.len() {
+            self.previous = vec![false; display.len()];
+            self.levels = vec![0.0; display.len()];
+        }
+
+        match self.persistence {
+            Persistence::Off => {
+                for (level, &lit) in self.levels.iter_mut().zip(display) {
+                    *level = lit as u8 as f32;
+                }
+            }
+            Persistence::Blend => {
+                for ((level, &lit), &was_lit) in
+                    self.levels.iter_mut().zip(display).zip(&self.previous)
+                {
+                    *level = (lit || was_lit) as u8 as f32;
+                }
+            }
+            Persistence::Decay { half_life } => {
+                let fade = 0.5f32.powf(FRAME_TIME.as_secs_f32() / half_life.as_secs_f32());
+                for (level, &lit) in self.levels.iter_mut().zip(display) {
+                    *level = if lit { 1.0 } else { *level * fade };
+                }
+            }
+        }
+        self.previous.copy_from_slice(display);
+    }
+
+    // Brightness of every pixel from 0 to 1, empty until the first update
+    pub fn levels(&self) -> &[f32] {
+        &self.levels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_modes() {
+        assert_eq!("off".parse(), Ok(Persistence::Off));
+        assert_eq!("blend".parse(), Ok(Persistence::Blend));
+        assert_eq!(
+            "decay:100".parse(),
+            Ok(Persistence::Decay {
+                half_life: Duration::from_millis(100)
+            })
+        );
+        assert!("decay:0".parse::<Persistence>().is_err());
+        assert!("glow".parse::<Persistence>().is_err());
+    }
+
+    #[test]
+    fn blend_keeps_pixels_for_one_extra_frame() {
+        let mut phosphor = Phosphor::new(Persistence::Blend);
+        phosphor.update(&[true, false]);
+        phosphor.update(&[false, true]);
+        assert_eq!(phosphor.levels(), &[1.0, 1.0]);
+        phosphor.update(&[false, false]);
+        assert_eq!(phosphor.levels(), &[0.0, 1.0]);
+        phosphor.update(&[false, false]);
+        assert_eq!(phosphor.levels(), &[0.0, 0.0]);
+    }
+
+    #[test]
+    fn decay_halves_every_half_life() {
+        let half_life = FRAME_TIME * 2;
+        let mut phosphor = Phosphor::new(Persistence::Decay { half_life });
+        phosphor.update(&[true, false]);
+        phosphor.update(&[false, false]);
+        phosphor.update(&[false, false]);
+        assert!((phosphor.levels()[0] - 0.5).abs() < 1e-4);
+        assert_eq!(phosphor.levels()[1], 0.0);
+
+        // Relighting a pixel brings it straight back to full brightness
+        phosphor.update(&[true, false]);
+        assert_eq!(phosphor.levels()[0], 1.0);
+    }
+}
@@ -0,0 +1,159 @@
+use crate::Framebuffer;
+
+use std::io::{self, Write};
+
+// GIF delays are in hundredths of a second
+const CENTISECONDS_PER_FRAME: f64 = 100.0 / 60.0;
+// Browsers and most viewers slow anything shorter than this right down
+const MIN_DELAY: u64 = 2;
+
+// Encodes 60 Hz frames into an animated GIF as they come in. A frame that
+// repeats the previous one isn't written again, the previous one is just
+// shown for longer, so static screens cost almost nothing. Frames that change
+// faster than GIFs can play are merged, keeping the latest.
+pub struct GifRecorder<W: Write> {
+    encoder: gif::Encoder<W>,
+    // The last distinct frame, not written until we know how long it lasts
+    pending: Option<Framebuffer>,
+    // Frames pushed so far
+    frames: u64,
+    // Total delay written so far
+    written: u64,
+}
+
+impl<W: Write> GifRecorder<W> {
+    pub fn new(writer: W, width: usize, height: usize) -> io::Result<Self> {
+        let mut encoder =
+            gif::Encoder::new(writer, width as u16, height as u16, &[]).map_err(gif_error)?;
+        encoder
+            .set_repeat(gif::Repeat::Infinite)
+            .map_err(gif_error)?;
+        Ok(GifRecorder {
+            encoder,
+            pending: None,
+            frames: 0,
+            written: 0,
+        })
+    }
+
+    // Add the next frame, shown 1/60th of a second after the previous one
+    pub fn push(&mut self, frame: &Framebuffer) -> io::Result<()> {
+        if self.pending.as_ref() != Some(frame) {
+            if self.pending_delay() >= MIN_DELAY {
+                self.flush_pending()?;
+            }
+            self.pending = Some(frame.clone());
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    // Write out the last frame and return the writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.flush_pending()?;
+        self.encoder.into_inner()
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        self.frames
+    }
+
+    // How long the pending frame has been shown. Delays are rounded from the
+    // frame's end time rather than its length, so the rounding errors don't
+    // add up over a long recording.
+    fn pending_delay(&self) -> u64 {
+        let end = (self.frames as f64 * CENTISECONDS_PER_FRAME).round() as u64;
+        end - self.written
+    }
+
+    fn flush_pending(&mut self) -> io::Result<()> {
+        let Some(frame) = self.pending.take() else {
+            return Ok(());
+        };
+
+        // Only the last frame can be too short, and it's stretched instead
+        let mut remaining = self.pending_delay().max(MIN_DELAY);
+        self.written += remaining;
+
+        let pixels: Vec<u8> = frame.pixels.iter().flat_map(|p| [p.r, p.g, p.b]).collect();
+        let mut gif_frame =
+            gif::Frame::from_rgb_speed(frame.width as u16, frame.height as u16, &pixels, 10);
+        // Very long stills are split up, as a single delay can't go over 65535
+        while remaining > 0 {
+            let mut delay = remaining.min(u16::MAX as u64);
+            // Leaving enough for the last piece to play at full speed
+            if remaining > delay && remaining - delay < MIN_DELAY {
+                delay -= MIN_DELAY;
+            }
+            gif_frame.delay = delay as u16;
+            self.encoder.write_frame(&gif_frame).map_err(gif_error)?;
+            remaining -= delay;
+        }
+        Ok(())
+    }
+}
+
+fn gif_error(err: gif::EncodingError) -> io::Error {
+    match err {
+        gif::EncodingError::Io(err) => err,
+        err => io::Error::new(io::ErrorKind::InvalidData, err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Palette;
+
+    fn frame(lit: bool) -> Framebuffer {
+        Palette::presets()[0].render(&[lit; 4], 2, 2)
+    }
+
+    // Decode the GIF and return every frame's delay
+    fn delays(data: &[u8]) -> Vec<u16> {
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::RGBA);
+        let mut decoder = options.read_info(data).unwrap();
+        let mut delays = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            delays.push(frame.delay);
+        }
+        delays
+    }
+
+    #[test]
+    fn duplicate_frames_extend_the_previous_one() {
+        let mut recorder = GifRecorder::new(Vec::new(), 2, 2).unwrap();
+        for lit in [false, false, false, true, false, false] {
+            recorder.push(&frame(lit)).unwrap();
+        }
+        assert_eq!(recorder.frame_count(), 6);
+        // 3, 1 and 2 frames at 60 Hz end at 5, 7 and 10 hundredths of a second
+        assert_eq!(delays(&recorder.finish().unwrap()), vec![5, 2, 3]);
+    }
+
+    #[test]
+    fn fast_changes_are_merged_into_playable_frames() {
+        let mut recorder = GifRecorder::new(Vec::new(), 2, 2).unwrap();
+        for i in 0..60 {
+            recorder.push(&frame(i % 2 == 0)).unwrap();
+        }
+        let second = delays(&recorder.finish().unwrap());
+        assert!(second.iter().all(|&delay| delay >= 2), "{second:?}");
+        assert_eq!(second.iter().map(|&d| d as u32).sum::<u32>(), 100);
+
+        // A single changed frame at the end is stretched to be playable
+        let mut recorder = GifRecorder::new(Vec::new(), 2, 2).unwrap();
+        for lit in [false, false, true] {
+            recorder.push(&frame(lit)).unwrap();
+        }
+        assert_eq!(delays(&recorder.finish().unwrap()), vec![3, 2]);
+
+        // So is the end of a still too long for one frame
+        let mut recorder = GifRecorder::new(Vec::new(), 2, 2).unwrap();
+        for i in 0..39_326 {
+            recorder.push(&frame(i < 4)).unwrap();
+        }
+        assert_eq!(delays(&recorder.finish().unwrap()), vec![7, 65_533, 3]);
+    }
+}
@@ -0,0 +1,290 @@
+use crate::{Palette, FRAME_RATE};
+
+use chip8::Quirks;
+
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+// A few well known public domain ROMs, in the format of the community
+// chip-8-database's programs.json. Replacing this file with upstream's brings
+// in every game it knows about.
+const BUNDLED: &str = include_str!("../romdb/programs.json");
+
+// The platforms from the database this emulator can act like, with the quirks
+// each one has unless a ROM says otherwise
+const PLATFORMS: [(&str, Quirks); 8] = [
+    ("modernChip8", Quirks::MODERN),
+    ("originalChip8", Quirks::VIP),
+    ("hybridVIP", Quirks::VIP),
+    ("chip48", Quirks::SCHIP),
+    ("superchip1", Quirks::SCHIP),
+    ("superchip", Quirks::SCHIP),
+    ("megachip8", Quirks::SCHIP),
+    ("xochip", Quirks::XOCHIP),
+];
+
+// What the database knows about one ROM
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RomInfo {
+    pub title: String,
+    pub quirks: Option<Quirks>,
+    pub ticks_per_frame: Option<u32>,
+    pub palette: Option<Palette>,
+    // The Chip-8 key for each direction or button the game uses, named as in
+    // the database: up, down, left, right, a, b, player2Up...
+    pub keys: BTreeMap<String, usize>,
+}
+
+impl RomInfo {
+    pub fn instructions_per_second(&self) -> Option<u32> {
+        self.ticks_per_frame
+            .and_then(|ticks| ticks.checked_mul(FRAME_RATE as u32))
+    }
+}
+
+// A tickrate from a database or cartridge, dropped when it's zero or too fast
+// to count in instructions per second
+pub(crate) fn tickrate(ticks: Option<u32>) -> Option<u32> {
+    ticks.filter(|&ticks| ticks > 0 && ticks.checked_mul(FRAME_RATE as u32).is_some())
+}
+
+#[derive(Default)]
+pub struct RomDb {
+    // Keyed by lowercase hex SHA-1 of the ROM
+    roms: HashMap<String, RomInfo>,
+}
+
+impl RomDb {
+    pub fn bundled() -> Self {
+        let mut db = RomDb::default();
+        // Checked by the tests, so this can't fail
+        db.merge(BUNDLED).unwrap();
+        db
+    }
+
+    // Add the games from another database, replacing any ROMs both know
+    pub fn merge(&mut self, json: &str) -> Result<(), String> {
+        let programs: Vec<Program> = serde_json::from_str(json).map_err(|err| err.to_string())?;
+        for program in programs {
+            for (hash, rom) in program.roms {
+                let info = rom.to_info(&program.title);
+                self.roms.insert(hash.to_ascii_lowercase(), info);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn lookup(&self, rom: &[u8]) -> Option<&RomInfo> {
+        self.roms.get(&sha1_hex(rom))
+    }
+
+    pub fn len(&self) -> usize {
+        self.roms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roms.is_empty()
+    }
+}
+
+pub fn sha1_hex(data: &[u8]) -> String {
+    Sha1::digest(data)
+        .iter()
+        .fold(String::with_capacity(40), |mut hex, byte| {
+            write!(hex, "{byte:02x}").unwrap();
+            hex
+        })
+}
+
+// Only the parts of the schema that matter here, everything else is ignored
+#[derive(Deserialize)]
+struct Program {
+    title: String,
+    #[serde(default)]
+    roms: BTreeMap<String, Rom>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Rom {
+    #[serde(default)]
+    platforms: Vec<String>,
+    #[serde(default)]
+    quirky_platforms: BTreeMap<String, QuirkFlags>,
+    tickrate: Option<u32>,
+    #[serde(default)]
+    keys: BTreeMap<String, usize>,
+    colors: Option<Colors>,
+}
+
+// How the database names quirks. Each one is true when the platform behaves
+// that way, and missing when it's the same as the platform's default.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QuirkFlags {
+    // Shift VX in place, ignoring VY
+    shift: Option<bool>,
+    memory_increment_by_x: Option<bool>,
+    memory_leave_i_unchanged: Option<bool>,
+    // Sprites wrap around instead of being cut off
+    wrap: Option<bool>,
+    jump: Option<bool>,
+    vblank: Option<bool>,
+    logic: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct Colors {
+    #[serde(default)]
+    pixels: Vec<String>,
+}
+
+impl Rom {
+    fn to_info(&self, title: &str) -> RomInfo {
+        RomInfo {
+            title: title.to_string(),
+            quirks: self.quirks(),
+            ticks_per_frame: tickrate(self.tickrate),
+            palette: self
+                .colors
+                .as_ref()
+                .and_then(|colors| colors.pixels.join(",").parse().ok()),
+            keys: self
+                .keys
+                .iter()
+                .filter(|&(_, &key)| key < 16)
+                .map(|(name, &key)| (name.clone(), key))
+                .collect(),
+        }
+    }
+
+    // The quirks of the first platform the ROM runs on that this emulator knows
+    fn quirks(&self) -> Option<Quirks> {
+        let (name, mut quirks) = self.platforms.iter().find_map(|name| {
+            PLATFORMS
+                .iter()
+                .find(|(platform, _)| platform == name)
+                .copied()
+        })?;
+        if let Some(flags) = self.quirky_platforms.get(name) {
+            let set = |quirk: &mut bool, flag: Option<bool>| {
+                if let Some(on) = flag {
+                    *quirk = on;
+                }
+            };
+            set(&mut quirks.shift_uses_vy, flags.shift.map(|on| !on));
+            set(
+                &mut quirks.memory_increments_i,
+                flags.memory_leave_i_unchanged.map(|on| !on),
+            );
+            // Incrementing by X instead of X + 1 is close enough
+            if flags.memory_increment_by_x == Some(true) {
+                quirks.memory_increments_i = true;
+            }
+            set(&mut quirks.clip_sprites, flags.wrap.map(|on| !on));
+            set(&mut quirks.jump_uses_vx, flags.jump);
+            set(&mut quirks.display_wait, flags.vblank);
+            set(&mut quirks.logic_resets_vf, flags.logic);
+        }
+        Some(quirks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PONG: &str = r##"[{
+        "title": "Pong",
+        "authors": ["Paul Vervalin"],
+        "roms": {
+            "2D9FF7E3D8C96F1B4F5D4C9A2D1F0E9C8B7A6F5E": {
+                "file": "pong.ch8",
+                "platforms": ["superchip", "originalChip8"],
+                "quirkyPlatforms": {"superchip": {"wrap": true, "logic": true}},
+                "tickrate": 15,
+                "keys": {"player1Up": 1, "player1Down": 4, "up": 12, "bad": 16},
+                "colors": {"pixels": ["#000000", "#ff4040"], "buzzer": "#ffaa00"}
+            }
+        }
+    }]"##;
+
+    #[test]
+    fn hashes_like_sha1sum() {
+        assert_eq!(sha1_hex(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+    }
+
+    // Whatever is bundled has to parse, and be keyed by SHA-1 so lookups can
+    // find it
+    #[test]
+    fn bundled_roms_are_keyed_by_sha1() {
+        let db = RomDb::bundled();
+        assert!(!db.is_empty());
+        for (hash, info) in &db.roms {
+            assert_eq!(hash.len(), 40, "{hash}");
+            assert!(hash.chars().all(|c| c.is_ascii_hexdigit()), "{hash}");
+            assert!(!info.title.is_empty(), "{hash} has no title");
+        }
+    }
+
+    #[test]
+    fn bundled_roms_are_found() {
+        let maze = [
+            0x60, 0x00, 0x61, 0x00, 0xA2, 0x22, 0xC2, 0x01, 0x32, 0x01, 0xA2, 0x1E, 0xD0, 0x14,
+            0x70, 0x04, 0x30, 0x40, 0x12, 0x04, 0x60, 0x00, 0x71, 0x04, 0x31, 0x20, 0x12, 0x04,
+            0x12, 0x1C, 0x80, 0x40, 0x20, 0x10, 0x20, 0x40, 0x80, 0x10,
+        ];
+        let db = RomDb::bundled();
+        let info = db.lookup(&maze).unwrap();
+        assert_eq!(info.title, "Maze");
+        assert_eq!(info.quirks, Some(Quirks::VIP));
+    }
+
+    #[test]
+    fn reads_the_community_schema() {
+        let mut db = RomDb::default();
+        db.merge(PONG).unwrap();
+        let info = &db.roms["2d9ff7e3d8c96f1b4f5d4c9a2d1f0e9c8b7a6f5e"];
+        assert_eq!(info.title, "Pong");
+        assert_eq!(info.ticks_per_frame, Some(15));
+        assert_eq!(
+            info.quirks,
+            Some(Quirks {
+                clip_sprites: false,
+                logic_resets_vf: true,
+                ..Quirks::SCHIP
+            })
+        );
+        assert_eq!(
+            info.palette.as_ref().map(|palette| palette.colors[1]),
+            Some("#FF4040".parse().unwrap())
+        );
+        assert_eq!(info.keys.len(), 3);
+        assert_eq!(info.keys["up"], 0xC);
+        assert!(db.merge("{}").is_err());
+
+        // Too fast to count per second
+        let json = PONG.replace("\"tickrate\": 15", "\"tickrate\": 100000000");
+        let mut db = RomDb::default();
+        db.merge(&json).unwrap();
+        let info = &db.roms["2d9ff7e3d8c96f1b4f5d4c9a2d1f0e9c8b7a6f5e"];
+        assert_eq!(info.ticks_per_frame, None);
+    }
+
+    #[test]
+    fn looks_roms_up_by_content() {
+        let rom = [0x12, 0x00];
+        let json = format!(
+            r#"[{{"title": "Loop", "roms": {{"{}": {{}}}}}}]"#,
+            sha1_hex(&rom)
+        );
+        let mut db = RomDb::bundled();
+        db.merge(&json).unwrap();
+        assert_eq!(db.lookup(&rom).unwrap().title, "Loop");
+        assert_eq!(db.lookup(&rom).unwrap().quirks, None);
+        assert!(db.lookup(&[0x12, 0x02]).is_none());
+    }
+}
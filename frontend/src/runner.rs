@@ -0,0 +1,1538 @@
+use crate::{
+    default_quirks, list_roms, Action, Browser, Clock, Filters, Framebuffer, GifRecorder, Library,
+    Machine, Movie, Navigation, Pacer, Palette, Persistence, Phosphor, ReloadMode, RomChoice,
+    RomInfo, Screenshots, SystemClock, Watch, DEFAULT_INSTRUCTIONS_PER_SECOND,
+};
+
+use chip8::*;
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+// Emulation speed steps, as a multiple of the normal 60 frames per second
+const SPEEDS: [f64; 8] = [0.25, 0.5, 0.75, 1.0, 1.5, 2.0, 3.0, 4.0];
+const NORMAL_SPEED: usize = 3;
+// Speeds used while turbo or slow motion is on, whatever the speed step
+const TURBO_SPEED: f64 = 8.0;
+const SLOW_MOTION_SPEED: f64 = 0.25;
+// Instructions per frame steps for the IPF hotkeys
+const IPF_STEPS: [usize; 14] = [1, 2, 3, 5, 7, 10, 15, 20, 30, 50, 100, 200, 500, 1000];
+
+pub trait Video {
+    fn draw(&mut self, frame: &Framebuffer, machine: &Machine);
+    fn set_title(&mut self, _title: &str) {}
+    fn toggle_fullscreen(&mut self) {}
+}
+
+pub trait Audio {
+    fn set_beeping(&mut self, beeping: bool);
+}
+
+pub trait Input {
+    // Return everything that happened since the last poll
+    fn poll(&mut self) -> Vec<InputEvent>;
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InputEvent {
+    // One of the 16 Chip-8 keys changed state
+    Key { key: usize, pressed: bool },
+    // An emulator hotkey was pressed
+    Action(Action),
+    // An emulator hotkey was let go, only used by hotkeys that are held
+    ActionReleased(Action),
+    // Moving around the ROM browser, ignored while it is closed
+    Navigate(Navigation),
+    // A file or folder was dropped on the window
+    Open(PathBuf),
+}
+
+// How to run a game switched to from the browser or dropped on the window
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameSettings {
+    pub quirks: Quirks,
+    pub instructions_per_second: u32,
+    // Whatever was in use for the last game is kept when these are None
+    pub palette: Option<Palette>,
+    pub persistence: Option<Persistence>,
+}
+
+impl GameSettings {
+    // What the ROM database, or failing that the file extension, says
+    pub fn from_info(rom_name: &str, info: Option<&RomInfo>) -> Self {
+        let info = info.cloned().unwrap_or_default();
+        GameSettings {
+            quirks: info
+                .quirks
+                .or_else(|| default_quirks(rom_name))
+                .unwrap_or_default(),
+            instructions_per_second: info
+                .instructions_per_second()
+                .unwrap_or(DEFAULT_INSTRUCTIONS_PER_SECOND),
+            palette: info.palette,
+            persistence: None,
+        }
+    }
+}
+
+// Works out the settings for a game switched to while running, from its file
+// name and what the ROM database knows about it, so frontends with settings
+// of their own can apply them the same way as on startup. It can set the
+// input up for the game too.
+pub type GameSetup<I> =
+    Box<dyn FnMut(&str, Option<&RomInfo>, &mut I) -> Result<GameSettings, String>>;
+
+// Drives a Machine with any combination of backends: polls input, runs the
+// right number of frames for the current speed, and presents the result
+pub struct Runner<V: Video, A: Audio, I: Input> {
+    machine: Machine,
+    video: V,
+    audio: A,
+    input: I,
+    paused: bool,
+    // Frames left to run while paused, queued by frame advance
+    advance: u32,
+    speed: usize,
+    turbo_held: bool,
+    turbo_toggled: bool,
+    slow_motion: bool,
+    // Fraction of a frame carried over when running slower or faster than normal
+    frame_budget: f64,
+    beeping: bool,
+    // Palettes the user can cycle through, and the one in use
+    palettes: Vec<Palette>,
+    palette: usize,
+    phosphor: Phosphor,
+    // Filters::choices, and which of them is in use
+    filters: Vec<Filters>,
+    filter_choice: usize,
+    screenshots: Screenshots,
+    recording: Option<Recording>,
+    movie: Option<MovieState>,
+    // Where recorded movies go, instead of next to the screenshots
+    movie_path: Option<PathBuf>,
+    // The state the load state hotkey goes back to
+    saved_state: Option<Vec<u8>>,
+    // The name of the game, shown in the window title
+    game_title: Option<String>,
+    library: Library,
+    // The ROM browser, while it is open. The game is paused in the meantime.
+    browser: Option<Browser>,
+    // The ROM file being watched for changes, while developing a game
+    watch: Option<Watch>,
+    game_setup: Option<GameSetup<I>>,
+}
+
+// A GIF being recorded, along with where it goes
+struct Recording {
+    path: PathBuf,
+    gif: GifRecorder<BufWriter<File>>,
+}
+
+// An input movie being recorded, or played back up to the next key change
+enum MovieState {
+    Recording(Movie),
+    Playing { movie: Movie, next: usize },
+}
+
+impl<V: Video, A: Audio, I: Input> Runner<V, A, I> {
+    pub fn new(machine: Machine, video: V, audio: A, input: I) -> Self {
+        Runner {
+            machine,
+            video,
+            audio,
+            input,
+            paused: false,
+            advance: 0,
+            speed: NORMAL_SPEED,
+            turbo_held: false,
+            turbo_toggled: false,
+            slow_motion: false,
+            frame_budget: 0.0,
+            beeping: false,
+            palettes: Palette::presets(),
+            palette: 0,
+            phosphor: Phosphor::new(Persistence::Off),
+            filters: Filters::default().choices(),
+            filter_choice: 0,
+            screenshots: Screenshots::default(),
+            recording: None,
+            movie: None,
+            movie_path: None,
+            saved_state: None,
+            game_title: None,
+            library: Library::default(),
+            browser: None,
+            watch: None,
+            game_setup: None,
+        }
+    }
+
+    // Run until the user quits, limiting the loop to 60 steps per second
+    pub fn run(&mut self) {
+        self.run_with_clock(&mut SystemClock::new());
+    }
+
+    // Run until the user quits, emulating 60 frames per second of the clock's
+    // time no matter how often the video lets the loop go round. A loop held
+    // up by a 144 Hz vsync runs a frame on some iterations and not others.
+    pub fn run_with_clock(&mut self, clock: &mut impl Clock) {
+        let mut pacer = Pacer::new(clock.now());
+        loop {
+            let due = pacer.frames_due(clock.now());
+            if !self.step_by(due) {
+                break;
+            }
+            clock.sleep(pacer.until_next(clock.now()));
+        }
+        self.stop_captures();
+    }
+
+    // Save any GIF or movie being recorded, so quitting or switching games in
+    // the middle of one doesn't lose it
+    fn stop_captures(&mut self) {
+        if self.is_recording() {
+            self.perform(Action::ToggleRecording);
+        }
+        if self.is_recording_movie() {
+            self.perform(Action::ToggleMovieRecording);
+        }
+        self.movie = None;
+    }
+
+    // Handle 1/60th of a second worth of work. Returns false once the user quits.
+    pub fn step(&mut self) -> bool {
+        self.step_by(1)
+    }
+
+    // Handle input, run the frames due for the given number of 60 Hz periods
+    // at the current speed, and draw the result
+    pub fn step_by(&mut self, periods: u64) -> bool {
+        for event in self.input.poll() {
+            match event {
+                InputEvent::Action(Action::Quit) => return false,
+                InputEvent::Open(path) => self.open_path(&path),
+                _ if self.browser.is_some() => self.browser_input(event),
+                InputEvent::Key { key, pressed } => self.key_changed(key, pressed),
+                InputEvent::Action(action) => self.perform(action),
+                InputEvent::ActionReleased(Action::Turbo) => self.set_turbo_held(false),
+                InputEvent::ActionReleased(_) | InputEvent::Navigate(_) => (),
+            }
+        }
+
+        if let Some(rom) = self.watch.as_mut().and_then(|watch| watch.poll(periods)) {
+            self.reload(rom);
+        }
+
+        if self.browser.is_some() {
+            // The game waits for the browser to close
+        } else if !self.paused {
+            self.frame_budget += periods as f64 * self.effective_speed();
+            while self.frame_budget >= 1.0 {
+                self.run_frame();
+                self.frame_budget -= 1.0;
+            }
+        } else if self.advance > 0 && periods > 0 {
+            self.run_frame();
+            self.advance -= 1;
+        }
+
+        let beeping = !self.paused && self.browser.is_none() && self.machine.is_beeping();
+        if beeping != self.beeping {
+            self.audio.set_beeping(beeping);
+            self.beeping = beeping;
+        }
+
+        if let Some(browser) = &self.browser {
+            let frame = browser.render(self.palette());
+            self.video.draw(&frame, &self.machine);
+            return true;
+        }
+
+        let frame = self.render_palette();
+        if let Some(recording) = &mut self.recording {
+            // GIFs play at 60 Hz, so the frame is shown once per period
+            let scaled = frame.scaled(self.screenshots.scale);
+            let result = (0..periods).try_for_each(|_| recording.gif.push(&scaled));
+            // Recording is stopped rather than interrupting the game on errors
+            if let Err(err) = result {
+                eprintln!("Unable to record {}: {err}", recording.path.display());
+                self.recording = None;
+            }
+        }
+        let frame = self.apply_filters(frame);
+        self.video.draw(&frame, &self.machine);
+        true
+    }
+
+    fn perform(&mut self, action: Action) {
+        match action {
+            Action::Quit => (),
+            Action::Reset => {
+                // The ROM was already loaded once, so reloading it can't fail
+                self.machine.reset().unwrap();
+                self.restart_movie();
+            }
+            Action::TogglePause => self.set_paused(!self.paused),
+            Action::FrameAdvance => {
+                // Advancing from a running game pauses it on the next frame
+                if self.paused {
+                    self.advance += 1;
+                } else {
+                    self.paused = true;
+                    self.update_title();
+                }
+            }
+            Action::Turbo => self.set_turbo_held(true),
+            Action::ToggleTurbo => {
+                self.turbo_toggled = !self.turbo_toggled;
+                self.update_title();
+            }
+            Action::ToggleSlowMotion => {
+                self.slow_motion = !self.slow_motion;
+                self.update_title();
+            }
+            Action::IpfUp => {
+                let ipf = self.machine.ticks_per_frame();
+                let next = IPF_STEPS.iter().find(|&&step| step > ipf);
+                self.set_ipf(*next.unwrap_or(&ipf));
+            }
+            Action::IpfDown => {
+                let ipf = self.machine.ticks_per_frame();
+                let next = IPF_STEPS.iter().rev().find(|&&step| step < ipf);
+                self.set_ipf(*next.unwrap_or(&ipf));
+            }
+            Action::SaveState => match self.save_state() {
+                Ok(path) => println!("Saved state to {}", path.display()),
+                Err(err) => eprintln!("Unable to save state: {err}"),
+            },
+            Action::LoadState => match self.saved_state.take() {
+                Some(state) => {
+                    // Saved states were made by this machine, so they are valid
+                    self.load_state(&state).unwrap();
+                    self.saved_state = Some(state);
+                }
+                None => eprintln!("No state saved yet"),
+            },
+            Action::SpeedUp => self.set_speed(self.speed + 1),
+            Action::SpeedDown => self.set_speed(self.speed.saturating_sub(1)),
+            Action::CyclePalette => self.palette = (self.palette + 1) % self.palettes.len(),
+            Action::ToggleFullscreen => self.video.toggle_fullscreen(),
+            Action::ToggleFilters => {
+                self.filter_choice = (self.filter_choice + 1) % self.filters.len();
+            }
+            Action::Screenshot => match self.screenshot() {
+                Ok(path) => println!("Saved screenshot to {}", path.display()),
+                Err(err) => eprintln!("Unable to save screenshot: {err}"),
+            },
+            Action::ToggleRecording if self.is_recording() => match self.stop_recording() {
+                Ok(Some(path)) => println!("Saved recording to {}", path.display()),
+                Ok(None) => (),
+                Err(err) => eprintln!("Unable to save recording: {err}"),
+            },
+            Action::ToggleRecording => {
+                let path = self
+                    .screenshots
+                    .new_path(self.machine.name(), "gif")
+                    .and_then(|path| self.start_recording(&path).map(|_| path));
+                match path {
+                    Ok(path) => println!("Recording to {}", path.display()),
+                    Err(err) => eprintln!("Unable to start recording: {err}"),
+                }
+            }
+            Action::ToggleMovieRecording => match self.stop_movie() {
+                Some(movie) => match self.save_movie(&movie) {
+                    Ok(path) => println!("Saved movie to {}", path.display()),
+                    Err(err) => eprintln!("Unable to save movie: {err}"),
+                },
+                None => {
+                    self.record_movie();
+                    println!("Recording movie");
+                }
+            },
+            Action::TakeOver => {
+                if self.take_over() {
+                    println!("Took over movie at frame {}", self.machine.frame_count());
+                }
+            }
+            Action::ToggleBrowser => match self.browser {
+                Some(_) => {
+                    self.browser = None;
+                    self.update_title();
+                }
+                None => self.open_browser(None),
+            },
+        }
+    }
+
+    // Movies start from a reset, so start them over along with the machine
+    fn restart_movie(&mut self) {
+        match &mut self.movie {
+            Some(MovieState::Recording(movie)) => movie.inputs.changes.clear(),
+            Some(MovieState::Playing { next, .. }) => *next = 0,
+            None => (),
+        }
+    }
+
+    // Reload the ROM whenever the file changes, resetting or patching the
+    // running game as the watch says
+    pub fn set_watch(&mut self, watch: Watch) {
+        self.watch = Some(watch);
+    }
+
+    // Bring in a new build of the watched ROM
+    fn reload(&mut self, rom: Result<Vec<u8>, RomError>) {
+        let Some(watch) = &self.watch else {
+            return;
+        };
+        let (path, mode) = (watch.path(), watch.mode);
+        let result = rom.and_then(|rom| match mode {
+            ReloadMode::Reset => self.machine.set_rom(rom).map(|_| self.restart_movie()),
+            ReloadMode::Patch => self.machine.patch_rom(rom),
+        });
+        match result {
+            Ok(()) => println!("Reloaded {}", path.display()),
+            Err(err) => eprintln!("Unable to reload {}: {err}", path.display()),
+        }
+    }
+
+    // Only the hotkeys for the window itself work while the browser is open.
+    // Keys are still let go of, so none are stuck down once the game goes on.
+    fn browser_input(&mut self, event: InputEvent) {
+        match event {
+            InputEvent::Navigate(navigation) => {
+                let Some(browser) = &mut self.browser else {
+                    return;
+                };
+                if let Some(rom) = browser.navigate(&self.library, navigation) {
+                    self.open_rom_reporting(&rom);
+                } else {
+                    self.update_title();
+                }
+            }
+            InputEvent::Key {
+                key,
+                pressed: false,
+            } => self.key_changed(key, false),
+            InputEvent::Action(
+                action @ (Action::ToggleBrowser | Action::ToggleFullscreen | Action::CyclePalette),
+            ) => self.perform(action),
+            InputEvent::ActionReleased(Action::Turbo) => self.set_turbo_held(false),
+            _ => (),
+        }
+    }
+
+    // Open the ROM browser at a directory or zip archive, or at the top
+    pub fn open_browser(&mut self, location: Option<&Path>) {
+        self.browser = Some(Browser::new(&self.library, location));
+        self.update_title();
+    }
+
+    pub fn is_browsing(&self) -> bool {
+        self.browser.is_some()
+    }
+
+    // Load a file dropped on the window, or browse it if it holds several ROMs
+    fn open_path(&mut self, path: &Path) {
+        match list_roms(path) {
+            Ok(Some(_)) => self.open_browser(Some(path)),
+            Ok(None) => self.open_rom_reporting(&RomChoice::file(path)),
+            Err(err) => eprintln!("Unable to open {}: {err}", path.display()),
+        }
+    }
+
+    fn open_rom_reporting(&mut self, rom: &RomChoice) {
+        if let Err(err) = self.open_rom(rom) {
+            eprintln!("Unable to load {}: {err}", rom.path.display());
+        }
+    }
+
+    // Switch to another game without starting over. It runs with the
+    // settings set_game_setup works out, or else with what the ROM database
+    // knows about it. Nothing from the last game carries over apart from the
+    // palette and persistence, when the settings leave them alone.
+    pub fn open_rom(&mut self, rom: &RomChoice) -> Result<(), RomError> {
+        let loaded = rom.load()?;
+        validate_rom(&loaded.data)?;
+        // Settings that came with the ROM win over the database's
+        let info = loaded
+            .info
+            .clone()
+            .or_else(|| self.library.db.lookup(&loaded.data).cloned());
+        let settings = match &mut self.game_setup {
+            Some(setup) => {
+                setup(&loaded.name, info.as_ref(), &mut self.input).map_err(RomError::Invalid)?
+            }
+            None => GameSettings::from_info(&loaded.name, info.as_ref()),
+        };
+        let title = info.map(|info| info.title).unwrap_or_default();
+        self.stop_captures();
+        self.machine.set_rom(loaded.data)?;
+        // The watched file belongs to the last game
+        self.watch = None;
+
+        self.machine.cpu_mut().set_quirks(settings.quirks);
+        self.machine
+            .set_instructions_per_second(settings.instructions_per_second);
+        if let Some(name) = Path::new(&loaded.name).file_stem() {
+            self.machine.set_name(&name.to_string_lossy());
+        }
+        if let Some(palette) = settings.palette {
+            self.set_palette(palette);
+        }
+        let persistence = settings.persistence.unwrap_or(self.phosphor.persistence());
+        self.phosphor = Phosphor::new(persistence);
+        self.saved_state = None;
+        self.browser = None;
+        self.paused = false;
+        self.advance = 0;
+        self.frame_budget = 0.0;
+        self.library.remember(rom, &title);
+        self.game_title = Some(title).filter(|title| !title.is_empty());
+        self.update_title();
+        Ok(())
+    }
+
+    pub fn set_game_setup(&mut self, setup: GameSetup<I>) {
+        self.game_setup = Some(setup);
+    }
+
+    // Where the ROM browser looks for games, and what it knows about them
+    pub fn set_library(&mut self, library: Library) {
+        self.library = library;
+    }
+
+    pub fn library(&self) -> &Library {
+        &self.library
+    }
+
+    pub fn library_mut(&mut self) -> &mut Library {
+        &mut self.library
+    }
+
+    fn run_frame(&mut self) {
+        self.play_movie_inputs();
+        self.machine.run_frame();
+        self.phosphor.update(self.machine.cpu().get_display());
+    }
+
+    fn key_changed(&mut self, key: usize, pressed: bool) {
+        // The movie is in control of the keys until taken over
+        if let Some(MovieState::Playing { .. }) = self.movie {
+            return;
+        }
+        // Keys past F don't exist, and are left out of recordings
+        if self.machine.keypress(key, pressed).is_err() {
+            return;
+        }
+        if let Some(MovieState::Recording(movie)) = &mut self.movie {
+            movie.inputs.push(self.machine.frame_count(), key, pressed);
+        }
+    }
+
+    // Press and release keys as the movie did before the coming frame
+    fn play_movie_inputs(&mut self) {
+        let Some(MovieState::Playing { movie, next }) = &mut self.movie else {
+            return;
+        };
+        let frame = self.machine.frame_count();
+        for change in &movie.inputs.changes[*next..] {
+            if change.frame > frame {
+                break;
+            }
+            // Input logs only hold keys 0 to F
+            self.machine.keypress(change.key, change.pressed).unwrap();
+            *next += 1;
+        }
+        // Hand the keys back to the player once there's nothing left to play
+        if *next == movie.inputs.changes.len() {
+            println!("Movie finished at frame {frame}");
+            self.movie = None;
+        }
+    }
+
+    // Reset the machine and record every key change from here on
+    pub fn record_movie(&mut self) {
+        self.machine.reset().unwrap();
+        let cpu = self.machine.cpu();
+        self.movie = Some(MovieState::Recording(Movie {
+            seed: cpu.get_seed(),
+            quirks: cpu.get_quirks(),
+            instructions_per_second: self.machine.instructions_per_second(),
+            ..Movie::default()
+        }));
+    }
+
+    // Set the machine up as the movie was recorded and replay its inputs.
+    // Keys from the player are ignored until they take over or the movie's
+    // last key change has been played.
+    pub fn play_movie(&mut self, movie: Movie) {
+        let cpu = self.machine.cpu_mut();
+        cpu.set_seed(movie.seed);
+        cpu.set_quirks(movie.quirks);
+        self.machine
+            .set_instructions_per_second(movie.instructions_per_second);
+        self.machine.reset().unwrap();
+        self.movie = Some(MovieState::Playing { movie, next: 0 });
+    }
+
+    // Stop playing the movie and record on from the current frame instead,
+    // dropping whatever the movie did after it. Returns false if no movie
+    // was playing.
+    pub fn take_over(&mut self) -> bool {
+        match self.movie.take() {
+            Some(MovieState::Playing { mut movie, next }) => {
+                movie.inputs.changes.truncate(next);
+                self.movie = Some(MovieState::Recording(movie));
+                true
+            }
+            other => {
+                self.movie = other;
+                false
+            }
+        }
+    }
+
+    // Stop recording or playing, returning the movie if it was being recorded
+    pub fn stop_movie(&mut self) -> Option<Movie> {
+        match self.movie.take() {
+            Some(MovieState::Recording(movie)) => Some(movie),
+            _ => None,
+        }
+    }
+
+    pub fn is_recording_movie(&self) -> bool {
+        matches!(self.movie, Some(MovieState::Recording(_)))
+    }
+
+    pub fn is_playing_movie(&self) -> bool {
+        matches!(self.movie, Some(MovieState::Playing { .. }))
+    }
+
+    // Save the movie where set_movie_path said, or next to the screenshots
+    fn save_movie(&self, movie: &Movie) -> io::Result<PathBuf> {
+        let path = match &self.movie_path {
+            Some(path) => path.clone(),
+            None => self.screenshots.new_path(self.machine.name(), "movie")?,
+        };
+        fs::write(&path, movie.to_string())?;
+        Ok(path)
+    }
+
+    pub fn set_movie_path(&mut self, path: &Path) {
+        self.movie_path = Some(path.to_path_buf());
+    }
+
+    // Save the machine next to the screenshots, and remember it for the load
+    // state hotkey
+    pub fn save_state(&mut self) -> io::Result<PathBuf> {
+        let state = self.machine.save_state();
+        let path = self.screenshots.new_path(self.machine.name(), "state")?;
+        fs::write(&path, &state)?;
+        self.saved_state = Some(state);
+        Ok(path)
+    }
+
+    // Continue from a saved state. Movies start from a reset machine, so any
+    // movie being played or recorded stops.
+    pub fn load_state(&mut self, state: &[u8]) -> io::Result<()> {
+        self.machine.load_state(state)?;
+        self.saved_state = Some(state.to_vec());
+        if let Some(movie) = self.stop_movie() {
+            if let Err(err) = self.save_movie(&movie) {
+                eprintln!("Unable to save movie: {err}");
+            }
+        }
+        Ok(())
+    }
+
+    // Record every frame shown from now on to a GIF, at the screenshot scale
+    pub fn start_recording(&mut self, path: &Path) -> io::Result<()> {
+        let scale = self.screenshots.scale;
+        let file = BufWriter::new(File::create(path)?);
+        let gif = GifRecorder::new(file, SCREEN_WIDTH * scale, SCREEN_HEIGHT * scale)?;
+        self.recording = Some(Recording {
+            path: path.to_path_buf(),
+            gif,
+        });
+        Ok(())
+    }
+
+    // Finish the GIF, returning where it was saved if there was one
+    pub fn stop_recording(&mut self) -> io::Result<Option<PathBuf>> {
+        let Some(recording) = self.recording.take() else {
+            return Ok(None);
+        };
+        let mut file = recording.gif.finish()?;
+        file.flush()?;
+        Ok(Some(recording.path))
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    // Save the display with the current palette and persistence, but without
+    // the filters, scaled as set with set_screenshots
+    pub fn screenshot(&self) -> io::Result<PathBuf> {
+        self.screenshots
+            .save(&self.render_palette(), self.machine.name())
+    }
+
+    // The same as screenshot, but saved to the path given
+    pub fn screenshot_as(&self, path: &Path) -> io::Result<()> {
+        self.screenshots.save_as(&self.render_palette(), path)
+    }
+
+    pub fn set_screenshots(&mut self, screenshots: Screenshots) {
+        self.screenshots = screenshots;
+    }
+
+    fn apply_filters(&self, frame: Framebuffer) -> Framebuffer {
+        let filters = &self.filters[self.filter_choice];
+        if filters.is_active() {
+            filters.apply(&frame)
+        } else {
+            frame
+        }
+    }
+
+    // Turn the display into an image with the palette and persistence
+    fn render_palette(&self) -> Framebuffer {
+        let palette = &self.palettes[self.palette];
+        let levels = self.phosphor.levels();
+        if self.phosphor.persistence() == Persistence::Off || levels.is_empty() {
+            palette.render(
+                self.machine.cpu().get_display(),
+                SCREEN_WIDTH,
+                SCREEN_HEIGHT,
+            )
+        } else {
+            palette.render_levels(levels, SCREEN_WIDTH, SCREEN_HEIGHT)
+        }
+    }
+
+    pub fn set_filters(&mut self, filters: Filters) {
+        self.filters = filters.choices();
+        self.filter_choice = 0;
+    }
+
+    pub fn set_persistence(&mut self, persistence: Persistence) {
+        self.phosphor = Phosphor::new(persistence);
+    }
+
+    // Switch to the given palette. Custom palettes join the ones cycled through.
+    pub fn set_palette(&mut self, palette: Palette) {
+        match self.palettes.iter().position(|p| *p == palette) {
+            Some(idx) => self.palette = idx,
+            None => {
+                self.palettes.insert(0, palette);
+                self.palette = 0;
+            }
+        }
+    }
+
+    pub fn palette(&self) -> &Palette {
+        &self.palettes[self.palette]
+    }
+
+    fn set_speed(&mut self, speed: usize) {
+        self.speed = speed.min(SPEEDS.len() - 1);
+        self.frame_budget = 0.0;
+        self.update_title();
+    }
+
+    fn set_turbo_held(&mut self, held: bool) {
+        if held != self.turbo_held {
+            self.turbo_held = held;
+            self.update_title();
+        }
+    }
+
+    // Movies are replayed with the instructions per frame they were recorded
+    // with, so it can't change while one is running
+    fn set_ipf(&mut self, ipf: usize) {
+        if self.movie.is_some() {
+            eprintln!("Instructions per frame can't be changed during a movie");
+            return;
+        }
+        self.machine.set_ticks_per_frame(ipf);
+        self.update_title();
+    }
+
+    fn update_title(&mut self) {
+        let mut title = String::from("Chip-8 Emulator");
+        if let Some(game) = &self.game_title {
+            title += &format!(" - {game}");
+        }
+        title += &format!(
+            " ({}%, {} IPF)",
+            self.speed_percent(),
+            self.machine.ticks_per_frame()
+        );
+        if self.browser.is_some() {
+            title += " - Browsing ROMs";
+        } else if self.paused {
+            title += " - Paused";
+        }
+        self.video.set_title(&title);
+    }
+
+    fn effective_speed(&self) -> f64 {
+        if self.turbo_held || self.turbo_toggled {
+            TURBO_SPEED
+        } else if self.slow_motion {
+            SLOW_MOTION_SPEED
+        } else {
+            SPEEDS[self.speed]
+        }
+    }
+
+    // Current speed including turbo and slow motion, 100 being normal speed
+    pub fn speed_percent(&self) -> u32 {
+        (self.effective_speed() * 100.0) as u32
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_game_title(&mut self, title: &str) {
+        self.game_title = Some(title.to_string());
+        self.update_title();
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.advance = 0;
+        self.update_title();
+    }
+
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    pub fn machine_mut(&mut self) -> &mut Machine {
+        &mut self.machine
+    }
+
+    pub fn video(&self) -> &V {
+        &self.video
+    }
+
+    pub fn audio(&self) -> &A {
+        &self.audio
+    }
+
+    pub fn input_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Rgb, MENU_WIDTH};
+
+    use std::collections::VecDeque;
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct MockVideo {
+        draws: usize,
+        title: String,
+        last_frame: Option<Framebuffer>,
+    }
+
+    impl Video for MockVideo {
+        fn draw(&mut self, frame: &Framebuffer, _machine: &Machine) {
+            self.draws += 1;
+            self.last_frame = Some(frame.clone());
+        }
+
+        fn set_title(&mut self, title: &str) {
+            self.title = title.to_string();
+        }
+    }
+
+    #[derive(Default)]
+    struct MockAudio {
+        changes: Vec<bool>,
+    }
+
+    impl Audio for MockAudio {
+        fn set_beeping(&mut self, beeping: bool) {
+            self.changes.push(beeping);
+        }
+    }
+
+    // Hands out one batch of events per poll
+    #[derive(Default)]
+    struct MockInput {
+        batches: VecDeque<Vec<InputEvent>>,
+    }
+
+    impl MockInput {
+        fn push(&mut self, events: &[InputEvent]) {
+            self.batches.push_back(events.to_vec());
+        }
+    }
+
+    impl Input for MockInput {
+        fn poll(&mut self) -> Vec<InputEvent> {
+            self.batches.pop_front().unwrap_or_default()
+        }
+    }
+
+    // Sleeping moves time forward by exactly as long as asked, unless the
+    // clock stands in for a vsync that always waits one refresh instead
+    #[derive(Default)]
+    struct MockClock {
+        now: Duration,
+        refresh: Option<Duration>,
+        // Extra time lost on the given sleep, like the process being suspended
+        stall: Option<(usize, Duration)>,
+        sleeps: usize,
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> Duration {
+            self.now
+        }
+
+        fn sleep(&mut self, duration: Duration) {
+            self.now += self.refresh.unwrap_or(duration);
+            if let Some((at, stall)) = self.stall {
+                if at == self.sleeps {
+                    self.now += stall;
+                }
+            }
+            self.sleeps += 1;
+        }
+    }
+
+    // Quit on the given poll, so run() returns
+    fn quit_after(runner: &mut Runner<MockVideo, MockAudio, MockInput>, polls: usize) {
+        for _ in 1..polls {
+            runner.input_mut().push(&[]);
+        }
+        runner.input_mut().push(&[InputEvent::Action(Action::Quit)]);
+    }
+
+    fn runner(rom: &[u8]) -> Runner<MockVideo, MockAudio, MockInput> {
+        let machine = Machine::new(rom.to_vec()).unwrap();
+        Runner::new(
+            machine,
+            MockVideo::default(),
+            MockAudio::default(),
+            MockInput::default(),
+        )
+    }
+
+    // V0 += 1, jump back to the start
+    const COUNTER: [u8; 4] = [0x70, 0x01, 0x12, 0x00];
+
+    fn v0(runner: &Runner<MockVideo, MockAudio, MockInput>) -> u8 {
+        runner.machine().cpu().get_variable_registers()[0]
+    }
+
+    #[test]
+    fn runs_one_frame_per_step() {
+        let mut runner = runner(&COUNTER);
+        assert!(runner.step());
+        assert!(runner.step());
+        assert_eq!(runner.machine().frame_count(), 2);
+        assert_eq!(runner.video().draws, 2);
+    }
+
+    #[test]
+    fn run_paces_frames_by_the_clock() {
+        let mut runner = runner(&COUNTER);
+        quit_after(&mut runner, 61);
+        let mut clock = MockClock::default();
+        runner.run_with_clock(&mut clock);
+        assert_eq!(runner.machine().frame_count(), 60);
+        assert_eq!(clock.now, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn run_ignores_the_display_refresh_rate() {
+        // One second on a 144 Hz display, which blocks the loop for each refresh
+        let mut runner = runner(&COUNTER);
+        quit_after(&mut runner, 145);
+        let mut clock = MockClock {
+            refresh: Some(Duration::from_nanos(1_000_000_000 / 144)),
+            ..MockClock::default()
+        };
+        runner.run_with_clock(&mut clock);
+        assert_eq!(runner.video().draws, 144);
+        assert_eq!(runner.machine().frame_count(), 60);
+    }
+
+    #[test]
+    fn run_drops_frames_after_a_stall() {
+        let mut runner = runner(&COUNTER);
+        quit_after(&mut runner, 11);
+        let mut clock = MockClock {
+            stall: Some((4, Duration::from_secs(1))),
+            ..MockClock::default()
+        };
+        runner.run_with_clock(&mut clock);
+        // Ten iterations, one of which catches up on a few of the 60 missed frames
+        assert_eq!(runner.machine().frame_count(), 9 + 6);
+    }
+
+    #[test]
+    fn quit_stops_the_runner() {
+        let mut runner = runner(&COUNTER);
+        runner.input_mut().push(&[InputEvent::Action(Action::Quit)]);
+        assert!(!runner.step());
+        assert_eq!(runner.machine().frame_count(), 0);
+    }
+
+    #[test]
+    fn pause_freezes_machine_but_keeps_drawing() {
+        let mut runner = runner(&COUNTER);
+        runner
+            .input_mut()
+            .push(&[InputEvent::Action(Action::TogglePause)]);
+        runner.step();
+        runner.step();
+        assert!(runner.is_paused());
+        assert_eq!(runner.machine().frame_count(), 0);
+        assert_eq!(runner.video().draws, 2);
+
+        runner
+            .input_mut()
+            .push(&[InputEvent::Action(Action::TogglePause)]);
+        runner.step();
+        assert_eq!(runner.machine().frame_count(), 1);
+    }
+
+    #[test]
+    fn speed_changes_frames_per_step() {
+        let mut runner = runner(&COUNTER);
+        runner.input_mut().push(&[
+            InputEvent::Action(Action::SpeedUp),
+            InputEvent::Action(Action::SpeedUp),
+        ]);
+        runner.step();
+        assert_eq!(runner.speed_percent(), 200);
+        assert_eq!(runner.machine().frame_count(), 2);
+        assert_eq!(runner.video().title, "Chip-8 Emulator (200%, 10 IPF)");
+
+        // Half speed runs a frame every other step
+        for _ in 0..4 {
+            runner
+                .input_mut()
+                .push(&[InputEvent::Action(Action::SpeedDown)]);
+        }
+        for _ in 0..4 {
+            runner.step();
+        }
+        assert_eq!(runner.speed_percent(), 50);
+        assert_eq!(runner.machine().frame_count(), 2 + 1 + 1);
+    }
+
+    #[test]
+    fn frame_advance_runs_one_frame_at_a_time() {
+        let mut runner = runner(&COUNTER);
+        runner
+            .input_mut()
+            .push(&[InputEvent::Action(Action::FrameAdvance)]);
+        runner.step();
+        assert!(runner.is_paused());
+        assert_eq!(
+            runner.video().title,
+            "Chip-8 Emulator (100%, 10 IPF) - Paused"
+        );
+        let frames = runner.machine().frame_count();
+
+        runner.input_mut().push(&[
+            InputEvent::Action(Action::FrameAdvance),
+            InputEvent::Action(Action::FrameAdvance),
+        ]);
+        runner.step();
+        runner.step();
+        runner.step();
+        assert_eq!(runner.machine().frame_count(), frames + 2);
+    }
+
+    #[test]
+    fn turbo_runs_while_held() {
+        let mut runner = runner(&COUNTER);
+        runner
+            .input_mut()
+            .push(&[InputEvent::Action(Action::Turbo)]);
+        runner.step();
+        assert_eq!(runner.speed_percent(), 800);
+        assert_eq!(runner.machine().frame_count(), 8);
+
+        runner
+            .input_mut()
+            .push(&[InputEvent::ActionReleased(Action::Turbo)]);
+        runner.step();
+        assert_eq!(runner.speed_percent(), 100);
+        assert_eq!(runner.machine().frame_count(), 9);
+
+        runner.input_mut().push(&[
+            InputEvent::Action(Action::ToggleSlowMotion),
+            InputEvent::Action(Action::ToggleTurbo),
+        ]);
+        runner.step();
+        assert_eq!(runner.speed_percent(), 800);
+        runner
+            .input_mut()
+            .push(&[InputEvent::Action(Action::ToggleTurbo)]);
+        runner.step();
+        assert_eq!(runner.speed_percent(), 25);
+    }
+
+    #[test]
+    fn ipf_steps_up_and_down() {
+        let mut runner = runner(&COUNTER);
+        runner.input_mut().push(&[
+            InputEvent::Action(Action::IpfUp),
+            InputEvent::Action(Action::IpfUp),
+        ]);
+        runner.step();
+        assert_eq!(runner.machine().ticks_per_frame(), 20);
+        assert_eq!(runner.video().title, "Chip-8 Emulator (100%, 20 IPF)");
+        runner.set_game_title("Counter");
+        assert_eq!(
+            runner.video().title,
+            "Chip-8 Emulator - Counter (100%, 20 IPF)"
+        );
+        // COUNTER adds 1 every other instruction
+        assert_eq!(v0(&runner), 10);
+
+        runner.machine_mut().set_ticks_per_frame(1);
+        runner
+            .input_mut()
+            .push(&[InputEvent::Action(Action::IpfDown)]);
+        runner.step();
+        assert_eq!(runner.machine().ticks_per_frame(), 1);
+    }
+
+    #[test]
+    fn load_state_goes_back_to_the_saved_state() {
+        let mut runner = runner(&RANDOM_KEYS);
+        runner.step();
+        let state = runner.machine().save_state();
+        let registers = runner.machine().cpu().get_variable_registers().to_vec();
+        runner.step();
+        assert_ne!(runner.machine().cpu().get_variable_registers(), registers);
+
+        runner.load_state(&state).unwrap();
+        assert_eq!(runner.machine().cpu().get_variable_registers(), registers);
+        assert_eq!(runner.machine().frame_count(), 1);
+        // The random numbers carry on the same way after loading
+        runner.step();
+        let after = runner.machine().cpu().get_variable_registers().to_vec();
+        runner
+            .input_mut()
+            .push(&[InputEvent::Action(Action::LoadState)]);
+        runner.step();
+        assert_eq!(runner.machine().cpu().get_variable_registers(), after);
+
+        assert!(runner.load_state(b"C8ST").is_err());
+        assert!(runner.load_state(&state[..100]).is_err());
+    }
+
+    #[test]
+    fn reset_restarts_the_rom() {
+        let mut runner = runner(&COUNTER);
+        runner.step();
+        assert_ne!(v0(&runner), 0);
+
+        // Pause straight away so the reset state can be inspected
+        runner.input_mut().push(&[
+            InputEvent::Action(Action::Reset),
+            InputEvent::Action(Action::TogglePause),
+        ]);
+        runner.step();
+        assert_eq!(v0(&runner), 0);
+        assert_eq!(runner.machine().cpu().get_pc(), 0x200);
+    }
+
+    #[test]
+    fn keys_reach_the_cpu() {
+        // Skip the V0 increment while key 5 is held
+        let rom = [0x60, 0x05, 0xE0, 0x9E, 0x71, 0x01, 0x12, 0x02];
+        let mut runner = runner(&rom);
+        runner.input_mut().push(&[InputEvent::Key {
+            key: 5,
+            pressed: true,
+        }]);
+        runner.step();
+        assert_eq!(runner.machine().cpu().get_variable_registers()[1], 0);
+
+        runner.input_mut().push(&[InputEvent::Key {
+            key: 5,
+            pressed: false,
+        }]);
+        runner.step();
+        assert_ne!(runner.machine().cpu().get_variable_registers()[1], 0);
+    }
+
+    #[test]
+    fn audio_follows_sound_timer() {
+        // ST = 2, then spin
+        let rom = [0x60, 0x02, 0xF0, 0x18, 0x12, 0x04];
+        let mut runner = runner(&rom);
+        runner.step();
+        runner.step();
+        runner.step();
+        assert_eq!(runner.audio().changes, vec![true, false]);
+    }
+
+    #[test]
+    fn frames_use_the_current_palette() {
+        // Draw the font's "0" in the top left corner
+        let rom = [0xD0, 0x05, 0x12, 0x02];
+        let mut runner = runner(&rom);
+        let amber: Palette = "amber".parse().unwrap();
+        runner.set_palette(amber.clone());
+        runner.step();
+        let frame = runner.video().last_frame.clone().unwrap();
+        assert_eq!(frame.pixel(0, 0), amber.foreground());
+        assert_eq!(frame.pixel(1, 1), amber.background());
+
+        runner
+            .input_mut()
+            .push(&[InputEvent::Action(Action::CyclePalette)]);
+        runner.step();
+        assert_ne!(runner.palette(), &amber);
+    }
+
+    #[test]
+    fn custom_palettes_join_the_cycle() {
+        let mut runner = runner(&COUNTER);
+        let custom: Palette = "#102030,#FFF".parse().unwrap();
+        assert_eq!(custom.colors[3], Rgb::new(0xFF, 0xFF, 0xFF));
+        runner.set_palette(custom.clone());
+        assert_eq!(runner.palette(), &custom);
+
+        for _ in 0..Palette::presets().len() + 1 {
+            runner
+                .input_mut()
+                .push(&[InputEvent::Action(Action::CyclePalette)]);
+            runner.step();
+        }
+        assert_eq!(runner.palette(), &custom);
+    }
+
+    #[test]
+    fn screenshots_are_scaled_pngs_named_after_the_rom() {
+        let rom = [0xD0, 0x05, 0x12, 0x02];
+        let mut runner = runner(&rom);
+        let dir = std::env::temp_dir().join(format!("chip8-screenshots-{}", std::process::id()));
+        runner.machine_mut().set_name("font zero");
+        runner.set_screenshots(Screenshots {
+            dir: dir.clone(),
+            scale: 2,
+        });
+        runner.step();
+
+        let first = runner.screenshot().unwrap();
+        let second = runner.screenshot().unwrap();
+        assert_ne!(first, second);
+        let name = first.file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with("font_zero-") && name.ends_with(".png"));
+
+        let decoder = png::Decoder::new(std::fs::File::open(&first).unwrap());
+        let info = decoder.read_info().unwrap().info().clone();
+        assert_eq!((info.width, info.height), (128, 64));
+
+        let named = dir.join("named.png");
+        runner.screenshot_as(&named).unwrap();
+        assert_eq!(
+            std::fs::read(&named).unwrap(),
+            std::fs::read(&first).unwrap()
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn recordings_skip_unchanged_frames() {
+        // Draw the font's "0" once, then spin
+        let rom = [0xD0, 0x05, 0x12, 0x02];
+        let mut runner = runner(&rom);
+        let path = std::env::temp_dir().join(format!("chip8-recording-{}.gif", std::process::id()));
+        runner.start_recording(&path).unwrap();
+        for _ in 0..30 {
+            runner.step();
+        }
+        assert_eq!(runner.stop_recording().unwrap(), Some(path.clone()));
+        assert!(!runner.is_recording());
+
+        let mut decoder = gif::DecodeOptions::new()
+            .read_info(std::fs::File::open(&path).unwrap())
+            .unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (64, 32));
+        let first = decoder.read_next_frame().unwrap().unwrap().delay;
+        assert!(decoder.read_next_frame().unwrap().is_none());
+        assert_eq!(first, 50);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    // Adds random numbers to V2, and counts frames with key 5 held in V3
+    const RANDOM_KEYS: [u8; 14] = [
+        0xC1, 0xFF, 0x82, 0x14, 0x60, 0x05, 0xE0, 0x9E, 0x12, 0x00, 0x73, 0x01, 0x12, 0x00,
+    ];
+
+    fn key(runner: &mut Runner<MockVideo, MockAudio, MockInput>, pressed: bool) {
+        runner
+            .input_mut()
+            .push(&[InputEvent::Key { key: 5, pressed }]);
+    }
+
+    #[test]
+    fn movies_replay_exactly() {
+        let mut runner = runner(&RANDOM_KEYS);
+        runner.step();
+        runner.record_movie();
+        for frame in 0..12 {
+            match frame {
+                3 => key(&mut runner, true),
+                8 => key(&mut runner, false),
+                _ => (),
+            }
+            runner.step();
+        }
+        let movie = runner.stop_movie().unwrap();
+        assert_eq!(movie.inputs.changes.len(), 2);
+        let registers = runner.machine().cpu().get_variable_registers().to_vec();
+        assert_ne!(registers[3], 0);
+
+        // A fresh machine starts with a different seed, the movie brings it back
+        let mut replay = self::runner(&RANDOM_KEYS);
+        replay.play_movie(movie.clone());
+        assert!(replay.is_playing_movie());
+        for _ in 0..12 {
+            // Live keys don't disturb the playback
+            if replay.is_playing_movie() {
+                key(&mut replay, true);
+            }
+            replay.step();
+        }
+        assert_eq!(replay.machine().cpu().get_variable_registers(), registers);
+
+        // The text format keeps everything needed
+        let parsed: Movie = movie.to_string().parse().unwrap();
+        assert_eq!(parsed, movie);
+    }
+
+    #[test]
+    fn live_keys_work_after_the_movie_ends() {
+        let mut movie = Movie::default();
+        movie.inputs.push(2, 5, true);
+        movie.inputs.push(4, 5, false);
+        let mut runner = runner(&RANDOM_KEYS);
+        runner.play_movie(movie);
+        for _ in 0..5 {
+            assert!(runner.is_playing_movie());
+            runner.step();
+        }
+        assert!(!runner.is_playing_movie());
+        assert!(!runner.machine().cpu().get_keys()[5]);
+
+        key(&mut runner, true);
+        runner.step();
+        assert!(runner.machine().cpu().get_keys()[5]);
+    }
+
+    #[test]
+    fn taking_over_continues_recording() {
+        let mut movie = Movie::default();
+        movie.inputs.push(2, 5, true);
+        movie.inputs.push(6, 5, false);
+        let mut runner = runner(&RANDOM_KEYS);
+        runner.play_movie(movie);
+        for _ in 0..4 {
+            runner.step();
+        }
+        assert!(runner.take_over());
+        assert!(runner.is_recording_movie());
+        key(&mut runner, false);
+        runner.step();
+
+        let movie = runner.stop_movie().unwrap();
+        let frames: Vec<_> = movie.inputs.changes.iter().map(|c| c.frame).collect();
+        assert_eq!(frames, vec![2, 4]);
+    }
+
+    #[test]
+    fn persistence_blends_erased_sprites() {
+        // Draw the font's "0" in one frame and erase it in the next
+        let rom = [0xD0, 0x05, 0xD0, 0x05, 0x12, 0x00];
+        let mut runner = runner(&rom);
+        runner.machine_mut().set_ticks_per_frame(1);
+        runner.set_persistence(Persistence::Blend);
+        runner.step();
+        runner.step();
+        let frame = runner.video().last_frame.clone().unwrap();
+        assert!(runner.machine().cpu().get_display().iter().all(|&lit| !lit));
+        assert_eq!(frame.pixel(0, 0), runner.palette().foreground());
+    }
+
+    #[test]
+    fn frontends_choose_the_settings_for_opened_games() {
+        let dir = std::env::temp_dir().join(format!("chip8-setup-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("count.sc8"), COUNTER).unwrap();
+        std::fs::write(dir.join("broken.ch8"), COUNTER).unwrap();
+
+        let mut runner = runner(&RANDOM_KEYS);
+        runner.set_game_setup(Box::new(|name, info, input: &mut MockInput| {
+            assert!(info.is_none());
+            if name == "broken.ch8" {
+                return Err("no settings for broken.ch8".to_string());
+            }
+            // The frontend can rebind its input along the way
+            input.push(&[InputEvent::Key {
+                key: 1,
+                pressed: true,
+            }]);
+            Ok(GameSettings {
+                quirks: Quirks::VIP,
+                instructions_per_second: 1200,
+                palette: Some("amber".parse().unwrap()),
+                persistence: Some(Persistence::Blend),
+            })
+        }));
+
+        runner
+            .open_rom(&RomChoice::file(&dir.join("count.sc8")))
+            .unwrap();
+        assert_eq!(runner.machine().cpu().get_quirks(), Quirks::VIP);
+        assert_eq!(runner.machine().instructions_per_second(), 1200);
+        assert_eq!(runner.palette().name, "amber");
+        assert_eq!(runner.phosphor.persistence(), Persistence::Blend);
+        runner.step();
+        assert!(runner.machine().cpu().get_keys()[1]);
+
+        let err = runner
+            .open_rom(&RomChoice::file(&dir.join("broken.ch8")))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "no settings for broken.ch8");
+        assert_eq!(runner.machine().name(), "count");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn browser_switches_games_in_place() {
+        let dir = std::env::temp_dir().join(format!("chip8-browser-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("count.sc8");
+        std::fs::write(&path, COUNTER).unwrap();
+
+        let mut runner = runner(&RANDOM_KEYS);
+        runner.step();
+        runner.set_library(Library {
+            rom_dir: Some(dir.clone()),
+            recent_path: Some(dir.join("recent.txt")),
+            ..Library::default()
+        });
+        runner.input_mut().push(&[
+            InputEvent::Action(Action::ToggleBrowser),
+            InputEvent::Key {
+                key: 5,
+                pressed: true,
+            },
+        ]);
+        runner.step();
+        assert!(runner.is_browsing());
+        assert_eq!(runner.machine().frame_count(), 1);
+        assert_eq!(
+            runner.video().last_frame.as_ref().unwrap().width,
+            MENU_WIDTH
+        );
+        assert!(runner.video().title.ends_with(" - Browsing ROMs"));
+
+        runner
+            .input_mut()
+            .push(&[InputEvent::Navigate(Navigation::Select)]);
+        runner.step();
+        assert!(!runner.is_browsing());
+        assert_eq!(runner.machine().rom(), COUNTER);
+        assert_eq!(runner.machine().name(), "count");
+        assert_eq!(runner.machine().cpu().get_quirks(), Quirks::SCHIP);
+        assert_eq!(v0(&runner), 5);
+
+        // Dropping a file loads it straight away, and it is remembered
+        std::fs::write(dir.join("keys.ch8"), RANDOM_KEYS).unwrap();
+        runner
+            .input_mut()
+            .push(&[InputEvent::Open(dir.join("keys.ch8"))]);
+        runner.step();
+        assert_eq!(runner.machine().rom(), RANDOM_KEYS);
+        let recent = std::fs::read_to_string(dir.join("recent.txt")).unwrap();
+        let recent: crate::RecentRoms = recent.parse().unwrap();
+        assert_eq!(recent.roms.len(), 2);
+        assert!(recent.roms[0].rom.path.ends_with("keys.ch8"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn watched_roms_reload_in_place() {
+        let dir = std::env::temp_dir().join(format!("chip8-watch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("count.ch8");
+        std::fs::write(&path, COUNTER).unwrap();
+
+        let mut runner = runner(&COUNTER);
+        runner.set_watch(Watch::new(RomChoice::file(&path), ReloadMode::Patch));
+        runner.step();
+        assert_eq!(v0(&runner), 5);
+
+        // Count by twos from now on, carrying on from where V0 got to
+        std::fs::write(&path, [0x70, 0x02, 0x12, 0x00, 0x00]).unwrap();
+        for _ in 0..15 {
+            runner.step();
+        }
+        assert_eq!(runner.machine().rom(), [0x70, 0x02, 0x12, 0x00, 0x00]);
+        // The file is looked at every 15 steps, counting the first one
+        assert_eq!(v0(&runner), 5 + 13 * 5 + 2 * 10);
+
+        // Starting over from the top in reset mode
+        std::fs::write(&path, [0x70, 0x03, 0x12, 0x00]).unwrap();
+        runner.watch.as_mut().unwrap().mode = ReloadMode::Reset;
+        for _ in 0..15 {
+            runner.step();
+        }
+        assert_eq!(v0(&runner), 2 * 15);
+        assert_eq!(runner.machine().frame_count(), 2);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn filters_can_be_toggled() {
+        // Draws the font's 0 in the corner, then loops
+        let mut runner = runner(&[0xD0, 0x05, 0x12, 0x02]);
+        let filters = Filters {
+            scale: 3,
+            grid: Some(1.0),
+            curvature: Some(0.5),
+            ..Filters::default()
+        };
+        runner.set_filters(filters.clone());
+        let mut frames = Vec::new();
+        for _ in 0..5 {
+            runner.step();
+            frames.push(runner.video().last_frame.clone().unwrap());
+            runner
+                .input_mut()
+                .push(&[InputEvent::Action(Action::ToggleFilters)]);
+        }
+
+        // All the effects, the grid, the curvature, none, and back to all
+        let plain = &frames[3];
+        assert_eq!(plain.width, 64);
+        for (frame, choice) in frames.iter().zip(filters.choices()) {
+            assert_eq!(*frame, choice.apply(plain));
+        }
+        assert_ne!(frames[1], frames[2]);
+        assert_eq!(frames[4], frames[0]);
+    }
+}
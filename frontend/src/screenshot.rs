@@ -0,0 +1,135 @@
+use crate::Framebuffer;
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Where screenshots go and how big they are
+#[derive(Clone, Debug, PartialEq)]
+pub struct Screenshots {
+    pub dir: PathBuf,
+    // Whole multiple of the native 64x32 resolution
+    pub scale: usize,
+}
+
+impl Default for Screenshots {
+    fn default() -> Self {
+        Screenshots {
+            dir: PathBuf::from("screenshots"),
+            scale: 1,
+        }
+    }
+}
+
+impl Screenshots {
+    // Save the frame as <rom>-<UTC timestamp>.png. Returns the path of the new file.
+    pub fn save(&self, frame: &Framebuffer, rom_name: &str) -> io::Result<PathBuf> {
+        let path = self.new_path(rom_name, "png")?;
+        self.save_as(frame, &path)?;
+        Ok(path)
+    }
+
+    // Save the frame at the screenshot scale to a path of the caller's choosing
+    pub fn save_as(&self, frame: &Framebuffer, path: &Path) -> io::Result<()> {
+        save_png(&frame.scaled(self.scale.max(1)), path)
+    }
+
+    // A fresh <rom>-<UTC timestamp>.<extension> path in the directory,
+    // creating the directory if needed. Recordings are named the same way.
+    pub fn new_path(&self, rom_name: &str, extension: &str) -> io::Result<PathBuf> {
+        fs::create_dir_all(&self.dir)?;
+        let stem = format!("{}-{}", sanitize(rom_name), timestamp(SystemTime::now()));
+        Ok(unused_path(&self.dir, &stem, extension))
+    }
+}
+
+pub fn save_png(frame: &Framebuffer, path: &Path) -> io::Result<()> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, frame.width as u32, frame.height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let data: Vec<u8> = frame.pixels.iter().flat_map(|p| [p.r, p.g, p.b]).collect();
+    encoder.write_header()?.write_image_data(&data)?;
+    Ok(())
+}
+
+// Two files in the same second get a counter instead of overwriting
+fn unused_path(dir: &Path, stem: &str, extension: &str) -> PathBuf {
+    let mut path = dir.join(format!("{stem}.{extension}"));
+    let mut n = 2;
+    while path.exists() {
+        path = dir.join(format!("{stem}-{n}.{extension}"));
+        n += 1;
+    }
+    path
+}
+
+// Keep file names portable whatever the ROM is called
+fn sanitize(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+            _ => '_',
+        })
+        .collect();
+    if name.is_empty() {
+        "chip8".to_string()
+    } else {
+        name
+    }
+}
+
+// YYYY-MM-DD_HH-MM-SS in UTC
+fn timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, secs) = (secs / 86_400, secs % 86_400);
+    let (year, month, day) = civil_from_days(days as i64);
+    format!(
+        "{year:04}-{month:02}-{day:02}_{:02}-{:02}-{:02}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+// Days since 1970-01-01 to a Gregorian date, from Howard Hinnant's algorithm
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    #[test]
+    fn timestamps_are_utc_dates() {
+        assert_eq!(timestamp(UNIX_EPOCH), "1970-01-01_00-00-00");
+        let leap_day = UNIX_EPOCH + Duration::from_secs(951_827_696);
+        assert_eq!(timestamp(leap_day), "2000-02-29_12-34-56");
+    }
+
+    #[test]
+    fn file_names_are_sanitized() {
+        assert_eq!(
+            sanitize("Space Invaders [David Winter]"),
+            "Space_Invaders__David_Winter_"
+        );
+        assert_eq!(sanitize(""), "chip8");
+    }
+}
@@ -0,0 +1,162 @@
+use crate::RomChoice;
+
+use chip8::RomError;
+
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+// How often the file is looked at, in 60 Hz periods
+const CHECK_PERIODS: u64 = 15;
+
+// What happens to the running game when the ROM changes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReloadMode {
+    // Start the new ROM from scratch
+    #[default]
+    Reset,
+    // Write the new ROM over the old one and carry on, keeping the registers,
+    // display and timers, so tweaks show up in the running game
+    Patch,
+}
+
+impl fmt::Display for ReloadMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ReloadMode::Reset => "reset",
+            ReloadMode::Patch => "patch",
+        })
+    }
+}
+
+impl FromStr for ReloadMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reset" => Ok(ReloadMode::Reset),
+            "patch" => Ok(ReloadMode::Patch),
+            _ => Err(format!(
+                "unknown reload mode '{s}', expected reset or patch"
+            )),
+        }
+    }
+}
+
+// Keeps an eye on a ROM or Octo source file and rebuilds it when it changes.
+// The file's modification time and size are polled, which works the same on
+// every platform and is cheap enough for a file or two.
+pub struct Watch {
+    rom: RomChoice,
+    pub mode: ReloadMode,
+    stamp: Option<(SystemTime, u64)>,
+    // The last ROM built, so saving the file without changes doesn't reload
+    last: Option<Vec<u8>>,
+    periods: u64,
+}
+
+impl Watch {
+    // Start watching from the file as it is now, which is taken to be loaded
+    pub fn new(rom: RomChoice, mode: ReloadMode) -> Self {
+        let mut watch = Watch {
+            rom,
+            mode,
+            stamp: None,
+            last: None,
+            periods: 0,
+        };
+        watch.stamp = watch.stamp();
+        watch.last = watch.rom.load().ok().map(|rom| rom.data);
+        watch
+    }
+
+    // The file holding the ROM, which is inside a watched directory
+    fn file(&self) -> PathBuf {
+        match &self.rom.name {
+            Some(name) if self.rom.path.is_dir() => self.rom.path.join(name),
+            _ => self.rom.path.clone(),
+        }
+    }
+
+    fn stamp(&self) -> Option<(SystemTime, u64)> {
+        let metadata = fs::metadata(self.file()).ok()?;
+        Some((metadata.modified().ok()?, metadata.len()))
+    }
+
+    // Count the 60 Hz periods gone by, and every so often check the file.
+    // Returns the new ROM if it changed, or why it couldn't be built. Files
+    // caught halfway through being saved fail to build, and are picked up
+    // again once the save finishes.
+    pub fn poll(&mut self, periods: u64) -> Option<Result<Vec<u8>, RomError>> {
+        self.periods += periods;
+        if self.periods < CHECK_PERIODS {
+            return None;
+        }
+        self.periods = 0;
+        self.check()
+    }
+
+    // Look at the file straight away
+    pub fn check(&mut self) -> Option<Result<Vec<u8>, RomError>> {
+        let stamp = self.stamp();
+        if stamp == self.stamp {
+            return None;
+        }
+        self.stamp = stamp;
+        match self.rom.load() {
+            Ok(rom) if self.last.as_ref() == Some(&rom.data) => None,
+            Ok(rom) => {
+                self.last = Some(rom.data.clone());
+                Some(Ok(rom.data))
+            }
+            Err(err) => Some(Err(err)),
+        }
+    }
+
+    pub fn path(&self) -> PathBuf {
+        self.file()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::path::Path;
+    use std::process;
+
+    #[test]
+    fn rebuilds_changed_sources() {
+        let dir = env::temp_dir().join(format!("rusty-chip-8-watch-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("game.8o");
+        fs::write(&path, ": main\n  v0 := 1\n").unwrap();
+
+        let mut watch = Watch::new(RomChoice::file(&path), ReloadMode::Patch);
+        assert!(watch.check().is_none());
+
+        // Saved without changing the program
+        fs::write(&path, ": main\n  v0 := 1 # still one\n").unwrap();
+        assert!(watch.check().is_none());
+
+        fs::write(&path, ": main\n  v0 := 2\n").unwrap();
+        assert!(watch.poll(CHECK_PERIODS - 1).is_none());
+        assert_eq!(watch.poll(1).unwrap().unwrap(), vec![0x60, 0x02]);
+
+        fs::write(&path, ": main\n  v0 := 300\n").unwrap();
+        assert!(watch.check().unwrap().is_err());
+        assert_eq!(watch.path(), Path::new(&path));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reload_modes_have_names() {
+        for mode in [ReloadMode::Reset, ReloadMode::Patch] {
+            assert_eq!(mode.to_string().parse(), Ok(mode));
+        }
+        assert!("restart".parse::<ReloadMode>().is_err());
+    }
+}
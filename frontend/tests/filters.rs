@@ -0,0 +1,159 @@
+// Golden image tests for the CRT filters. Each test renders a small test
+// pattern and compares the result against a PPM image in tests/golden. Run
+// with UPDATE_GOLDEN=1 to rewrite the images after an intended change.
+
+use frontend::*;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+// 8x4 pattern: a checkerboard on the left half, a solid block on the right
+fn test_pattern() -> Framebuffer {
+    let (width, height) = (8, 4);
+    let display: Vec<bool> = (0..width * height)
+        .map(|i| {
+            let (x, y) = (i % width, i / width);
+            if x < 4 {
+                (x + y) % 2 == 0
+            } else {
+                (1..=2).contains(&y) && (5..=6).contains(&x)
+            }
+        })
+        .collect();
+    let palette: Palette = "green".parse().unwrap();
+    palette.render(&display, width, height)
+}
+
+fn encode_ppm(frame: &Framebuffer) -> Vec<u8> {
+    let mut data = format!("P6\n{} {}\n255\n", frame.width, frame.height).into_bytes();
+    data.extend(frame.pixels.iter().flat_map(|p| [p.r, p.g, p.b]));
+    data
+}
+
+fn assert_golden(name: &str, frame: &Framebuffer) {
+    let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "golden", name]
+        .iter()
+        .collect();
+    let actual = encode_ppm(frame);
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, &actual).unwrap();
+        return;
+    }
+
+    let expected = fs::read(&path)
+        .unwrap_or_else(|err| panic!("missing golden image {}: {err}", path.display()));
+    if actual != expected {
+        let failed = path.with_extension("actual.ppm");
+        fs::write(&failed, &actual).unwrap();
+        panic!(
+            "{name} differs from the golden image, result written to {}",
+            failed.display()
+        );
+    }
+}
+
+fn filters() -> Filters {
+    Filters {
+        scale: 4,
+        ..Filters::default()
+    }
+}
+
+#[test]
+fn upscale() {
+    let out = filters().apply(&test_pattern());
+    assert_eq!((out.width, out.height), (32, 16));
+    assert_golden("upscale.ppm", &out);
+}
+
+#[test]
+fn grid() {
+    let filters = Filters {
+        grid: Some(0.5),
+        ..filters()
+    };
+    assert_golden("grid.ppm", &filters.apply(&test_pattern()));
+}
+
+#[test]
+fn scanlines() {
+    let filters = Filters {
+        scanlines: Some(0.6),
+        ..filters()
+    };
+    assert_golden("scanlines.ppm", &filters.apply(&test_pattern()));
+}
+
+#[test]
+fn bloom() {
+    let filters = Filters {
+        bloom: Some(Bloom {
+            strength: 0.8,
+            radius: 2,
+        }),
+        ..filters()
+    };
+    assert_golden("bloom.ppm", &filters.apply(&test_pattern()));
+}
+
+#[test]
+fn curvature() {
+    let filters = Filters {
+        curvature: Some(0.25),
+        ..filters()
+    };
+    assert_golden("curvature.ppm", &filters.apply(&test_pattern()));
+}
+
+#[test]
+fn all_effects() {
+    let filters = Filters {
+        scale: 4,
+        grid: Some(0.3),
+        scanlines: Some(0.4),
+        bloom: Some(Bloom {
+            strength: 0.5,
+            radius: 1,
+        }),
+        curvature: Some(0.15),
+    };
+    assert_golden("all_effects.ppm", &filters.apply(&test_pattern()));
+}
+
+#[test]
+fn inactive_filters_leave_the_image_alone() {
+    let frame = test_pattern();
+    assert!(!Filters::default().is_active());
+    assert_eq!(Filters::default().apply(&frame), frame);
+}
+
+#[test]
+fn the_hotkey_steps_through_each_effect() {
+    let all = Filters {
+        scale: 4,
+        scanlines: Some(0.5),
+        curvature: Some(0.1),
+        ..Filters::default()
+    };
+    let scanlines = Filters {
+        scanlines: Some(0.5),
+        ..filters()
+    };
+    let curvature = Filters {
+        curvature: Some(0.1),
+        ..filters()
+    };
+    assert_eq!(
+        all.choices(),
+        [
+            all.clone(),
+            scanlines.clone(),
+            curvature,
+            Filters::default()
+        ]
+    );
+
+    // One effect on its own is the same as all of them
+    assert_eq!(scanlines.choices(), [scanlines, Filters::default()]);
+}
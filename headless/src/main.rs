@@ -0,0 +1,224 @@
+use chip8::{Profile, RomError};
+use frontend::{
+    default_quirks, list_roms, load_rom, load_rom_from, select_rom, Audio, Framebuffer, Input,
+    InputEvent, LoadedRom, Machine, Movie, Palette, Runner, Screenshots, Video,
+};
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+const USAGE: &str = "Usage: cargo run -- [--movie file] [--frames n] [--palette name] \
+                     [--scale n] [--select rom] [--gif out.gif] [--png out.png] \
+                     [--profile out.txt|out.json|out.folded] path/to/game";
+
+// Frames to keep running after the movie's last key change
+const TAIL_FRAMES: u64 = 60;
+
+// Rendering happens in the runner, there is nothing to show or play
+struct NullVideo;
+
+impl Video for NullVideo {
+    fn draw(&mut self, _frame: &Framebuffer, _machine: &Machine) {}
+}
+
+struct NullAudio;
+
+impl Audio for NullAudio {
+    fn set_beeping(&mut self, _beeping: bool) {}
+}
+
+// All input comes from the movie
+struct NullInput;
+
+impl Input for NullInput {
+    fn poll(&mut self) -> Vec<InputEvent> {
+        Vec::new()
+    }
+}
+
+struct Options {
+    rom: PathBuf,
+    gif: Option<PathBuf>,
+    // Where to save the last frame
+    png: Option<PathBuf>,
+    // Where to write a profile of the run, in the format its extension names
+    profile: Option<PathBuf>,
+    movie: Option<PathBuf>,
+    frames: Option<u64>,
+    palette: Option<Palette>,
+    scale: usize,
+    // Which ROM to run from a zip archive or directory
+    select: Option<String>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut rom = None;
+    let mut gif = None;
+    let mut png = None;
+    let mut profile = None;
+    let mut movie = None;
+    let mut frames = None;
+    let mut palette = None;
+    let mut scale = 1;
+    let mut select = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{arg} needs a value"));
+        match arg.as_str() {
+            "--gif" => gif = Some(PathBuf::from(value()?)),
+            "--png" => png = Some(PathBuf::from(value()?)),
+            "--profile" => profile = Some(PathBuf::from(value()?)),
+            "--movie" => movie = Some(PathBuf::from(value()?)),
+            "--frames" => {
+                let value = value()?;
+                frames = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("invalid frame count '{value}'"))?,
+                );
+            }
+            "--select" => select = Some(value()?.clone()),
+            "--palette" => palette = Some(value()?.parse()?),
+            "--scale" => {
+                let value = value()?;
+                scale = value
+                    .parse()
+                    .ok()
+                    .filter(|&scale| scale > 0)
+                    .ok_or_else(|| format!("invalid scale '{value}'"))?;
+            }
+            flag if flag.starts_with("--") => return Err(format!("unknown option {flag}")),
+            path if rom.is_none() => rom = Some(PathBuf::from(path)),
+            _ => return Err("only one ROM can be given".to_string()),
+        }
+    }
+
+    if gif.is_none() && png.is_none() && profile.is_none() {
+        return Err("no output file given with --gif, --png or --profile".to_string());
+    }
+    Ok(Options {
+        rom: rom.ok_or("no ROM given")?,
+        gif,
+        png,
+        profile,
+        movie,
+        frames,
+        palette,
+        scale,
+        select,
+    })
+}
+
+fn read_movie(path: &Path) -> Result<Movie, String> {
+    let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    text.parse()
+}
+
+// Load the ROM given, or one from an archive or directory of them. There is
+// no one to ask, so a choice has to be made with --select when there are
+// several ROMs.
+fn open_rom(path: &Path, selection: Option<&str>) -> Result<LoadedRom, RomError> {
+    let Some(names) = list_roms(path)? else {
+        return load_rom(path);
+    };
+    let name = match (selection, names.as_slice()) {
+        (_, []) => return Err(RomError::Invalid("no ROMs found".to_string())),
+        (Some(selection), _) => select_rom(&names, selection)?,
+        (None, [name]) => name,
+        (None, _) => {
+            return Err(RomError::Invalid(format!(
+                "choose one of the ROMs with --select: {}",
+                names.join(", ")
+            )))
+        }
+    };
+    load_rom_from(path, name)
+}
+
+// A profile as text, JSON or folded stacks for flamegraphs, going by the
+// file's extension
+fn profile_report(profile: &Profile, path: &Path) -> String {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => profile.to_json(),
+        Some("folded") => profile.to_folded(),
+        _ => profile.to_text(),
+    }
+}
+
+// Replay a movie and render it to a GIF or PNG or profile it, without any
+// window
+fn main() {
+    let args: Vec<_> = env::args().skip(1).collect();
+    let options = parse_args(&args).unwrap_or_else(|err| {
+        eprintln!("{err}\n{USAGE}");
+        process::exit(2);
+    });
+
+    let rom = open_rom(&options.rom, options.select.as_deref()).unwrap_or_else(|err| {
+        eprintln!("{}: {err}", options.rom.display());
+        process::exit(1);
+    });
+
+    let movie = match &options.movie {
+        Some(path) => read_movie(path).unwrap_or_else(|err| {
+            eprintln!("Invalid movie {}: {err}", path.display());
+            process::exit(1);
+        }),
+        None => Movie {
+            quirks: default_quirks(&rom.name).unwrap_or_default(),
+            ..Movie::default()
+        },
+    };
+    let frames = options
+        .frames
+        .unwrap_or(movie.inputs.last_frame() + TAIL_FRAMES);
+
+    // Loaded ROMs are always valid
+    let machine = Machine::new(rom.data).unwrap();
+
+    let mut runner = Runner::new(machine, NullVideo, NullAudio, NullInput);
+    runner.play_movie(movie);
+    if let Some(palette) = options.palette {
+        runner.set_palette(palette);
+    }
+    runner.set_screenshots(Screenshots {
+        scale: options.scale,
+        ..Screenshots::default()
+    });
+
+    if let Some(gif) = &options.gif {
+        if let Err(err) = runner.start_recording(gif) {
+            eprintln!("Unable to create {}: {err}", gif.display());
+            process::exit(1);
+        }
+    }
+    if options.profile.is_some() {
+        runner.machine_mut().cpu_mut().start_profiling();
+    }
+    for _ in 0..frames {
+        runner.step();
+    }
+    if let Some(gif) = &options.gif {
+        if let Err(err) = runner.stop_recording() {
+            eprintln!("Unable to write {}: {err}", gif.display());
+            process::exit(1);
+        }
+    }
+    if let Some(png) = &options.png {
+        if let Err(err) = runner.screenshot_as(png) {
+            eprintln!("Unable to write {}: {err}", png.display());
+            process::exit(1);
+        }
+    }
+    if let Some(path) = &options.profile {
+        // Profiling was started above
+        let profile = runner.machine_mut().cpu_mut().stop_profiling().unwrap();
+        if let Err(err) = fs::write(path, profile_report(&profile, path)) {
+            eprintln!("Unable to write {}: {err}", path.display());
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use frontend::{Action, Input, InputEvent};
+
+use std::time::{Duration, Instant};
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+
+// Most terminals never report key releases, only presses and auto-repeats.
+// A key is therefore held for this long after its last press event, which
+// is long enough to bridge the gap between auto-repeats while held.
+const KEY_HOLD: Duration = Duration::from_millis(150);
+
+const NUM_KEYS: usize = 16;
+
+pub struct TuiInput {
+    // When set, the terminal reports real key releases and no emulation is needed
+    reports_release: bool,
+    held_until: [Option<Instant>; NUM_KEYS],
+}
+
+impl TuiInput {
+    pub fn new(reports_release: bool) -> Self {
+        TuiInput {
+            reports_release,
+            held_until: [None; NUM_KEYS],
+        }
+    }
+
+    fn press(&mut self, key: usize, now: Instant) -> InputEvent {
+        self.held_until[key] = if self.reports_release {
+            None
+        } else {
+            Some(now + KEY_HOLD)
+        };
+        InputEvent::Key { key, pressed: true }
+    }
+
+    fn release(&mut self, key: usize) -> InputEvent {
+        self.held_until[key] = None;
+        InputEvent::Key {
+            key,
+            pressed: false,
+        }
+    }
+}
+
+impl Input for TuiInput {
+    fn poll(&mut self) -> Vec<InputEvent> {
+        let now = Instant::now();
+        let mut events = Vec::new();
+
+        // A terminal that stops answering has gone away, so treat errors as quitting
+        loop {
+            let event = match event::poll(Duration::ZERO) {
+                Ok(true) => event::read(),
+                Ok(false) => break,
+                Err(err) => Err(err),
+            };
+            let key = match event {
+                Ok(Event::Key(key)) => key,
+                Ok(_) => continue,
+                Err(_) => {
+                    events.push(InputEvent::Action(Action::Quit));
+                    break;
+                }
+            };
+
+            let btn = match key.code {
+                KeyCode::Char(c) => key2btn(c),
+                _ => None,
+            };
+            if key.kind == KeyEventKind::Release {
+                if let Some(k) = btn {
+                    events.push(self.release(k));
+                }
+            } else if key.code == KeyCode::Char('c')
+                && key.modifiers.contains(KeyModifiers::CONTROL)
+            {
+                events.push(InputEvent::Action(Action::Quit));
+            } else if let Some(k) = btn {
+                events.push(self.press(k, now));
+            } else if let Some(action) = key2action(key.code) {
+                events.push(InputEvent::Action(action));
+            }
+        }
+
+        // Release every emulated key whose hold time has run out
+        for key in 0..NUM_KEYS {
+            if matches!(self.held_until[key], Some(until) if until <= now) {
+                events.push(self.release(key));
+            }
+        }
+        events
+    }
+}
+
+fn key2action(key: KeyCode) -> Option<Action> {
+    match key {
+        KeyCode::Esc => Some(Action::Quit),
+        KeyCode::Char('p') => Some(Action::TogglePause),
+        KeyCode::Backspace => Some(Action::Reset),
+        KeyCode::Char('-') => Some(Action::SpeedDown),
+        KeyCode::Char('=') => Some(Action::SpeedUp),
+        KeyCode::Tab => Some(Action::CyclePalette),
+        KeyCode::Char('.') => Some(Action::FrameAdvance),
+        KeyCode::Char('t') => Some(Action::ToggleTurbo),
+        KeyCode::Char('m') => Some(Action::ToggleSlowMotion),
+        KeyCode::Char('[') => Some(Action::IpfDown),
+        KeyCode::Char(']') => Some(Action::IpfUp),
+        _ => None,
+    }
+}
+
+/*
+    Keyboard                    Chip-8
+    +---+---+---+---+           +---+---+---+---+
+    | 1 | 2 | 3 | 4 |           | 1 | 2 | 3 | C |
+    +---+---+---+---+           +---+---+---+---+
+    | Q | W | E | R |           | 4 | 5 | 6 | D |
+    +---+---+---+---+     =>    +---+---+---+---+
+    | A | S | D | F |           | 7 | 8 | 9 | E |
+    +---+---+---+---+           +---+---+---+---+
+    | Z | X | C | V |           | A | 0 | B | F |
+    +---+---+---+---+           +---+---+---+---+
+*/
+
+fn key2btn(key: char) -> Option<usize> {
+    match key.to_ascii_lowercase() {
+        '1' => Some(0x1),
+        '2' => Some(0x2),
+        '3' => Some(0x3),
+        '4' => Some(0xC),
+        'q' => Some(0x4),
+        'w' => Some(0x5),
+        'e' => Some(0x6),
+        'r' => Some(0xD),
+        'a' => Some(0x7),
+        's' => Some(0x8),
+        'd' => Some(0x9),
+        'f' => Some(0xE),
+        'z' => Some(0xA),
+        'x' => Some(0x0),
+        'c' => Some(0xB),
+        'v' => Some(0xF),
+        _ => None,
+    }
+}
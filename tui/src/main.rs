@@ -0,0 +1,163 @@
+mod input;
+mod panel;
+mod render;
+
+use chip8::*;
+use frontend::{load_rom, Audio, Framebuffer, Machine, Runner, Video};
+
+use std::env;
+use std::io::{self, Stdout, Write};
+use std::path::Path;
+use std::process;
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{
+    KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::style::Print;
+use crossterm::terminal::{
+    self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen, SetTitle,
+};
+use crossterm::{execute, queue};
+
+use input::TuiInput;
+use render::Mode;
+
+// Columns between the screen border and the side panel
+const PANEL_GAP: u16 = 2;
+
+// Puts the terminal into raw mode for the lifetime of the guard, and restores
+// it on drop so a panic doesn't leave the user's shell unusable
+struct TerminalGuard {
+    keyboard_enhanced: bool,
+}
+
+impl TerminalGuard {
+    fn enter(stdout: &mut Stdout) -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(stdout, EnterAlternateScreen, Hide, Clear(ClearType::All))?;
+
+        // Terminals implementing the kitty keyboard protocol can report key releases
+        let keyboard_enhanced = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if keyboard_enhanced {
+            execute!(
+                stdout,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+        Ok(TerminalGuard { keyboard_enhanced })
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        if self.keyboard_enhanced {
+            let _ = execute!(stdout, PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(stdout, Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+// Draws the screen and debugging panel. Drawing errors are ignored, as
+// there is nowhere left to report them once the terminal is unusable.
+struct TuiVideo {
+    stdout: Stdout,
+    mode: Mode,
+}
+
+impl Video for TuiVideo {
+    fn draw(&mut self, frame: &Framebuffer, machine: &Machine) {
+        let _ = draw_screen(frame, machine.cpu(), self.mode, &mut self.stdout);
+    }
+
+    fn set_title(&mut self, title: &str) {
+        let _ = execute!(self.stdout, SetTitle(title));
+    }
+}
+
+// Terminals can only ring the bell, so do that once when a beep starts
+struct TuiAudio;
+
+impl Audio for TuiAudio {
+    fn set_beeping(&mut self, beeping: bool) {
+        if beeping {
+            let mut stdout = io::stdout();
+            let _ = stdout.write_all(b"\x07").and_then(|_| stdout.flush());
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<_> = env::args().skip(1).collect();
+    let (mode, path) = match args.as_slice() {
+        [path] => (Mode::HalfBlock, path),
+        [flag, path] if flag == "--braille" => (Mode::Braille, path),
+        _ => {
+            println!("Usage: cargo run [--braille] path/to/game");
+            return;
+        }
+    };
+
+    let rom = load_rom(Path::new(path)).unwrap_or_else(|err| {
+        eprintln!("{path}: {err}");
+        process::exit(1);
+    });
+    // Loaded ROMs are always valid
+    let mut machine = Machine::new(rom.data).unwrap();
+    // Octo cartridges come with their own settings
+    if let Some(info) = &rom.info {
+        if let Some(quirks) = info.quirks {
+            machine.cpu_mut().set_quirks(quirks);
+        }
+        if let Some(ips) = info.instructions_per_second() {
+            machine.set_instructions_per_second(ips);
+        }
+    }
+
+    let mut stdout = io::stdout();
+    let guard = match TerminalGuard::enter(&mut stdout) {
+        Ok(guard) => guard,
+        Err(err) => {
+            eprintln!("Terminal error: {err}");
+            return;
+        }
+    };
+
+    let mut runner = Runner::new(
+        machine,
+        TuiVideo { stdout, mode },
+        TuiAudio,
+        TuiInput::new(guard.keyboard_enhanced),
+    );
+    runner.run();
+}
+
+fn draw_screen(frame: &Framebuffer, emu: &Cpu, mode: Mode, out: &mut impl Write) -> io::Result<()> {
+    let (width, height) = mode.size(frame);
+    let border = "─".repeat(width);
+
+    // Draw the screen inside a box, starting in the top left corner
+    queue!(out, MoveTo(0, 0), Print(format!("┌{border}┐")))?;
+    for (row, line) in render::render(frame, mode).iter().enumerate() {
+        queue!(out, MoveTo(0, row as u16 + 1), Print(format!("│{line}│")))?;
+    }
+    queue!(
+        out,
+        MoveTo(0, height as u16 + 1),
+        Print(format!("└{border}┘"))
+    )?;
+
+    // Registers and disassembly go to the right of the screen
+    let panel_x = width as u16 + 2 + PANEL_GAP;
+    for (row, line) in panel::panel_lines(emu).iter().enumerate() {
+        queue!(
+            out,
+            MoveTo(panel_x, row as u16),
+            Clear(ClearType::UntilNewLine),
+            Print(line)
+        )?;
+    }
+    out.flush()
+}
@@ -0,0 +1,55 @@
+use chip8::*;
+
+// How many upcoming instructions to disassemble
+const DISASM_LINES: u16 = 12;
+
+pub fn panel_lines(cpu: &Cpu) -> Vec<String> {
+    let mut lines = vec![
+        format!(
+            "PC {:#06X}   I {:#06X}",
+            cpu.get_pc(),
+            cpu.get_index_register()
+        ),
+        format!(
+            "DT {:#04X}     ST {:#04X}",
+            cpu.get_delay_timer(),
+            cpu.get_sound_timer()
+        ),
+        String::new(),
+    ];
+
+    // Four registers per line
+    for (row, regs) in cpu.get_variable_registers().chunks(4).enumerate() {
+        let line: Vec<String> = regs
+            .iter()
+            .enumerate()
+            .map(|(i, v)| format!("V{:X} {v:02X}", row * 4 + i))
+            .collect();
+        lines.push(line.join("  "));
+    }
+
+    let stack: Vec<String> = cpu
+        .get_stack()
+        .iter()
+        .map(|addr| format!("{addr:03X}"))
+        .collect();
+    lines.push(String::new());
+    lines.push(format!("Stack [{}]", stack.join(" ")));
+    lines.push(String::new());
+
+    // Disassemble from the program counter onwards
+    let memory = cpu.get_memory();
+    for i in 0..DISASM_LINES {
+        let addr = cpu.get_pc() as usize + 2 * i as usize;
+        if addr + 1 >= memory.len() {
+            break;
+        }
+        let op = ((memory[addr] as u16) << 8) | memory[addr + 1] as u16;
+        let marker = if i == 0 { '>' } else { ' ' };
+        lines.push(format!(
+            "{marker} {addr:03X}  {op:04X}  {}",
+            disassemble(op)
+        ));
+    }
+    lines
+}
@@ -0,0 +1,102 @@
+use frontend::{Framebuffer, Rgb};
+
+use std::fmt::Write;
+
+use crossterm::style::{Color, ResetColor, SetBackgroundColor, SetForegroundColor};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    // One character cell covers 1x2 pixels
+    HalfBlock,
+    // One character cell covers 2x4 pixels
+    Braille,
+}
+
+impl Mode {
+    pub fn cell_size(self) -> (usize, usize) {
+        match self {
+            Mode::HalfBlock => (1, 2),
+            Mode::Braille => (2, 4),
+        }
+    }
+
+    // Size of the rendered screen in character cells
+    pub fn size(self, frame: &Framebuffer) -> (usize, usize) {
+        let (w, h) = self.cell_size();
+        (frame.width.div_ceil(w), frame.height.div_ceil(h))
+    }
+}
+
+// Each line comes with the escape codes for its colors, and resets them at the end
+pub fn render(frame: &Framebuffer, mode: Mode) -> Vec<String> {
+    let (columns, rows) = mode.size(frame);
+    (0..rows)
+        .map(|row| {
+            let mut line = String::new();
+            let mut colors = None;
+            for column in 0..columns {
+                let (c, fg, bg) = match mode {
+                    Mode::HalfBlock => half_block(frame, column, row),
+                    Mode::Braille => braille(frame, column, row),
+                };
+                // Only emit color changes, which keeps lines short
+                if colors != Some((fg, bg)) {
+                    let _ = write!(
+                        line,
+                        "{}{}",
+                        SetForegroundColor(term_color(fg)),
+                        SetBackgroundColor(term_color(bg))
+                    );
+                    colors = Some((fg, bg));
+                }
+                line.push(c);
+            }
+            let _ = write!(line, "{ResetColor}");
+            line
+        })
+        .collect()
+}
+
+fn term_color(rgb: Rgb) -> Color {
+    Color::Rgb {
+        r: rgb.r,
+        g: rgb.g,
+        b: rgb.b,
+    }
+}
+
+fn pixel(frame: &Framebuffer, x: usize, y: usize) -> Rgb {
+    if x < frame.width && y < frame.height {
+        frame.pixel(x, y)
+    } else {
+        frame.background
+    }
+}
+
+// The upper half of the cell is drawn in the foreground color, the lower in the background
+fn half_block(frame: &Framebuffer, column: usize, row: usize) -> (char, Rgb, Rgb) {
+    let top = pixel(frame, column, row * 2);
+    let bottom = pixel(frame, column, row * 2 + 1);
+    ('▀', top, bottom)
+}
+
+// Bit for each dot of a braille cell, indexed by [y][x]
+const BRAILLE_DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+// Braille cells only have one foreground color, so the dots take the color of
+// the first lit pixel in the cell
+fn braille(frame: &Framebuffer, column: usize, row: usize) -> (char, Rgb, Rgb) {
+    let mut bits = 0;
+    let mut fg = None;
+    for (dy, dots) in BRAILLE_DOTS.iter().enumerate() {
+        for (dx, dot) in dots.iter().enumerate() {
+            let color = pixel(frame, column * 2 + dx, row * 4 + dy);
+            if color != frame.background {
+                bits |= dot;
+                fg.get_or_insert(color);
+            }
+        }
+    }
+    let c = char::from_u32(0x2800 + bits).unwrap();
+    (c, fg.unwrap_or(frame.background), frame.background)
+}